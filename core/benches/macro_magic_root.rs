@@ -0,0 +1,39 @@
+//! Compares the memoized [`macro_magic_root`] with parsing `MACRO_MAGIC_ROOT` on every call,
+//! as it was done before the root was memoized.
+//!
+//! Run with `cargo bench -p macro_magic_core --bench macro_magic_root`.
+
+use std::{hint::black_box, time::Instant};
+
+use macro_magic_core::{macro_magic_root, private_path, MACRO_MAGIC_ROOT};
+use quote::quote;
+
+const ITERATIONS: u32 = 100_000;
+
+fn bench(name: &str, mut f: impl FnMut()) {
+    // warm up, which also initializes the memoized root
+    for _ in 0..ITERATIONS / 10 {
+        f();
+    }
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    println!(
+        "{:<24} {:>8.0?} per call",
+        name,
+        start.elapsed() / ITERATIONS
+    );
+}
+
+fn main() {
+    bench("uncached root", || {
+        black_box(syn::parse_str::<syn::Path>(black_box(MACRO_MAGIC_ROOT)).unwrap());
+    });
+    bench("macro_magic_root", || {
+        black_box(macro_magic_root());
+    });
+    bench("private_path", || {
+        black_box(private_path(&quote!(import_tokens_inner)));
+    });
+}
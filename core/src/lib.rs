@@ -4,6 +4,8 @@
 
 #![no_std]
 extern crate alloc;
+#[cfg(feature = "rustfmt")]
+extern crate std;
 use core::fmt::Display;
 
 use alloc::{
@@ -17,14 +19,28 @@ use libc_print::libc_println as println;
 
 use derive_syn_parse::Parse;
 use macro_magic_core_macros::*;
-use proc_macro2::{Punct, Spacing, Span, TokenStream as TokenStream2};
-use quote::{format_ident, quote, ToTokens};
+use proc_macro2::{Delimiter, Punct, Spacing, Span, TokenStream as TokenStream2, TokenTree};
+use quote::{format_ident, quote, quote_spanned, ToTokens};
 use syn::{
-    parse::Nothing, parse2, parse_quote, spanned::Spanned, token::Comma, Attribute, Error, FnArg,
-    Ident, Item, ItemFn, LitStr, Pat, Path, Result, Token, Visibility,
+    parse::{Nothing, ParseStream},
+    parse2, parse_quote,
+    punctuated::Punctuated,
+    spanned::Spanned,
+    token::Comma,
+    AttrStyle, Attribute, Error, Fields, FnArg, GenericArgument, Generics, Ident, Item, ItemEnum,
+    ItemFn, ItemStruct, Lit, LitStr, Pat, Path, PathArguments, Result, Token, TraitItem, Type,
+    Variant, Visibility,
 };
 
-pub const MACRO_MAGIC_ROOT: &'static str = get_macro_magic_root!();
+pub const MACRO_MAGIC_ROOT: &str = get_macro_magic_root!();
+
+/// The prefix used for the names of the callback macros generated by `#[export_tokens]`.
+///
+/// Defaults to `__export_tokens_tt_`, but can be configured via the `MACRO_MAGIC_EXPORT_PREFIX`
+/// env var in the `[env]` section of `.cargo/config.toml`, just like [`MACRO_MAGIC_ROOT`]. This
+/// allows a library that rebrands macro_magic to keep its `#[macro_export]`-ed callback macros
+/// from colliding with those of other macro_magic-based libraries.
+pub const MACRO_MAGIC_EXPORT_PREFIX: &str = get_macro_magic_export_prefix!();
 
 /// Private module containing custom keywords used for parsing in this crate
 mod keywords {
@@ -33,6 +49,36 @@ mod keywords {
     custom_keyword!(proc_macro_attribute);
     custom_keyword!(proc_macro);
     custom_keyword!(proc_macro_derive);
+    custom_keyword!(variants);
+    custom_keyword!(make_pub);
+    custom_keyword!(hash);
+    custom_keyword!(body);
+    custom_keyword!(sig);
+    custom_keyword!(items);
+    custom_keyword!(fields);
+    custom_keyword!(emit);
+    custom_keyword!(no_emit);
+    custom_keyword!(dump);
+    custom_keyword!(version);
+    custom_keyword!(min_version);
+    custom_keyword!(with_generics);
+    custom_keyword!(strict);
+    custom_keyword!(warn_unused);
+    custom_keyword!(value);
+    custom_keyword!(prefix);
+    custom_keyword!(dry_run);
+    custom_keyword!(then);
+    custom_keyword!(local);
+    custom_keyword!(strip);
+    custom_keyword!(cfg);
+    custom_keyword!(cfg_attr);
+    custom_keyword!(keep);
+    custom_keyword!(apply);
+    custom_keyword!(verbatim_source);
+    custom_keyword!(const_fn);
+    custom_keyword!(export_const);
+    custom_keyword!(extend);
+    custom_keyword!(name);
 }
 
 /// Used to parse args that were passed to [`forward_tokens_internal`].
@@ -42,29 +88,65 @@ mod keywords {
 pub struct ForwardTokensArgs {
     /// The path of the item whose tokens are being forwarded
     pub source: Path,
-    _comma1: Comma,
+    _arrow1: Option<Token![=>]>,
+    /// The path of an optional attribute macro that transforms the item before it is
+    /// delivered, specified using the `source => transform => target` syntax
+    #[parse_if(_arrow1.is_some())]
+    pub transform: Option<Path>,
+    #[parse_if(_arrow1.is_some())]
+    _arrow2: Option<Token![=>]>,
+    #[parse_if(_arrow1.is_none())]
+    _comma1: Option<Comma>,
     /// The path of the macro that will receive the forwarded tokens
     pub target: Path,
     _comma2: Option<Comma>,
     #[parse_if(_comma2.is_some())]
     pub mm_path: Option<Path>,
-    _comma3: Option<Comma>,
-    #[parse_if(_comma3.is_some())]
-    /// Optional extra data that can be passed as a [`struct@LitStr`]. This is how
-    /// [`import_tokens_attr_internal`] passes the item the attribute macro is attached to, but
-    /// this can be repurposed for other things potentially as [`str`] could encode anything.
-    pub extra: Option<LitStr>,
+    /// Optional extra literals that are delivered to the target after the item, in order.
+    /// This is how [`import_tokens_attr_internal`] passes the item the attribute macro is
+    /// attached to (as a single [`struct@LitStr`]), but this can be repurposed for other things.
+    #[call(parse_forward_extras)]
+    pub extras: Vec<Lit>,
 }
 
-/// Used to parse args that were passed to [`forward_tokens_inner_internal`].
+/// Parses the optional trailing `, lit, lit, ...` list of [`ForwardTokensArgs`].
+fn parse_forward_extras(input: ParseStream) -> Result<Vec<Lit>> {
+    if input.is_empty() {
+        return Ok(Vec::new());
+    }
+    input.parse::<Comma>()?;
+    Ok(Punctuated::<Lit, Comma>::parse_terminated(input)?
+        .into_iter()
+        .collect())
+}
+
+/// Used to parse the args for the [`forward_tokens_dynamic_internal`] function.
 ///
 /// You shouldn't need to use this directly.
 #[derive(Parse)]
+pub struct ForwardTokensDynamicArgs {
+    /// The path of the item whose tokens are being forwarded
+    pub source: Path,
+    _comma1: Comma,
+    /// The path of the `macro_rules!` table that maps keys to target macros
+    pub table: Path,
+    _comma2: Comma,
+    /// The key to look up in the table
+    pub key: Ident,
+    _trailing_comma: Option<Comma>,
+}
+
+/// Used to parse args that were passed to [`forward_tokens_inner_internal`].
+///
+/// Custom forwarding flows can build these via [`ForwardedTokens::new`] and serialize them
+/// via [`ToTokens`].
+#[derive(Parse)]
 pub struct ForwardedTokens {
     /// The path of the macro that will receive the forwarded tokens
     pub target_path: Path,
     _comma1: Comma,
     /// The item whose tokens are being forwarded
+    #[call(parse_exported_item)]
     pub item: Item,
     _comma2: Option<Comma>,
     #[parse_if(_comma2.is_some())]
@@ -74,1020 +156,8356 @@ pub struct ForwardedTokens {
     pub extra: Option<LitStr>,
 }
 
-/// Used to parse args passed to the inner pro macro auto-generated by
-/// [`import_tokens_attr_internal`].
-///
-/// You shouldn't need to use this directly.
-#[derive(Parse)]
-pub struct AttrItemWithExtra {
-    pub imported_item: Item,
-    _comma: Comma,
-    pub extra: LitStr,
+impl ForwardedTokens {
+    /// Creates a new [`ForwardedTokens`] that forwards the tokens of `item` to the macro at
+    /// `target_path`, along with the optional `extra` data.
+    ///
+    /// Serializing the result via [`ToTokens`] produces `target_path, item` (followed by
+    /// `, extra` if it is present), which parses back into an identical [`ForwardedTokens`].
+    pub fn new(target_path: Path, item: Item, extra: Option<LitStr>) -> Self {
+        ForwardedTokens {
+            target_path,
+            _comma1: Default::default(),
+            item,
+            _comma2: extra.as_ref().map(|_| Default::default()),
+            extra,
+        }
+    }
 }
 
-/// Used to parse the args for the [`import_tokens_internal`] function.
-///
-/// You shouldn't need to use this directly.
-#[derive(Parse)]
-pub struct ImportTokensArgs {
-    _let: Token![let],
-    pub tokens_var_ident: Ident,
-    _eq: Token![=],
-    pub source_path: Path,
+impl ToTokens for ForwardedTokens {
+    fn to_tokens(&self, tokens: &mut TokenStream2) {
+        self.target_path.to_tokens(tokens);
+        self._comma1.to_tokens(tokens);
+        self.item.to_tokens(tokens);
+        if let Some(extra) = &self.extra {
+            self._comma2.unwrap_or_default().to_tokens(tokens);
+            extra.to_tokens(tokens);
+        }
+    }
 }
 
-/// Used to parse the args for the [`import_tokens_inner_internal`] function.
+/// Used to parse args that were passed to [`forward_tokens_spanned_inner_internal`].
 ///
 /// You shouldn't need to use this directly.
 #[derive(Parse)]
-pub struct ImportedTokens {
-    pub tokens_var_ident: Ident,
+pub struct ForwardedTokensSpanned {
+    /// The path of the macro that will receive the forwarded tokens
+    pub target_path: Path,
     _comma: Comma,
-    pub item: Item,
+    /// The raw tokens of the item whose tokens are being forwarded
+    #[call(parse_exported_tokens)]
+    pub tokens: TokenStream2,
 }
 
+/// Used to parse args passed to the inner pro macro auto-generated by
+/// [`import_tokens_attr_internal`].
+///
+/// Custom forwarding flows can build these via [`AttrItemWithExtra::new`] and serialize them
+/// via [`ToTokens`].
 #[derive(Parse)]
-pub struct BasicUseStmt {
-    #[call(Attribute::parse_outer)]
-    pub attrs: Vec<Attribute>,
-    pub vis: Visibility,
-    _use: Token![use],
-    pub path: Path,
-    _semi: Token![;],
+pub struct AttrItemWithExtra {
+    /// The item whose tokens were imported
+    pub imported_item: Item,
+    _comma: Comma,
+    /// A packed [`ImportTokensAttrContext`] (see [`ImportTokensAttrContext::to_extra`])
+    pub extra: LitStr,
 }
 
-/// Delineates the different types of proc macro
-#[derive(Copy, Clone, Eq, PartialEq, Debug)]
-pub enum ProcMacroType {
-    /// Corresponds with `#[proc_macro]`
-    Normal,
-    /// Corresponds with `#[proc_macro_attribute]`
-    Attribute,
-    /// Corresponds with `#[proc_macro_derive]`
-    Derive,
+impl AttrItemWithExtra {
+    /// Creates a new [`AttrItemWithExtra`] from the imported item and the packed `extra` data.
+    ///
+    /// Serializing the result via [`ToTokens`] produces `imported_item, extra`, which parses
+    /// back into an identical [`AttrItemWithExtra`].
+    pub fn new(imported_item: Item, extra: LitStr) -> Self {
+        AttrItemWithExtra {
+            imported_item,
+            _comma: Default::default(),
+            extra,
+        }
+    }
 }
 
-impl ProcMacroType {
-    /// Gets the `&'static str` representation of this proc macro type
-    pub fn to_str(&self) -> &'static str {
-        match self {
-            ProcMacroType::Normal => "#[proc_macro]",
-            ProcMacroType::Attribute => "#[proc_macro_attribute]",
-            ProcMacroType::Derive => "#[proc_macro_derive]",
-        }
+impl ToTokens for AttrItemWithExtra {
+    fn to_tokens(&self, tokens: &mut TokenStream2) {
+        self.imported_item.to_tokens(tokens);
+        self._comma.to_tokens(tokens);
+        self.extra.to_tokens(tokens);
     }
+}
 
-    /// Gets the [`Attribute`] representation of this proc macro type
-    pub fn to_attr(&self) -> Attribute {
-        match self {
-            ProcMacroType::Normal => parse_quote!(#[proc_macro]),
-            ProcMacroType::Attribute => parse_quote!(#[proc_macro_attribute]),
-            ProcMacroType::Derive => parse_quote!(#[proc_macro_derive]),
+/// Used to parse the args passed to `#[export_tokens]` by [`export_tokens_internal`].
+///
+/// The args are a comma-separated list containing at most one name override
+/// [`struct@Ident`] along with any of the following options. A bare ident matching one of
+/// the options (see [`EXPORT_TOKENS_OPTIONS`]) always means that option; to export under such
+/// a name, spell the override out as `name = some_ident` instead.
+/// - `name = some_ident`: the explicit form of the name override, which also accepts the
+///   option keywords.
+/// - `variants`: (enums only) additionally export each variant of the enum individually.
+/// - `make_pub`: emit the item with `pub` visibility (the exported tokens are unaffected).
+/// - `hash`: additionally export the item under its name suffixed with a short hash of its
+///   tokens (see [`export_tokens_hash_suffix`]).
+/// - `body`: (functions only) additionally export the [`syn::Block`] body of the function on
+///   its own, under the name `<fn>_body`.
+/// - `sig`: (functions only) additionally export the [`syn::Signature`] of the function on its
+///   own, under the name `<fn>_sig`.
+/// - `items`: (traits only) additionally export each associated fn, type and const of the
+///   trait individually, under the name `<trait>_<item>`.
+/// - `fields`: (unions and structs with named fields only) additionally export each field
+///   individually, under the name `<item>_<field>`.
+/// - `emit` / `no_emit`: override whether the item itself is emitted, regardless of the
+///   default of the macro being used (such as an alias created via `export_tokens_alias!`).
+/// - `dump = "some/dir"`: write the tokens of the item to a JSON file in the specified
+///   directory. This is handled by `#[export_tokens]` itself (when the `dump` feature is
+///   enabled) rather than by [`export_tokens_internal`], which ignores it.
+/// - `version = 2`: declare the "schema version" of the exported tokens, which can be checked
+///   by importers via `import_tokens!(let tokens = path, min_version = 2)`. Defaults to `0`.
+/// - `cfg_attr = keep | apply | strip`: how the `#[cfg_attr(..)]`s within the exported tokens
+///   are resolved (see [`CfgAttrResolution`]). Defaults to `keep`.
+/// - `export_const`: additionally emit a `const` containing the stringified tokens of the item
+///   (see [`export_tokens_const_ident`]).
+///
+/// You shouldn't need to use this directly.
+#[derive(Clone, Default)]
+pub struct ExportTokensArgs {
+    /// The explicit name to export the item under, if any.
+    pub name: Option<Ident>,
+    /// Whether the `variants` option was specified.
+    pub variants: bool,
+    /// Whether the `make_pub` option was specified.
+    pub make_pub: bool,
+    /// Whether the `hash` option was specified.
+    pub hash: bool,
+    /// Whether the `body` option was specified.
+    pub body: bool,
+    /// Whether the `sig` option was specified.
+    pub sig: bool,
+    /// Whether the `items` option was specified.
+    pub items: bool,
+    /// Whether the `fields` option was specified.
+    pub fields: bool,
+    /// `Some(true)` if `emit` was specified, `Some(false)` if `no_emit` was specified.
+    pub emit: Option<bool>,
+    /// The directory specified via the `dump` option, if any.
+    pub dump: Option<LitStr>,
+    /// The version specified via the `version` option, if any.
+    pub version: Option<u64>,
+    /// Whether the `with_generics` option was specified.
+    pub with_generics: bool,
+    /// Whether the `strict` option was specified.
+    pub strict: bool,
+    /// Whether the `warn_unused` option was specified.
+    pub warn_unused: bool,
+    /// Whether the `value` option was specified.
+    pub value: bool,
+    /// Whether the `local` option was specified.
+    pub local: bool,
+    /// The predicate specified via the `cfg(..)` option, if any.
+    pub cfg: Option<TokenStream2>,
+    /// Whether the `verbatim_source` option was specified.
+    pub verbatim_source: bool,
+    /// Whether the `const_fn` option was specified.
+    pub const_fn: bool,
+    /// Whether the `export_const` option was specified.
+    pub export_const: bool,
+    /// The resolution specified via the `cfg_attr` option, if any.
+    pub cfg_attr: Option<CfgAttrResolution>,
+}
+
+impl ExportTokensArgs {
+    /// Converts the parsed args into an [`ExportTokensOptions`], using the specified `emit`
+    /// setting unless it was overridden by the `emit` or `no_emit` option.
+    pub fn into_options(self, emit: bool) -> ExportTokensOptions {
+        ExportTokensOptions {
+            name: self.name,
+            emit: self.emit.unwrap_or(emit),
+            variants: self.variants,
+            make_pub: self.make_pub,
+            hash: self.hash,
+            body: self.body,
+            sig: self.sig,
+            items: self.items,
+            fields: self.fields,
+            version: self.version.unwrap_or_default(),
+            with_generics: self.with_generics,
+            strict: self.strict,
+            allow_unused: !self.warn_unused,
+            value: self.value,
+            prefix: None,
+            local: self.local,
+            cfg: self.cfg,
+            verbatim_source: self.verbatim_source,
+            source_text: None,
+            const_fn: self.const_fn,
+            export_const: self.export_const,
+            cfg_attr: self.cfg_attr.unwrap_or_default(),
         }
     }
 }
 
-/// Should be implemented by structs that will be passed to `#[with_custom_parsing(..)]`. Such
-/// structs should also implement [`syn::parse::Parse`].
+/// Controls the behavior of [`export_tokens_with_options`].
 ///
-/// ## Example
+/// Options are set using builder-style methods, starting from [`ExportTokensOptions::new`]:
 ///
 /// ```ignore
-/// #[derive(derive_syn_parse::Parse)]
-/// struct CustomParsingA {
-///     foreign_path: syn::Path,
-///     _comma: syn::token::Comma,
-///     custom_path: syn::Path,
-/// }
-///
-/// impl ToTokens for CustomParsingA {
-///     fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
-///         tokens.extend(self.foreign_path.to_token_stream());
-///         tokens.extend(self._comma.to_token_stream());
-///         tokens.extend(self.custom_path.to_token_stream());
-///     }
-/// }
-///
-/// impl ForeignPath for CustomParsingA {
-///     fn foreign_path(&self) -> &syn::Path {
-///         &self.foreign_path
-///     }
-/// }
+/// let options = ExportTokensOptions::new().name(parse_quote!(my_name)).make_pub(true);
+/// let output = export_tokens_with_options(item_tokens, options)?;
 /// ```
-pub trait ForeignPath {
-    fn foreign_path(&self) -> &syn::Path;
+///
+/// The struct is `#[non_exhaustive]`, so that new options can be added without breaking
+/// callers: outside this crate it can only be created via [`ExportTokensOptions::new`] (or
+/// [`Default`]) and [`ExportTokensArgs::into_options`].
+#[derive(Clone)]
+#[non_exhaustive]
+pub struct ExportTokensOptions {
+    /// The explicit name to export the item under, if any.
+    pub name: Option<Ident>,
+    /// Whether the item itself should be emitted (defaults to `true`).
+    pub emit: bool,
+    /// (enums only) Whether each variant of the enum should also be exported individually.
+    pub variants: bool,
+    /// Whether the item should be emitted with `pub` visibility.
+    pub make_pub: bool,
+    /// Whether the item should also be exported under a hashed name (see
+    /// [`export_tokens_hash_suffix`]).
+    pub hash: bool,
+    /// (functions only) Whether the body of the function should also be exported on its own.
+    pub body: bool,
+    /// (functions only) Whether the signature of the function should also be exported on its
+    /// own.
+    pub sig: bool,
+    /// (traits only) Whether each associated item of the trait should also be exported
+    /// individually.
+    pub items: bool,
+    /// (unions and structs with named fields only) Whether each field should also be exported
+    /// individually.
+    pub fields: bool,
+    /// The "schema version" of the exported tokens (defaults to `0`).
+    pub version: u64,
+    /// Whether the generics of the item should also be stored on their own, so they can be
+    /// imported via `import_tokens_generics!`.
+    pub with_generics: bool,
+    /// Whether to reject items containing inner attributes (`#![...]`), which are only valid
+    /// when the tokens are expanded in the same kind of context they were exported from.
+    pub strict: bool,
+    /// Whether the emitted item should be marked `#[allow(unused)]` (defaults to `true`).
+    pub allow_unused: bool,
+    /// (consts only) Whether the value expression of the const should also be stored on its
+    /// own, so it can be imported directly into expression position via
+    /// `import_tokens_value!`.
+    pub value: bool,
+    /// The prefix of the generated callback macros, if it isn't [`MACRO_MAGIC_EXPORT_PREFIX`].
+    /// Items exported with a custom prefix must be imported with the same prefix.
+    pub prefix: Option<String>,
+    /// Whether the generated callback macros should be left out of `#[macro_export]`, so they
+    /// are only usable (textually) after the item within the defining crate.
+    pub local: bool,
+    /// A `cfg` predicate (e.g. `feature = "extra"`) the generated callback macros are gated
+    /// behind, if any. The item itself is emitted regardless.
+    pub cfg: Option<TokenStream2>,
+    /// Whether the source text of the item should also be stored, so it can be imported via
+    /// `import_tokens_str!(path, verbatim_source)`.
+    pub verbatim_source: bool,
+    /// The verbatim source text of the item to store when `verbatim_source` is set. If this
+    /// isn't provided, [`verbatim_source_text`] is used, falling back to the stringified tokens.
+    pub source_text: Option<String>,
+    /// Whether a `const fn` returning the stringified tokens of the item should be emitted
+    /// along with the item. Requires `emit`.
+    pub const_fn: bool,
+    /// Whether a `const` containing the stringified tokens of the item (see
+    /// [`export_tokens_const_ident`]) should be emitted along with the item. Requires `emit`.
+    pub export_const: bool,
+    /// How the `#[cfg_attr(..)]`s within the exported tokens are resolved (defaults to
+    /// [`CfgAttrResolution::Keep`]). The emitted item is unaffected.
+    pub cfg_attr: CfgAttrResolution,
 }
 
-#[derive(Clone)]
-pub struct ProcMacro {
-    /// The underlying proc macro function definition
-    pub proc_fn: ItemFn,
-    /// Specified the type of this proc macro, i.e. attribute vs normal vs derive
-    pub macro_type: ProcMacroType,
-    /// Specifies the [`struct@Ident`] for the `tokens` parameter of this proc macro function
-    /// definition. For normal and derive macros this is the only parameter, and for attribute
-    /// macros this is the second parameter.
-    pub tokens_ident: Ident,
-    /// Specifies the [`struct@Ident`] for the `attr` parameter of this proc macro function
-    /// definition, if it is an attribute macro. Otherwise this will be set to [`None`].
-    pub attr_ident: Option<Ident>,
+impl Default for ExportTokensOptions {
+    fn default() -> Self {
+        ExportTokensOptions {
+            name: None,
+            emit: true,
+            variants: false,
+            make_pub: false,
+            hash: false,
+            body: false,
+            sig: false,
+            items: false,
+            fields: false,
+            version: 0,
+            with_generics: false,
+            strict: false,
+            allow_unused: true,
+            value: false,
+            prefix: None,
+            local: false,
+            cfg: None,
+            verbatim_source: false,
+            source_text: None,
+            const_fn: false,
+            export_const: false,
+            cfg_attr: CfgAttrResolution::Keep,
+        }
+    }
 }
 
-impl ProcMacro {
-    /// Constructs a [`ProcMacro`] from anything compatible with [`TokenStream2`].
-    pub fn from<T: Into<TokenStream2>>(tokens: T) -> Result<Self> {
-        let proc_fn = parse2::<ItemFn>(tokens.into())?;
-        let Visibility::Public(_) = proc_fn.vis else { return Err(Error::new(proc_fn.vis.span(), "Visibility must be public")) };
-        let mut macro_type: Option<ProcMacroType> = None;
-        if proc_fn
-            .attrs
-            .iter()
-            .find(|attr| {
-                if syn::parse2::<keywords::proc_macro>(attr.path().to_token_stream()).is_ok() {
-                    macro_type = Some(ProcMacroType::Normal);
-                } else if syn::parse2::<keywords::proc_macro_attribute>(
-                    attr.path().to_token_stream(),
-                )
-                .is_ok()
-                {
-                    macro_type = Some(ProcMacroType::Attribute);
-                } else if syn::parse2::<keywords::proc_macro>(attr.path().to_token_stream()).is_ok()
-                {
-                    macro_type = Some(ProcMacroType::Derive);
-                }
-                macro_type.is_some()
-            })
-            .is_none()
-        {
-            return Err(Error::new(
-                proc_fn.sig.ident.span(),
-                "can only be attached to a proc macro function definition",
-            ));
-        };
-        let macro_type = macro_type.unwrap();
+impl ExportTokensOptions {
+    /// Creates a new [`ExportTokensOptions`] with the same behavior as a bare
+    /// `#[export_tokens]`.
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-        // tokens_ident
-        let Some(FnArg::Typed(tokens_arg)) = proc_fn.sig.inputs.last() else {
-            unreachable!("missing tokens arg");
-        };
-        let Pat::Ident(tokens_ident) = *tokens_arg.pat.clone() else {
-            unreachable!("invalid tokens arg");
-        };
-        let tokens_ident = tokens_ident.ident;
+    /// Sets the explicit name to export the item under.
+    pub fn name(mut self, name: Ident) -> Self {
+        self.name = Some(name);
+        self
+    }
 
-        // attr_ident (if applicable)
-        let attr_ident = match macro_type {
-            ProcMacroType::Attribute => {
-                let Some(FnArg::Typed(attr_arg)) = proc_fn.sig.inputs.first() else {
-                    unreachable!("missing attr arg");
-                };
-                let Pat::Ident(attr_ident) = *attr_arg.pat.clone() else {
-                    unreachable!("invalid attr arg");
-                };
-                Some(attr_ident.ident)
-            }
-            _ => None,
-        };
-        Ok(ProcMacro {
-            proc_fn,
-            macro_type,
-            tokens_ident,
-            attr_ident,
-        })
+    /// Sets whether the item itself should be emitted.
+    pub fn emit(mut self, emit: bool) -> Self {
+        self.emit = emit;
+        self
     }
-}
 
-/// Parses a proc macro function from a `TokenStream2` expecting only the specified `macro_type`
-pub fn parse_proc_macro_variant<T: Into<TokenStream2>>(
-    tokens: T,
-    macro_type: ProcMacroType,
-) -> Result<ProcMacro> {
-    let proc_macro = ProcMacro::from(tokens.into())?;
-    if proc_macro.macro_type != macro_type {
-        let actual = proc_macro.macro_type.to_str();
-        let desired = macro_type.to_str();
-        return Err(Error::new(
-            proc_macro.proc_fn.sig.ident.span(),
-            format!(
-                "expected a function definition with {} but found {} instead",
-                actual, desired
-            ),
-        ));
+    /// Sets whether each variant of an enum should also be exported individually.
+    pub fn variants(mut self, variants: bool) -> Self {
+        self.variants = variants;
+        self
     }
-    Ok(proc_macro)
-}
 
-/// Convenience function that will pretty-print anything compatible with [`TokenStream2`]
-/// including [`TokenStream2`], `TokenStream`, and all [`syn`] items.
-///
-/// Uses the `prettyplease` crate. Only built if the `pretty_print` feature is enabled.
-#[cfg(feature = "pretty_print")]
-pub fn pretty_print<T: Into<TokenStream2> + Clone>(tokens: &T) {
-    let tokens = (*tokens).clone();
-    println!(
-        "\n\n{}\n\n",
-        prettyplease::unparse(&syn::parse_file(tokens.into().to_string().as_str()).unwrap())
-    );
-}
+    /// Sets whether the item should be emitted with `pub` visibility.
+    pub fn make_pub(mut self, make_pub: bool) -> Self {
+        self.make_pub = make_pub;
+        self
+    }
 
-/// Safely access the `macro_magic` root based on the `MACRO_MAGIC_ROOT` env var, which
-/// defaults to `::macro_magic`, but can be configured via the `[env]` section of
-/// `.cargo/config.toml`
-pub fn macro_magic_root() -> Path {
-    parse2::<Path>(
-        MACRO_MAGIC_ROOT
-            .parse::<TokenStream2>()
-            .expect("environment var `MACRO_MAGIC_ROOT` must parse to a valid TokenStream2"),
-    )
-    .expect("environment variable `MACRO_MAGIC_ROOT` must parse to a valid syn::Path")
-}
+    /// Sets whether the item should also be exported under a hashed name.
+    pub fn hash(mut self, hash: bool) -> Self {
+        self.hash = hash;
+        self
+    }
 
-/// Safely access a subpath of `macro_magic::__private`
-pub fn private_path<T: Into<TokenStream2> + Clone>(subpath: &T) -> Path {
-    let subpath = subpath.clone().into();
-    let root = macro_magic_root();
-    parse_quote!(#root::__private::#subpath)
-}
+    /// Sets whether the body of a function should also be exported on its own.
+    pub fn body(mut self, body: bool) -> Self {
+        self.body = body;
+        self
+    }
 
-/// Safely access a subpath of `macro_magic`
-pub fn macro_magic_path<T: Into<TokenStream2> + Clone>(subpath: &T) -> Path {
-    let subpath = subpath.clone().into();
-    let root = macro_magic_root();
-    parse_quote! {
-        #root::#subpath
+    /// Sets whether the signature of a function should also be exported on its own.
+    pub fn sig(mut self, sig: bool) -> Self {
+        self.sig = sig;
+        self
     }
-}
 
-/// Returns the specified string in snake_case
-pub fn to_snake_case(input: impl Into<String>) -> String {
-    let input: String = input.into();
-    if input.len() == 0 {
-        return input.into();
+    /// Sets whether each associated item of a trait should also be exported individually.
+    pub fn items(mut self, items: bool) -> Self {
+        self.items = items;
+        self
     }
-    let mut prev_lower = input.chars().next().unwrap().is_lowercase();
-    let mut prev_whitespace = true;
-    let mut first = true;
-    let mut output: Vec<char> = Vec::new();
-    for c in input.chars() {
-        if c == '_' {
-            prev_whitespace = true;
-            output.push('_');
-            continue;
-        }
-        if !c.is_ascii_alphanumeric() && c != '_' && !c.is_whitespace() {
-            continue;
-        }
-        if !first && c.is_whitespace() || c == '_' {
-            if !prev_whitespace {
-                output.push('_');
-            }
-            prev_whitespace = true;
-        } else {
-            let current_lower = c.is_lowercase();
-            if ((prev_lower != current_lower && prev_lower)
-                || (prev_lower == current_lower && !prev_lower))
-                && !first
-                && !prev_whitespace
-            {
-                output.push('_');
-            }
-            output.push(c.to_ascii_lowercase());
-            prev_lower = current_lower;
-            prev_whitespace = false;
-        }
-        first = false;
+
+    /// Sets whether each field of a union or struct should also be exported individually.
+    pub fn fields(mut self, fields: bool) -> Self {
+        self.fields = fields;
+        self
     }
-    output.iter().collect::<String>()
-}
 
-/// Converts a string-like value (via [`Display`]) such that the sequence `~~` is safely escaped
-/// so that `~~` can be used as a list delimiter.
-///
-/// Used by [`forward_tokens_internal`] to escape items appearing in the `extra` variable.
-pub fn escape_extra<T: Display>(extra: T) -> String {
-    extra
-        .to_string()
-        .replace("\\", "\\\\")
-        .replace("~~", "\\~\\~")
+    /// Sets the "schema version" of the exported tokens.
+    pub fn version(mut self, version: u64) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Sets whether the generics of the item should also be stored on their own.
+    pub fn with_generics(mut self, with_generics: bool) -> Self {
+        self.with_generics = with_generics;
+        self
+    }
+
+    /// Sets whether items containing inner attributes should be rejected.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Sets whether the emitted item should be marked `#[allow(unused)]`.
+    pub fn allow_unused(mut self, allow_unused: bool) -> Self {
+        self.allow_unused = allow_unused;
+        self
+    }
+
+    /// Sets whether the value expression of a const should also be stored on its own.
+    pub fn value(mut self, value: bool) -> Self {
+        self.value = value;
+        self
+    }
+
+    /// Sets the prefix of the generated callback macros.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Sets whether the generated callback macros should be local to the defining crate.
+    pub fn local(mut self, local: bool) -> Self {
+        self.local = local;
+        self
+    }
+
+    /// Sets the `cfg` predicate the generated callback macros are gated behind.
+    pub fn cfg(mut self, cfg: impl Into<TokenStream2>) -> Self {
+        self.cfg = Some(cfg.into());
+        self
+    }
+
+    /// Sets whether the source text of the item should also be stored.
+    pub fn verbatim_source(mut self, verbatim_source: bool) -> Self {
+        self.verbatim_source = verbatim_source;
+        self
+    }
+
+    /// Sets the verbatim source text of the item, enabling `verbatim_source`.
+    pub fn source_text(mut self, source_text: impl Into<String>) -> Self {
+        self.verbatim_source = true;
+        self.source_text = Some(source_text.into());
+        self
+    }
+
+    /// Sets whether a `const fn` returning the stringified tokens of the item should be
+    /// emitted.
+    pub fn const_fn(mut self, const_fn: bool) -> Self {
+        self.const_fn = const_fn;
+        self
+    }
+
+    /// Sets whether a `const` containing the stringified tokens of the item should be emitted.
+    pub fn export_const(mut self, export_const: bool) -> Self {
+        self.export_const = export_const;
+        self
+    }
+
+    /// Sets how the `#[cfg_attr(..)]`s within the exported tokens are resolved.
+    pub fn cfg_attr(mut self, cfg_attr: CfgAttrResolution) -> Self {
+        self.cfg_attr = cfg_attr;
+        self
+    }
 }
 
-/// Unescapes a `String` that has been escaped via [`escape_extra`].
+/// Controls how `#[export_tokens]` handles the `#[cfg_attr(..)]` attributes within an exported
+/// item, via the `cfg_attr` option (e.g. `#[export_tokens(cfg_attr = apply)]`).
 ///
-/// Used by [`forward_tokens_internal`] to unescape items appearing in the `extra` variable.
-pub fn unescape_extra<T: Display>(extra: T) -> String {
-    extra
-        .to_string()
-        .replace("\\\\", "\\")
-        .replace("\\~\\~", "~~")
+/// The `cfg_attr`s of the item itself are evaluated by the compiler (under the exporting
+/// crate's configuration) before `#[export_tokens]` sees the item, so this only affects those
+/// nested within it, such as on fields, variants or associated items. Either way, the emitted
+/// item keeps its `cfg_attr`s, which the compiler evaluates as usual.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum CfgAttrResolution {
+    /// Store `cfg_attr`s unexpanded, so they are evaluated under the configuration of
+    /// wherever the tokens end up being expanded.
+    #[default]
+    Keep,
+    /// Replace each `cfg_attr` with the attributes it contains, as if its predicate held.
+    Apply,
+    /// Remove each `cfg_attr`, as if its predicate didn't hold.
+    Strip,
 }
 
-/// "Flattens" an [`struct@Ident`] by converting it to snake case.
-///
-/// Used by [`export_tokens_macro_ident`].
-pub fn flatten_ident(ident: &Ident) -> Ident {
-    Ident::new(to_snake_case(ident.to_string()).as_str(), ident.span())
+impl syn::parse::Parse for CfgAttrResolution {
+    fn parse(input: ParseStream) -> Result<Self> {
+        if input.peek(keywords::keep) {
+            input.parse::<keywords::keep>()?;
+            Ok(CfgAttrResolution::Keep)
+        } else if input.peek(keywords::apply) {
+            input.parse::<keywords::apply>()?;
+            Ok(CfgAttrResolution::Apply)
+        } else if input.peek(keywords::strip) {
+            input.parse::<keywords::strip>()?;
+            Ok(CfgAttrResolution::Strip)
+        } else {
+            Err(input.error("expected one of `keep`, `apply` or `strip`"))
+        }
+    }
 }
 
-/// Produces the full path for the auto-generated callback-based decl macro that allows us to
-/// forward tokens across crate boundaries.
+/// The option keywords accepted by `#[export_tokens]` (see [`ExportTokensArgs`]).
 ///
-/// Used by [`export_tokens_internal`] and several other functions.
-pub fn export_tokens_macro_ident(ident: &Ident) -> Ident {
-    let ident = flatten_ident(&ident);
-    let ident_string = format!("__export_tokens_tt_{}", ident.to_token_stream().to_string());
-    Ident::new(ident_string.as_str(), Span::call_site())
+/// These take precedence over the bare override name, so e.g. `#[export_tokens(value)]`
+/// enables the `value` option rather than exporting the item under the name `value`. Use
+/// `#[export_tokens(name = value)]` to export under one of these names.
+pub const EXPORT_TOKENS_OPTIONS: &[&str] = &[
+    "variants",
+    "make_pub",
+    "hash",
+    "body",
+    "sig",
+    "items",
+    "fields",
+    "with_generics",
+    "strict",
+    "warn_unused",
+    "value",
+    "local",
+    "verbatim_source",
+    "const_fn",
+    "export_const",
+    "cfg",
+    "cfg_attr",
+    "emit",
+    "no_emit",
+    "dump",
+    "version",
+];
+
+impl syn::parse::Parse for ExportTokensArgs {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let mut args = ExportTokensArgs::default();
+        while !input.is_empty() {
+            if input.peek(keywords::name) && input.peek2(Token![=]) {
+                input.parse::<keywords::name>()?;
+                input.parse::<Token![=]>()?;
+                let name = input.parse::<Ident>()?;
+                if args.name.is_some() {
+                    return Err(Error::new(
+                        name.span(),
+                        "only one export name can be specified",
+                    ));
+                }
+                args.name = Some(name);
+            } else if input.peek(keywords::variants) {
+                input.parse::<keywords::variants>()?;
+                args.variants = true;
+            } else if input.peek(keywords::make_pub) {
+                input.parse::<keywords::make_pub>()?;
+                args.make_pub = true;
+            } else if input.peek(keywords::hash) {
+                input.parse::<keywords::hash>()?;
+                args.hash = true;
+            } else if input.peek(keywords::body) {
+                input.parse::<keywords::body>()?;
+                args.body = true;
+            } else if input.peek(keywords::sig) {
+                input.parse::<keywords::sig>()?;
+                args.sig = true;
+            } else if input.peek(keywords::items) {
+                input.parse::<keywords::items>()?;
+                args.items = true;
+            } else if input.peek(keywords::fields) {
+                input.parse::<keywords::fields>()?;
+                args.fields = true;
+            } else if input.peek(keywords::with_generics) {
+                input.parse::<keywords::with_generics>()?;
+                args.with_generics = true;
+            } else if input.peek(keywords::strict) {
+                input.parse::<keywords::strict>()?;
+                args.strict = true;
+            } else if input.peek(keywords::warn_unused) {
+                input.parse::<keywords::warn_unused>()?;
+                args.warn_unused = true;
+            } else if input.peek(keywords::value) {
+                input.parse::<keywords::value>()?;
+                args.value = true;
+            } else if input.peek(keywords::local) {
+                input.parse::<keywords::local>()?;
+                args.local = true;
+            } else if input.peek(keywords::verbatim_source) {
+                input.parse::<keywords::verbatim_source>()?;
+                args.verbatim_source = true;
+            } else if input.peek(keywords::const_fn) {
+                input.parse::<keywords::const_fn>()?;
+                args.const_fn = true;
+            } else if input.peek(keywords::export_const) {
+                input.parse::<keywords::export_const>()?;
+                args.export_const = true;
+            } else if input.peek(keywords::cfg) {
+                let cfg = input.parse::<keywords::cfg>()?;
+                if args.cfg.is_some() {
+                    return Err(Error::new(cfg.span, "`cfg` can only be specified once"));
+                }
+                let content;
+                syn::parenthesized!(content in input);
+                args.cfg = Some(content.parse()?);
+            } else if input.peek(keywords::cfg_attr) {
+                let cfg_attr = input.parse::<keywords::cfg_attr>()?;
+                input.parse::<Token![=]>()?;
+                if args.cfg_attr.is_some() {
+                    return Err(Error::new(
+                        cfg_attr.span,
+                        "`cfg_attr` can only be specified once",
+                    ));
+                }
+                args.cfg_attr = Some(input.parse()?);
+            } else if input.peek(keywords::emit) || input.peek(keywords::no_emit) {
+                let emit = input.peek(keywords::emit);
+                let span = match emit {
+                    true => input.parse::<keywords::emit>()?.span,
+                    false => input.parse::<keywords::no_emit>()?.span,
+                };
+                if args.emit.is_some() {
+                    return Err(Error::new(
+                        span,
+                        "only one of `emit` and `no_emit` can be specified",
+                    ));
+                }
+                args.emit = Some(emit);
+            } else if input.peek(keywords::dump) {
+                let dump = input.parse::<keywords::dump>()?;
+                input.parse::<Token![=]>()?;
+                if args.dump.is_some() {
+                    return Err(Error::new(dump.span, "`dump` can only be specified once"));
+                }
+                args.dump = Some(input.parse()?);
+            } else if input.peek(keywords::version) {
+                let version = input.parse::<keywords::version>()?;
+                input.parse::<Token![=]>()?;
+                if args.version.is_some() {
+                    return Err(Error::new(
+                        version.span,
+                        "`version` can only be specified once",
+                    ));
+                }
+                args.version = Some(input.parse::<syn::LitInt>()?.base10_parse()?);
+            } else {
+                let name = input.parse::<Ident>()?;
+                if args.name.is_some() {
+                    return Err(Error::new(
+                        name.span(),
+                        "only one export name can be specified",
+                    ));
+                }
+                args.name = Some(name);
+            }
+            if input.is_empty() {
+                break;
+            }
+            input.parse::<Comma>()?;
+        }
+        Ok(args)
+    }
 }
 
-/// The internal code behind the `#[export_tokens]` attribute macro.
-///
-/// The `attr` variable contains the tokens for the optional naming [`struct@Ident`] (necessary
-/// on [`Item`]s that don't have an inherent [`struct@Ident`]), and the `tokens` variable is
-/// the tokens for the [`Item`] the attribute macro can be attached to. The `attr` variable can
-/// be blank tokens for supported items, which include every valid [`syn::Item`] except for
-/// [`syn::ItemForeignMod`], [`syn::ItemUse`], [`syn::ItemImpl`], and [`Item::Verbatim`], which
-/// all require `attr` to be specified.
+/// Used to parse the args for the [`import_tokens_internal`] function.
 ///
-/// An empty [`TokenStream2`] is sufficient for opting out of using `attr`
-pub fn export_tokens_internal<T: Into<TokenStream2>, E: Into<TokenStream2>>(
-    attr: T,
-    tokens: E,
-    emit: bool,
-) -> Result<TokenStream2> {
-    let attr = attr.into();
-    let item: Item = parse2(tokens.into())?;
-    let ident = match item.clone() {
-        Item::Const(item_const) => Some(item_const.ident),
-        Item::Enum(item_enum) => Some(item_enum.ident),
-        Item::ExternCrate(item_extern_crate) => Some(item_extern_crate.ident),
-        Item::Fn(item_fn) => Some(item_fn.sig.ident),
-        Item::Macro(item_macro) => item_macro.ident, // note this one might not have an Ident as well
-        Item::Mod(item_mod) => Some(item_mod.ident),
-        Item::Static(item_static) => Some(item_static.ident),
-        Item::Struct(item_struct) => Some(item_struct.ident),
-        Item::Trait(item_trait) => Some(item_trait.ident),
-        Item::TraitAlias(item_trait_alias) => Some(item_trait_alias.ident),
-        Item::Type(item_type) => Some(item_type.ident),
-        Item::Union(item_union) => Some(item_union.ident),
-        // Item::ForeignMod(item_foreign_mod) => None,
-        // Item::Use(item_use) => None,
-        // Item::Impl(item_impl) => None,
-        // Item::Verbatim(_) => None,
-        _ => None,
-    };
-    let ident = match ident {
-        Some(ident) => {
-            if let Ok(_) = parse2::<Nothing>(attr.clone()) {
-                ident
+/// You shouldn't need to use this directly.
+pub struct ImportTokensArgs {
+    pub tokens_var_ident: Ident,
+    pub source_path: Path,
+    /// Whether the imported tokens should be appended to an existing, mutable `TokenStream2`
+    /// variable (i.e. `extend <ident> = <path>`) rather than bound to a new one via `let`.
+    pub extend: bool,
+    /// The name the imported item should be renamed to (i.e. `<path> as <ident>`), if any.
+    pub rename: Option<Ident>,
+    /// The minimum "schema version" the imported item must have been exported with, if any.
+    pub min_version: Option<syn::LitInt>,
+    /// The callback macro prefix the item was exported with, if it isn't
+    /// [`MACRO_MAGIC_EXPORT_PREFIX`].
+    pub prefix: Option<LitStr>,
+    /// Whether the item was exported with `#[export_tokens(local)]`, in which case its callback
+    /// macro is referred to by name alone rather than via the crate root.
+    pub local: bool,
+    /// The paths of the attributes to remove from the imported tokens (see
+    /// [`strip_attributes`]), if any.
+    pub strip: Vec<Path>,
+}
+
+impl syn::parse::Parse for ImportTokensArgs {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let extend = input.peek(keywords::extend);
+        if extend {
+            input.parse::<keywords::extend>()?;
+        } else {
+            input.parse::<Token![let]>()?;
+        }
+        let tokens_var_ident = input.parse()?;
+        input.parse::<Token![=]>()?;
+        let source_path = input.parse()?;
+        let rename = match input.peek(Token![as]) {
+            true => {
+                input.parse::<Token![as]>()?;
+                Some(input.parse()?)
+            }
+            false => None,
+        };
+        let mut min_version = None;
+        let mut prefix = None;
+        let mut local = false;
+        let mut strip = None;
+        while !input.is_empty() {
+            input.parse::<Comma>()?;
+            if input.peek(keywords::min_version) && min_version.is_none() {
+                input.parse::<keywords::min_version>()?;
+                input.parse::<Token![=]>()?;
+                min_version = Some(input.parse()?);
+            } else if input.peek(keywords::prefix) && prefix.is_none() {
+                input.parse::<keywords::prefix>()?;
+                input.parse::<Token![=]>()?;
+                prefix = Some(input.parse()?);
+            } else if input.peek(keywords::local) && !local {
+                input.parse::<keywords::local>()?;
+                local = true;
+            } else if input.peek(keywords::strip) && strip.is_none() {
+                input.parse::<keywords::strip>()?;
+                input.parse::<Token![=]>()?;
+                let content;
+                syn::bracketed!(content in input);
+                let paths = Punctuated::<Path, Comma>::parse_terminated(&content)?;
+                strip = Some(paths.into_iter().collect());
             } else {
-                parse2::<Ident>(attr)?
+                return Err(input.error(
+                    "expected `min_version = ..`, `prefix = \"..\"`, `local` or `strip = [..]`",
+                ));
             }
         }
-        None => parse2::<Ident>(attr)?,
-    };
-    let ident = export_tokens_macro_ident(&ident);
-    let item_emit = match emit {
-        true => quote! {
-            #[allow(unused)]
-            #item
-        },
-        false => quote!(),
-    };
-    let output = quote! {
-        #[doc(hidden)]
-        #[macro_export]
-        macro_rules! #ident {
-            // arm with extra support (used by attr)
-            ($(::)?$($tokens_var:ident)::*, $(::)?$($callback:ident)::*, $extra:expr) => {
-                $($callback)::*! {
-                    $($tokens_var)::*,
-                    #item,
-                    $extra
-                }
-            };
-            // regular arm (used by proc, import_tokens, etc)
-            ($(::)?$($tokens_var:ident)::*, $(::)?$($callback:ident)::*) => {
-                $($callback)::*! {
-                    $($tokens_var)::*,
-                    #item
-                }
-            };
-        }
-        #item_emit
-    };
-    // pretty_print(&output);
-    Ok(output)
+        Ok(ImportTokensArgs {
+            tokens_var_ident,
+            source_path,
+            extend,
+            rename,
+            min_version,
+            prefix,
+            local,
+            strip: strip.unwrap_or_default(),
+        })
+    }
 }
 
-/// Internal implementation of `export_tokens_alias!`. Allows creating a renamed/rebranded
-/// macro that does the same thing as `#[export_tokens]`
-pub fn export_tokens_alias_internal<T: Into<TokenStream2>>(
-    tokens: T,
-    emit: bool,
-) -> Result<TokenStream2> {
-    let alias = parse2::<Ident>(tokens.into())?;
-    let export_tokens_internal_path = macro_magic_path(&quote!(mm_core::export_tokens_internal));
-    Ok(quote! {
-        #[proc_macro_attribute]
-        pub fn #alias(attr: proc_macro::TokenStream, tokens: proc_macro::TokenStream) -> proc_macro::TokenStream {
-            match #export_tokens_internal_path(attr, tokens, #emit) {
-                Ok(tokens) => tokens.into(),
-                Err(err) => err.to_compile_error().into(),
+/// Used to parse the args for the [`import_tokens_attr_internal`] function, i.e. an optional
+/// path to a re-export of `macro_magic`, followed by the optional `dry_run` flag and
+/// `then = <path>` option.
+///
+/// You shouldn't need to use this directly.
+pub struct ImportTokensAttrArgs {
+    /// The path `macro_magic` should be referenced by in the generated code.
+    pub mm_override_path: Path,
+    /// Whether the attached item should be re-emitted unchanged instead of being forwarded
+    /// to the user's proc macro body along with the imported item.
+    pub dry_run: bool,
+    /// The path of an attribute macro the output of the user's proc macro body should be
+    /// passed through, if any.
+    pub then: Option<Path>,
+}
+
+impl syn::parse::Parse for ImportTokensAttrArgs {
+    fn parse(input: ParseStream) -> Result<Self> {
+        Self::parse_with_root(input, macro_magic_root)
+    }
+}
+
+impl ImportTokensAttrArgs {
+    /// Like the [`Parse`](`syn::parse::Parse`) implementation, but falls back to the
+    /// `macro_magic` root returned by `root` (rather than parsing it anew via
+    /// [`macro_magic_root`]) when no override path is specified.
+    fn parse_with_root(input: ParseStream, root: impl FnOnce() -> Path) -> Result<Self> {
+        let mut mm_override_path = None;
+        let is_option = |input: ParseStream| {
+            input.peek(keywords::dry_run) || (input.peek(keywords::then) && input.peek2(Token![=]))
+        };
+        if !input.is_empty() && !is_option(input) {
+            mm_override_path = Some(input.parse::<Path>().map_err(|err| {
+                Error::new(
+                    err.span(),
+                    "expected the path to a re-export of `macro_magic`, e.g. \
+                    `my_crate::macro_magic`",
+                )
+            })?);
+            if !input.is_empty() {
+                input.parse::<Comma>()?;
             }
         }
-    })
+        let mut dry_run = false;
+        let mut then = None;
+        while !input.is_empty() {
+            if input.peek(keywords::dry_run) && !dry_run {
+                input.parse::<keywords::dry_run>()?;
+                dry_run = true;
+            } else if input.peek(keywords::then) && then.is_none() {
+                input.parse::<keywords::then>()?;
+                input.parse::<Token![=]>()?;
+                then = Some(input.parse()?);
+            } else {
+                return Err(input.error("expected `dry_run` or `then = <path>`"));
+            }
+            if !input.is_empty() {
+                input.parse::<Comma>()?;
+            }
+        }
+        Ok(ImportTokensAttrArgs {
+            mm_override_path: mm_override_path.unwrap_or_else(root),
+            dry_run,
+            then,
+        })
+    }
 }
 
-/// The internal implementation for the `import_tokens` macro.
+/// Used to parse the args for the [`export_tokens_alias_internal`] function.
 ///
-/// You can call this in your own proc macros to make use of the `import_tokens` functionality
-/// directly, though this approach is limited. The arguments should be a [`TokenStream2`] that
-/// can parse into an [`ImportTokensArgs`] successfully. That is a valid `let` variable
-/// declaration set to equal a path where an `#[export_tokens]` with the specified ident can be
-/// found.
+/// You shouldn't need to use this directly.
+#[derive(Parse)]
+pub struct ExportTokensAliasArgs {
+    /// The name of the alias
+    pub alias: Ident,
+    _comma: Option<Comma>,
+    #[parse_if(_comma.is_some())]
+    _prefix: Option<keywords::prefix>,
+    #[parse_if(_comma.is_some())]
+    _eq: Option<Token![=]>,
+    /// The callback macro prefix used by the alias, if any
+    #[parse_if(_comma.is_some())]
+    pub prefix: Option<LitStr>,
+}
+
+/// Used to parse the args for the [`import_tokens_fn_internal`] function.
 ///
-/// ### Example:
-/// ```
-/// use macro_magic_core::*;
-/// use quote::quote;
+/// You shouldn't need to use this directly.
+#[derive(Parse)]
+pub struct ImportTokensFnArgs {
+    /// The visibility of the generated function
+    pub vis: Visibility,
+    _fn: Token![fn],
+    /// The name of the generated function
+    pub fn_ident: Ident,
+    _eq: Token![=],
+    pub source_path: Path,
+}
+
+/// Used to parse the args for the [`import_tokens_mod_internal`] function.
 ///
-/// let some_ident = quote!(my_tokens);
-/// let some_path = quote!(other_crate::exported_item);
-/// let tokens = import_tokens_internal(quote!(let #some_ident = other_crate::ExportedItem)).unwrap();
-/// assert_eq!(
-///     tokens.to_string(),
-///     "other_crate :: __export_tokens_tt_exported_item ! { my_tokens , \
-///     :: macro_magic :: __private :: import_tokens_inner }");
-/// ```
-/// If these tokens were emitted as part of a proc macro, they would expand to a variable
-/// declaration like:
-/// ```ignore
-/// let my_tokens: TokenStream2;
-/// ```
-/// where `my_tokens` contains the tokens of `ExportedItem`.
-pub fn import_tokens_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
-    let args = parse2::<ImportTokensArgs>(tokens.into())?;
-    let Some(source_ident_seg) = args.source_path.segments.last() else { unreachable!("must have at least one segment") };
-    let source_ident_seg = export_tokens_macro_ident(&source_ident_seg.ident);
-    let source_path = if args.source_path.segments.len() > 1 {
-        let Some(crate_seg) = args.source_path.segments.first() else {
-            unreachable!("path has at least two segments, so there is a first segment");
-        };
-        quote!(#crate_seg::#source_ident_seg)
-    } else {
-        quote!(#source_ident_seg)
-    };
-    let inner_macro_path = private_path(&quote!(import_tokens_inner));
-    let tokens_var_ident = args.tokens_var_ident;
-    Ok(quote! {
-        #source_path! { #tokens_var_ident, #inner_macro_path }
-    })
+/// You shouldn't need to use this directly.
+#[derive(Parse)]
+pub struct ImportTokensModArgs {
+    /// The visibility of the generated module
+    pub vis: Visibility,
+    /// The name of the generated module
+    pub mod_ident: Ident,
+    _comma: Comma,
+    /// The path of the exported item to import into the module
+    pub source_path: Path,
 }
 
-/// The internal implementation for the `import_tokens_inner` macro.
+/// Used to parse the args for the [`assert_tokens_eq_internal`] function.
 ///
-/// You shouldn't need to call this in any circumstances but it is provided just in case.
-pub fn import_tokens_inner_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
-    let parsed = parse2::<ImportedTokens>(tokens.into())?;
-    let tokens_string = parsed.item.to_token_stream().to_string();
-    let ident = parsed.tokens_var_ident;
-    let token_stream_2 = private_path(&quote!(TokenStream2));
-    Ok(quote! {
-        let #ident = #tokens_string.parse::<#token_stream_2>().expect("failed to parse quoted tokens");
-    })
+/// You shouldn't need to use this directly.
+#[derive(Parse)]
+pub struct AssertTokensEqArgs {
+    /// The path of the first exported item
+    pub left: Path,
+    _comma: Comma,
+    /// The path of the second exported item
+    pub right: Path,
+    _trailing_comma: Option<Comma>,
 }
 
-/// The internal implementation for the `forward_tokens` macro.
+/// Used to parse the args for the [`export_tokens_file_internal`] function, i.e. any number of
+/// items.
 ///
-/// You shouldn't need to call this in any circumstances but it is provided just in case.
-pub fn forward_tokens_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
-    let args = parse2::<ForwardTokensArgs>(tokens.into())?;
-    let mm_path = match args.mm_path {
-        Some(path) => path,
-        None => macro_magic_root(),
-    };
-    let Some(source_ident_seg) = args.source.segments.last() else { unreachable!("must have at least one segment") };
-    let source_ident_seg = export_tokens_macro_ident(&source_ident_seg.ident);
-    let source_path = if args.source.segments.len() > 1 {
-        let Some(crate_seg) = args.source.segments.first() else {
-            unreachable!("path has at least two segments, so there is a first segment");
-        };
-        quote!(#crate_seg::#source_ident_seg)
-    } else {
-        quote!(#source_ident_seg)
-    };
-    let target_path = args.target;
-    if let Some(extra) = args.extra {
-        Ok(quote! {
-            #source_path! {
-                #target_path,
-                #mm_path::__private::forward_tokens_inner,
-                #extra
-            }
-        })
-    } else {
-        Ok(quote! {
-            #source_path! { #target_path, #mm_path::__private::forward_tokens_inner }
-        })
-    }
+/// You shouldn't need to use this directly.
+pub struct ExportTokensFileArgs {
+    /// The items to export
+    pub items: Vec<Item>,
 }
 
-/// Used by [`forward_tokens_internal`].
-pub fn forward_tokens_inner_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
-    let parsed = parse2::<ForwardedTokens>(tokens.into())?;
-    let target_path = parsed.target_path;
-    let imported_tokens = parsed.item;
-    let combined_tokens = match parsed.extra {
-        Some(extra) => quote! {
-            #imported_tokens,
-            #extra
-        },
-        None => quote!(#imported_tokens),
-    };
-    Ok(quote! {
-        #target_path! {
-            #combined_tokens
+impl syn::parse::Parse for ExportTokensFileArgs {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let mut items = Vec::new();
+        while !input.is_empty() {
+            items.push(input.parse()?);
         }
-    })
+        Ok(ExportTokensFileArgs { items })
+    }
 }
 
-/// The internal implementation for the `#[with_custom_parsing(..)` attribute macro.
+/// Used to parse the args for the [`import_tokens_doc_internal`] function.
 ///
-/// Note that this implementation just does parsing and re-orders the attributes of the
-/// attached proc macro attribute definition such that the `#[import_tokens_attr]` attribute
-/// comes before this attribute. The real implementation for `#[with_custom_parsing(..)]` can
-/// be found in [`import_tokens_attr_internal`]. The purpose of this is to allow programmers to
-/// use either ordering and still have the proper compiler errors when something is invalid.
+/// You shouldn't need to use this directly.
+#[derive(Parse)]
+pub struct ImportTokensDocArgs {
+    /// The path of the exported item whose tokens should be embedded in the docs
+    pub source_path: Path,
+    _arrow: Token![=>],
+    /// The outer attributes (including doc comments) of the item, which are emitted before the
+    /// generated doc attribute
+    #[call(Attribute::parse_outer)]
+    pub attrs: Vec<Attribute>,
+    /// The item the generated doc attribute is attached to
+    pub item: Item,
+}
+
+/// Used to parse the args for the [`import_tokens_merge_internal`] function.
 ///
-/// The `import_tokens_att_name` argument is used when generating error messages and matching
-/// against the `#[import_tokens_attr]` macro this is to be used with. If you use a
-/// renamed/rebranded version of `#[import_tokens_attr]`, you should change this value to match
-/// the name of your macro.
-pub fn with_custom_parsing_internal<T1: Into<TokenStream2>, T2: Into<TokenStream2>>(
-    attr: T1,
-    tokens: T2,
-    import_tokens_attr_name: &'static str,
-) -> Result<TokenStream2> {
-    // verify that we are attached to a valid #[import_tokens_attr] proc macro def
-    let proc_macro = parse_proc_macro_variant(tokens, ProcMacroType::Attribute)?;
-    if proc_macro
-        .proc_fn
-        .attrs
-        .iter()
-        .find(|attr| {
-            if let Some(seg) = attr.meta.path().segments.last() {
-                return seg.ident == import_tokens_attr_name;
-            }
-            false
-        })
-        .is_none()
-    {
-        return Err(Error::new(
-            Span::call_site(),
-            format!(
-                "Can only be attached to an attribute proc macro marked with `#[{}]`",
-                import_tokens_attr_name
-            ),
-        ));
-    }
-
-    // ensure there is only one `#[with_custom_parsing]`
-    if proc_macro
-        .proc_fn
-        .attrs
-        .iter()
-        .find(|attr| {
-            if let Some(seg) = attr.meta.path().segments.last() {
-                return seg.ident == "with_custom_parsing_internal";
-            }
-            false
-        })
-        .is_some()
-    {
-        return Err(Error::new(
-            Span::call_site(),
-            "Only one instance of #[with_custom_parsing] can be attached at a time.",
-        ));
-    }
-
-    // parse attr to ensure it is a Path
-    let custom_path = parse2::<Path>(attr.into())?;
-
-    // emit original item unchanged now that parsing has passed
-    let mut item_fn = proc_macro.proc_fn;
-    item_fn
-        .attrs
-        .push(parse_quote!(#[with_custom_parsing(#custom_path)]));
-
-    Ok(quote!(#item_fn))
+/// You shouldn't need to use this directly.
+#[derive(Parse)]
+pub struct ImportTokensMergeArgs {
+    _let: Token![let],
+    pub tokens_var_ident: Ident,
+    _eq: Token![=],
+    #[bracket]
+    _bracket: syn::token::Bracket,
+    /// The paths of the items to merge, in the order their tokens are concatenated
+    #[inside(_bracket)]
+    #[call(Punctuated::parse_terminated)]
+    pub source_paths: Punctuated<Path, Comma>,
 }
 
-/// Internal implementation for the `#[import_tokens_attr]` attribute.
+/// The context made available to the body of an
+/// [`#[import_tokens_attr]`](`import_tokens_attr_internal`) attribute macro via the magic
+/// variable `__context`.
 ///
-/// You shouldn't need to use this directly, but it may be useful if you wish to rebrand/rename
-/// the `#[import_tokens_attr]` macro without extra indirection.
-pub fn import_tokens_attr_internal<T1: Into<TokenStream2>, T2: Into<TokenStream2>>(
-    attr: T1,
-    tokens: T2,
-) -> Result<TokenStream2> {
-    let mm_override_path = match parse2::<Path>(attr.into()) {
-        Ok(override_path) => override_path,
-        Err(_) => macro_magic_root(),
-    };
-    let mm_path = macro_magic_root();
-    let mut proc_macro = parse_proc_macro_variant(tokens, ProcMacroType::Attribute)?;
-
-    // params
-    let attr_ident = proc_macro.attr_ident.unwrap();
-    let tokens_ident = proc_macro.tokens_ident;
-
-    // handle custom parsing, if applicable
-    let path_resolver = if let Some(index) = proc_macro.proc_fn.attrs.iter().position(|attr| {
-        if let Some(seg) = attr.meta.path().segments.last() {
-            return seg.ident == "with_custom_parsing";
-        }
-        false
-    }) {
-        let custom_attr = &proc_macro.proc_fn.attrs[index];
-        let custom_struct_path: Path = custom_attr.parse_args()?;
+/// This is packed into (see [`ImportTokensAttrContext::to_extra`]) and unpacked from (see
+/// [`ImportTokensAttrContext::from_extra`]) the `extra` string that is forwarded along with the
+/// foreign item(s) being imported.
+#[derive(Clone)]
+pub struct ImportTokensAttrContext {
+    /// The item the attribute is attached to
+    pub item: Item,
+    /// The foreign path(s) passed to the attribute, in the order they are imported
+    pub source_paths: Vec<Path>,
+    /// The tokens of the custom parsed attribute (see `#[with_custom_parsing]`), or empty
+    /// tokens if custom parsing isn't being used
+    pub custom_parsed: TokenStream2,
+    /// The raw, unparsed tokens of the attribute
+    pub raw_attr: TokenStream2,
+    /// The foreign items that have been imported so far, in the order they were imported
+    pub foreign_items: Vec<Item>,
+}
 
-        proc_macro.proc_fn.attrs.remove(index);
-        quote! {
-            let custom_parsed = syn::parse_macro_input!(#attr_ident as #custom_struct_path);
-            let path = (&custom_parsed as &dyn ForeignPath).foreign_path();
-            let _ = (&custom_parsed as &dyn quote::ToTokens);
-        }
-    } else {
-        quote! {
-            let custom_parsed = quote::quote!();
-            let path = syn::parse_macro_input!(#attr_ident as syn::Path);
+impl ImportTokensAttrContext {
+    /// Creates a new context, with no foreign items imported yet
+    pub fn new(
+        item: Item,
+        source_paths: Vec<Path>,
+        custom_parsed: TokenStream2,
+        raw_attr: TokenStream2,
+    ) -> Self {
+        ImportTokensAttrContext {
+            item,
+            source_paths,
+            custom_parsed,
+            raw_attr,
+            foreign_items: Vec::new(),
         }
-    };
-
-    // outer macro
-    let orig_sig = proc_macro.proc_fn.sig;
-    let orig_stmts = proc_macro.proc_fn.block.stmts;
-    let orig_attrs = proc_macro.proc_fn.attrs;
+    }
 
-    // inner macro
-    let inner_macro_ident = format_ident!("__import_tokens_attr_{}_inner", orig_sig.ident);
-    let mut inner_sig = orig_sig.clone();
-    inner_sig.ident = inner_macro_ident.clone();
-    inner_sig.inputs.pop().unwrap();
+    /// The first (and usually only) foreign path passed to the attribute
+    pub fn source_path(&self) -> Option<&Path> {
+        self.source_paths.first()
+    }
 
-    let pound = Punct::new('#', Spacing::Alone);
+    /// The next foreign path whose item still needs to be imported, if any
+    pub fn next_source_path(&self) -> Option<&Path> {
+        self.source_paths.get(self.foreign_items.len())
+    }
 
-    // final quoted tokens
-    Ok(quote! {
-        #(#orig_attrs)
-        *
-        pub #orig_sig {
-            use #mm_path::__private::*;
-            use #mm_path::__private::quote::ToTokens;
-            use #mm_path::mm_core::*;
-            let attached_item = syn::parse_macro_input!(#tokens_ident as syn::Item);
-            let attached_item_str = attached_item.to_token_stream().to_string();
-            #path_resolver
-            let extra = format!(
-                "{}~~{}~~{}",
-                escape_extra(attached_item_str),
-                escape_extra(path.to_token_stream().to_string().as_str()),
-                escape_extra(custom_parsed.to_token_stream().to_string().as_str())
-            );
-            quote::quote! {
-                #mm_override_path::forward_tokens! {
-                    #pound path,
-                    #inner_macro_ident,
-                    #mm_override_path,
-                    #pound extra
-                }
-            }.into()
-        }
+    /// Packs this context into an `extra` [`struct@LitStr`] consisting of `~~`-separated fields,
+    /// each escaped with [`escape_extra`]: the attached item, the foreign paths, the custom
+    /// parsed tokens, the raw attr tokens, and finally any foreign items imported so far.
+    pub fn to_extra(&self) -> LitStr {
+        let source_paths = &self.source_paths;
+        let fields = [
+            self.item.to_token_stream(),
+            quote!(#(#source_paths),*),
+            self.custom_parsed.clone(),
+            self.raw_attr.clone(),
+        ]
+        .into_iter()
+        .chain(self.foreign_items.iter().map(ToTokens::to_token_stream));
+        LitStr::new(pack_extras(fields).as_str(), Span::call_site())
+    }
 
-        #[doc(hidden)]
-        #[proc_macro]
-        pub #inner_sig {
-            let __combined_args = #mm_path::__private::syn::parse_macro_input!(#attr_ident as #mm_path::mm_core::AttrItemWithExtra);
-            let (#attr_ident, #tokens_ident) = (__combined_args.imported_item, __combined_args.extra);
-            let #attr_ident: proc_macro::TokenStream = #attr_ident.to_token_stream().into();
-            let (#tokens_ident, __source_path, __custom_tokens) = {
-                use #mm_path::mm_core::unescape_extra;
-                let extra = #tokens_ident.value();
-                let mut extra_split = extra.split("~~");
-                let (tokens_string, foreign_path_string, custom_parsed_string) = (
-                    unescape_extra(extra_split.next().unwrap()),
-                    unescape_extra(extra_split.next().unwrap()),
-                    unescape_extra(extra_split.next().unwrap()),
-                );
-                let foreign_path: proc_macro::TokenStream = foreign_path_string.as_str().parse().unwrap();
-                let tokens: proc_macro::TokenStream = tokens_string.as_str().parse().unwrap();
-                let custom_parsed_tokens: proc_macro::TokenStream = custom_parsed_string.as_str().parse().unwrap();
-                (tokens, foreign_path, custom_parsed_tokens)
-            };
-            #(#orig_stmts)
-            *
+    /// Unpacks a context that was packed by [`ImportTokensAttrContext::to_extra`], returning
+    /// an error (rather than panicking) if `extra` is malformed.
+    pub fn from_extra(extra: &LitStr) -> Result<Self> {
+        let span = extra.span();
+        let value = extra.value();
+        let fields = unpack_extra(&value)
+            .map_err(|err| Error::new(span, format!("malformed `extra`: {}", err)))?;
+        if fields.len() < 4 {
+            return Err(Error::new(
+                span,
+                format!(
+                    "malformed `extra`: expected at least 4 fields but found {}",
+                    fields.len()
+                ),
+            ));
         }
-    })
+        let parse_tokens = |field: &String, name: &str| {
+            field.parse::<TokenStream2>().map_err(|err| {
+                Error::new(
+                    span,
+                    format!("malformed `extra`: invalid {}: {}", name, err),
+                )
+            })
+        };
+        let parse_item = |field: &String, name: &str| {
+            parse2::<Item>(parse_tokens(field, name)?).map_err(|err| {
+                Error::new(
+                    span,
+                    format!("malformed `extra`: invalid {}: {}", name, err),
+                )
+            })
+        };
+        let item = parse_item(&fields[0], "attached item")?;
+        let source_paths = syn::parse::Parser::parse2(
+            syn::punctuated::Punctuated::<Path, Comma>::parse_separated_nonempty,
+            parse_tokens(&fields[1], "source path")?,
+        )
+        .map_err(|err| {
+            Error::new(
+                span,
+                format!("malformed `extra`: invalid source path: {}", err),
+            )
+        })?
+        .into_iter()
+        .collect();
+        let custom_parsed = parse_tokens(&fields[2], "custom parsed tokens")?;
+        let raw_attr = parse_tokens(&fields[3], "raw attr")?;
+        let foreign_items = fields[4..]
+            .iter()
+            .map(|field| parse_item(field, "foreign item"))
+            .collect::<Result<Vec<Item>>>()?;
+        Ok(ImportTokensAttrContext {
+            item,
+            source_paths,
+            custom_parsed,
+            raw_attr,
+            foreign_items,
+        })
+    }
 }
 
-/// Internal implementation for the `#[import_tokens_proc]` attribute.
+/// Used to parse the args for the [`import_tokens_env_internal`] function.
 ///
-/// You shouldn't need to use this directly, but it may be useful if you wish to rebrand/rename
-/// the `#[import_tokens_proc]` macro without extra indirection.
-pub fn import_tokens_proc_internal<T1: Into<TokenStream2>, T2: Into<TokenStream2>>(
-    attr: T1,
-    tokens: T2,
-) -> Result<TokenStream2> {
-    let mm_override_path = match parse2::<Path>(attr.into()) {
-        Ok(override_path) => override_path,
-        Err(_) => macro_magic_root(),
-    };
-    let mm_path = macro_magic_root();
-    let proc_macro = parse_proc_macro_variant(tokens, ProcMacroType::Normal)?;
-
-    // outer macro
-    let orig_sig = proc_macro.proc_fn.sig;
-    let orig_stmts = proc_macro.proc_fn.block.stmts;
-    let orig_attrs = proc_macro.proc_fn.attrs;
+/// You shouldn't need to use this directly.
+#[derive(Parse)]
+pub struct ImportTokensEnvArgs {
+    _let: Token![let],
+    pub tokens_var_ident: Ident,
+    _eq: Token![=],
+    pub env_var_ident: Ident,
+}
 
-    // inner macro
-    let inner_macro_ident = format_ident!("__import_tokens_proc_{}_inner", orig_sig.ident);
-    let mut inner_sig = orig_sig.clone();
-    inner_sig.ident = inner_macro_ident.clone();
-    inner_sig.inputs = inner_sig.inputs.iter().rev().cloned().collect();
+/// Used to parse the args for the [`import_tokens_inner_internal`] function.
+///
+/// You shouldn't need to use this directly.
+#[derive(Parse)]
+pub struct ImportedTokens {
+    pub tokens_var_ident: Ident,
+    _comma: Comma,
+    #[call(parse_exported_item)]
+    pub item: Item,
+}
 
-    // params
-    let tokens_ident = proc_macro.tokens_ident;
+/// Like [`ImportedTokens`], but keeps the imported tokens as a raw [`TokenStream2`] so that
+/// exports which aren't full [`Item`]s (such as the `body` and `sig` of a function) can be
+/// imported as well.
+///
+/// You shouldn't need to use this directly.
+#[derive(Parse)]
+pub struct ImportedRawTokens {
+    pub tokens_var_ident: Ident,
+    _comma: Comma,
+    #[call(parse_exported_tokens)]
+    pub tokens: TokenStream2,
+}
 
-    let pound = Punct::new('#', Spacing::Alone);
+#[derive(Parse)]
+pub struct BasicUseStmt {
+    #[call(Attribute::parse_outer)]
+    pub attrs: Vec<Attribute>,
+    pub vis: Visibility,
+    _use: Token![use],
+    pub path: Path,
+    _semi: Token![;],
+}
 
-    // TODO: add support for forwarding source_path for these as well
+/// Delineates the different types of proc macro
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ProcMacroType {
+    /// Corresponds with `#[proc_macro]`
+    Normal,
+    /// Corresponds with `#[proc_macro_attribute]`
+    Attribute,
+    /// Corresponds with `#[proc_macro_derive]`
+    Derive,
+}
 
-    Ok(quote! {
-        #(#orig_attrs)
-        *
-        pub #orig_sig {
-            use #mm_path::__private::*;
-            use #mm_path::__private::quote::ToTokens;
-            let source_path = match syn::parse::<syn::Path>(#tokens_ident) {
-                Ok(path) => path,
-                Err(e) => return e.to_compile_error().into(),
-            };
-            quote::quote! {
-                #mm_override_path::forward_tokens! {
-                    #pound source_path,
-                    #inner_macro_ident,
-                    #mm_override_path
-                }
-            }.into()
+impl ProcMacroType {
+    /// Gets the `&'static str` representation of this proc macro type
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            ProcMacroType::Normal => "#[proc_macro]",
+            ProcMacroType::Attribute => "#[proc_macro_attribute]",
+            ProcMacroType::Derive => "#[proc_macro_derive]",
         }
+    }
 
-        #[doc(hidden)]
-        #[proc_macro]
-        pub #inner_sig {
-            #(#orig_stmts)
-            *
+    /// Gets the [`Attribute`] representation of this proc macro type
+    pub fn to_attr(&self) -> Attribute {
+        match self {
+            ProcMacroType::Normal => parse_quote!(#[proc_macro]),
+            ProcMacroType::Attribute => parse_quote!(#[proc_macro_attribute]),
+            ProcMacroType::Derive => parse_quote!(#[proc_macro_derive]),
         }
-    })
+    }
 }
 
-/// Internal implementation for the `#[use_proc]` and `#[use_attr]` attribute macros
-pub fn use_internal<T1: Into<TokenStream2>, T2: Into<TokenStream2>>(
-    attr: T1,
-    tokens: T2,
-    mode: ProcMacroType,
-) -> Result<TokenStream2> {
-    parse2::<Nothing>(attr.into())?;
-    let orig_stmt = parse2::<BasicUseStmt>(tokens.into())?;
-    let orig_path = orig_stmt.path.clone();
-    let orig_attrs = orig_stmt.attrs;
-    let vis = orig_stmt.vis;
-    let ident = &orig_stmt
-        .path
-        .segments
-        .last()
-        .expect("path must have at least one segment")
-        .ident;
-    let hidden_ident = match mode {
-        ProcMacroType::Normal => format_ident!("__import_tokens_proc_{}_inner", ident),
-        ProcMacroType::Attribute => format_ident!("__import_tokens_attr_{}_inner", ident),
-        ProcMacroType::Derive => unimplemented!(),
+/// Should be implemented by structs that will be passed to `#[with_custom_parsing(..)]`. Such
+/// structs should also implement [`syn::parse::Parse`].
+///
+/// ## Example
+///
+/// ```ignore
+/// #[derive(derive_syn_parse::Parse)]
+/// struct CustomParsingA {
+///     foreign_path: syn::Path,
+///     _comma: syn::token::Comma,
+///     custom_path: syn::Path,
+/// }
+///
+/// impl ToTokens for CustomParsingA {
+///     fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
+///         tokens.extend(self.foreign_path.to_token_stream());
+///         tokens.extend(self._comma.to_token_stream());
+///         tokens.extend(self.custom_path.to_token_stream());
+///     }
+/// }
+///
+/// impl ForeignPath for CustomParsingA {
+///     fn foreign_path(&self) -> &syn::Path {
+///         &self.foreign_path
+///     }
+/// }
+/// ```
+///
+/// For the common case where the struct simply stores the foreign path in one of its fields,
+/// the impl can be generated with `#[derive(ForeignPath)]`, which uses the field marked with
+/// `#[foreign_path]`, or otherwise the field named `foreign_path`.
+pub trait ForeignPath {
+    fn foreign_path(&self) -> &syn::Path;
+
+    /// Returns all of the foreign paths whose tokens should be imported, in the order they
+    /// should be imported. Defaults to just [`ForeignPath::foreign_path`], but can be
+    /// overridden to import the tokens of several foreign items at once (see `__foreign_items`
+    /// in the docs for `#[import_tokens_attr]`).
+    fn foreign_paths(&self) -> Vec<syn::Path> {
+        alloc::vec![self.foreign_path().clone()]
+    }
+}
+
+#[derive(Clone)]
+pub struct ProcMacro {
+    /// The underlying proc macro function definition
+    pub proc_fn: ItemFn,
+    /// Specified the type of this proc macro, i.e. attribute vs normal vs derive
+    pub macro_type: ProcMacroType,
+    /// Specifies the [`struct@Ident`] for the `tokens` parameter of this proc macro function
+    /// definition. For normal and derive macros this is the only parameter, and for attribute
+    /// macros this is the second parameter.
+    pub tokens_ident: Ident,
+    /// Specifies the [`struct@Ident`] for the `attr` parameter of this proc macro function
+    /// definition, if it is an attribute macro. Otherwise this will be set to [`None`].
+    pub attr_ident: Option<Ident>,
+}
+
+impl ProcMacro {
+    /// Constructs a [`ProcMacro`] from anything compatible with [`TokenStream2`].
+    pub fn from<T: Into<TokenStream2>>(tokens: T) -> Result<Self> {
+        let proc_fn = parse2::<ItemFn>(tokens.into())?;
+        let Visibility::Public(_) = proc_fn.vis else {
+            return Err(Error::new(proc_fn.vis.span(), "Visibility must be public"));
+        };
+        let Some(macro_type) = proc_fn.attrs.iter().find_map(|attr| {
+            let path = attr.path().to_token_stream();
+            if syn::parse2::<keywords::proc_macro>(path.clone()).is_ok() {
+                Some(ProcMacroType::Normal)
+            } else if syn::parse2::<keywords::proc_macro_attribute>(path.clone()).is_ok() {
+                Some(ProcMacroType::Attribute)
+            } else if syn::parse2::<keywords::proc_macro_derive>(path).is_ok() {
+                Some(ProcMacroType::Derive)
+            } else {
+                None
+            }
+        }) else {
+            return Err(Error::new(
+                proc_fn.sig.ident.span(),
+                "can only be attached to a proc macro function definition",
+            ));
+        };
+
+        // tokens_ident
+        let expected_args = match macro_type {
+            ProcMacroType::Attribute => 2,
+            ProcMacroType::Normal | ProcMacroType::Derive => 1,
+        };
+        if proc_fn.sig.inputs.len() != expected_args {
+            return Err(Error::new(
+                proc_fn.sig.span(),
+                format!(
+                    "a {} function must take exactly {} argument(s)",
+                    macro_type.to_str(),
+                    expected_args
+                ),
+            ));
+        }
+        let Some(tokens_arg) = proc_fn.sig.inputs.last() else {
+            unreachable!("argument count was checked above");
+        };
+        let tokens_ident = parse_proc_macro_arg_ident(tokens_arg)?;
+
+        // attr_ident (if applicable)
+        let attr_ident = match macro_type {
+            ProcMacroType::Attribute => {
+                let Some(attr_arg) = proc_fn.sig.inputs.first() else {
+                    unreachable!("argument count was checked above");
+                };
+                Some(parse_proc_macro_arg_ident(attr_arg)?)
+            }
+            _ => None,
+        };
+        Ok(ProcMacro {
+            proc_fn,
+            macro_type,
+            tokens_ident,
+            attr_ident,
+        })
+    }
+}
+
+/// Extracts the [`struct@Ident`] of a proc macro function argument, erroring if the argument
+/// is a receiver (i.e. `self`) or uses a pattern other than a plain identifier.
+fn parse_proc_macro_arg_ident(arg: &FnArg) -> Result<Ident> {
+    let FnArg::Typed(typed_arg) = arg else {
+        return Err(Error::new(
+            arg.span(),
+            "proc macro functions cannot take a `self` argument",
+        ));
     };
-    let mut hidden_path: Path = orig_stmt.path.clone();
-    hidden_path.segments.last_mut().unwrap().ident = hidden_ident;
-    Ok(quote! {
-        #(#orig_attrs)
-        *
-        #vis use #orig_path;
-        #[doc(hidden)]
-        #vis use #hidden_path;
+    let Pat::Ident(pat_ident) = &*typed_arg.pat else {
+        return Err(Error::new(
+            typed_arg.pat.span(),
+            "expected a plain identifier for this proc macro argument",
+        ));
+    };
+    Ok(pat_ident.ident.clone())
+}
+
+/// Parses a proc macro function from a `TokenStream2` expecting only the specified `macro_type`
+pub fn parse_proc_macro_variant<T: Into<TokenStream2>>(
+    tokens: T,
+    macro_type: ProcMacroType,
+) -> Result<ProcMacro> {
+    let proc_macro = ProcMacro::from(tokens.into())?;
+    if proc_macro.macro_type != macro_type {
+        let actual = proc_macro.macro_type.to_str();
+        let desired = macro_type.to_str();
+        return Err(Error::new(
+            proc_macro.proc_fn.sig.ident.span(),
+            format!(
+                "expected a function definition with {} but found {} instead",
+                desired, actual
+            ),
+        ));
+    }
+    Ok(proc_macro)
+}
+
+/// Like [`parse_proc_macro_variant`], but used by [`import_tokens_attr_internal`] and
+/// [`import_tokens_proc_internal`] to suggest the other importer when it is attached to the
+/// wrong kind of proc macro.
+fn parse_importer_proc_macro_variant<T: Into<TokenStream2>>(
+    tokens: T,
+    macro_type: ProcMacroType,
+) -> Result<ProcMacro> {
+    let proc_macro = ProcMacro::from(tokens.into())?;
+    let suggestion = match (macro_type, proc_macro.macro_type) {
+        (ProcMacroType::Attribute, ProcMacroType::Normal) => "import_tokens_proc",
+        (ProcMacroType::Normal, ProcMacroType::Attribute) => "import_tokens_attr",
+        (desired, actual) if desired == actual => return Ok(proc_macro),
+        (desired, actual) => {
+            return Err(Error::new(
+                proc_macro.proc_fn.sig.ident.span(),
+                format!(
+                    "expected a function definition with {} but found {} instead",
+                    desired.to_str(),
+                    actual.to_str()
+                ),
+            ))
+        }
+    };
+    Err(Error::new(
+        proc_macro.proc_fn.sig.ident.span(),
+        format!(
+            "expected a function definition with {} but found {} instead, \
+            use `#[{}]` to import tokens into {} functions",
+            macro_type.to_str(),
+            proc_macro.macro_type.to_str(),
+            suggestion,
+            proc_macro.macro_type.to_str()
+        ),
+    ))
+}
+
+/// Convenience function that will pretty-print anything compatible with [`TokenStream2`]
+/// including [`TokenStream2`], `TokenStream`, and all [`syn`] items.
+///
+/// Uses the `prettyplease` crate. Only built if the `pretty_print` feature is enabled.
+#[cfg(feature = "pretty_print")]
+pub fn pretty_print<T: Into<TokenStream2> + Clone>(tokens: &T) {
+    pretty_print_with(tokens, Formatter::Prettyplease);
+}
+
+/// The backends that can be used by [`pretty_print_with`] and [`pretty_format_with`].
+#[cfg(feature = "pretty_print")]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Formatter {
+    /// Formats using the `prettyplease` crate.
+    Prettyplease,
+    /// Formats by piping the tokens through the `rustfmt` executable, which picks up any
+    /// `rustfmt.toml` in the current directory. Falls back to [`Formatter::Prettyplease`] if
+    /// `rustfmt` can't be run or fails. Only available with the `rustfmt` feature, which
+    /// requires `std`.
+    #[cfg(feature = "rustfmt")]
+    Rustfmt,
+}
+
+/// Like [`pretty_print`], but allows choosing the [`Formatter`] backend to use.
+#[cfg(feature = "pretty_print")]
+pub fn pretty_print_with<T: Into<TokenStream2> + Clone>(tokens: &T, formatter: Formatter) {
+    println!("\n\n{}\n\n", pretty_format_with(tokens, formatter));
+}
+
+/// Formats anything compatible with [`TokenStream2`] as a [`String`] using the specified
+/// [`Formatter`] backend. This is what [`pretty_print_with`] prints.
+///
+/// Tokens that do not form a valid source file (e.g. a bare expression) cannot be formatted, so
+/// they are returned unformatted instead.
+#[cfg(feature = "pretty_print")]
+pub fn pretty_format_with<T: Into<TokenStream2> + Clone>(
+    tokens: &T,
+    formatter: Formatter,
+) -> String {
+    let source = (*tokens).clone().into().to_string();
+    let prettyplease_format = |source: &str| match syn::parse_file(source) {
+        Ok(file) => prettyplease::unparse(&file),
+        Err(_) => source.to_string(),
+    };
+    match formatter {
+        Formatter::Prettyplease => prettyplease_format(source.as_str()),
+        #[cfg(feature = "rustfmt")]
+        Formatter::Rustfmt => {
+            rustfmt_format(source.as_str()).unwrap_or_else(|| prettyplease_format(source.as_str()))
+        }
+    }
+}
+
+/// Formats the specified source code using the `rustfmt` executable, returning [`None`] if
+/// `rustfmt` could not be run or did not succeed.
+#[cfg(feature = "rustfmt")]
+fn rustfmt_format(source: &str) -> Option<String> {
+    use std::{
+        io::Write,
+        process::{Command, Stdio},
+    };
+    let mut child = Command::new("rustfmt")
+        .args(["--emit", "stdout", "--edition", "2021"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    child.stdin.take()?.write_all(source.as_bytes()).ok()?;
+    let output = child.wait_with_output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
+
+/// The segments of [`MACRO_MAGIC_ROOT`], parsed once by [`macro_magic_root`].
+///
+/// A [`Path`] can't be cached directly, since its [`proc_macro2`] tokens are neither `Send`
+/// nor `Sync`, so only the idents are kept and the path is rebuilt from them on each call.
+struct RootSegments {
+    leading_colon: bool,
+    idents: Vec<String>,
+}
+
+static ROOT_SEGMENTS: once_cell::race::OnceBox<RootSegments> = once_cell::race::OnceBox::new();
+
+/// Safely access the `macro_magic` root based on the `MACRO_MAGIC_ROOT` env var, which
+/// defaults to `::macro_magic`, but can be configured via the `[env]` section of
+/// `.cargo/config.toml`
+///
+/// The root is only parsed the first time this is called; later calls rebuild the [`Path`]
+/// from the memoized segments, with [`Span::call_site`] spans.
+pub fn macro_magic_root() -> Path {
+    let root = ROOT_SEGMENTS.get_or_init(|| {
+        let path = parse2::<Path>(
+            MACRO_MAGIC_ROOT
+                .parse::<TokenStream2>()
+                .expect("environment var `MACRO_MAGIC_ROOT` must parse to a valid TokenStream2"),
+        )
+        .expect("environment variable `MACRO_MAGIC_ROOT` must parse to a valid syn::Path");
+        let idents = path
+            .segments
+            .iter()
+            .map(|segment| {
+                assert!(
+                    segment.arguments.is_none(),
+                    "environment variable `MACRO_MAGIC_ROOT` must not contain generic arguments"
+                );
+                segment.ident.to_string()
+            })
+            .collect();
+        alloc::boxed::Box::new(RootSegments {
+            leading_colon: path.leading_colon.is_some(),
+            idents,
+        })
+    });
+    Path {
+        leading_colon: root.leading_colon.then(Default::default),
+        segments: root
+            .idents
+            .iter()
+            .map(|ident| syn::PathSegment::from(format_ident!("{}", ident)))
+            .collect(),
+    }
+}
+
+/// Safely access a subpath of `macro_magic::__private`
+pub fn private_path<T: Into<TokenStream2> + Clone>(subpath: &T) -> Path {
+    private_path_in(&macro_magic_root(), subpath)
+}
+
+/// Like [`private_path`], but relative to an already resolved `macro_magic` `root`.
+pub fn private_path_in<T: Into<TokenStream2> + Clone>(root: &Path, subpath: &T) -> Path {
+    let subpath = subpath.clone().into();
+    parse_quote!(#root::__private::#subpath)
+}
+
+/// Safely access a subpath of `macro_magic`
+pub fn macro_magic_path<T: Into<TokenStream2> + Clone>(subpath: &T) -> Path {
+    macro_magic_path_in(&macro_magic_root(), subpath)
+}
+
+/// Like [`macro_magic_path`], but relative to an already resolved `macro_magic` `root`.
+pub fn macro_magic_path_in<T: Into<TokenStream2> + Clone>(root: &Path, subpath: &T) -> Path {
+    let subpath = subpath.clone().into();
+    parse_quote! {
+        #root::#subpath
+    }
+}
+
+/// Generates tokens for a `compile_error!` invocation reporting `msg` at `span`.
+///
+/// Shorthand for `syn::Error::new(span, msg).to_compile_error()`, handy when a generator wants
+/// to emit an error in place of its output rather than returning a [`Result`]. The proc macros
+/// generated by [`import_tokens_attr_internal`] and [`import_tokens_derive_internal`] use it
+/// to report errors at expansion time.
+pub fn compile_error(span: Span, msg: &str) -> TokenStream2 {
+    Error::new(span, msg).to_compile_error()
+}
+
+/// Flattens `tokens` into a list of normalized tokens for [`tokens_first_difference`] and
+/// [`tokens_hash_normalized`].
+///
+/// The [`Spacing`] of punctuation is ignored (since it merely reflects whether the next
+/// character was whitespace), and the contents of invisible (`Delimiter::None`) groups are
+/// inlined, so that the result only depends on the tokens themselves rather than on their
+/// spans or on the whitespace between them.
+fn normalize_tokens(tokens: TokenStream2, normalized: &mut Vec<String>) {
+    for token in tokens {
+        match token {
+            TokenTree::Punct(punct) => normalized.push(punct.as_char().to_string()),
+            TokenTree::Group(group) => {
+                let (open, close) = match group.delimiter() {
+                    Delimiter::Parenthesis => ("(", ")"),
+                    Delimiter::Brace => ("{", "}"),
+                    Delimiter::Bracket => ("[", "]"),
+                    Delimiter::None => ("", ""),
+                };
+                if !open.is_empty() {
+                    normalized.push(open.into());
+                }
+                normalize_tokens(group.stream(), normalized);
+                if !close.is_empty() {
+                    normalized.push(close.into());
+                }
+            }
+            TokenTree::Ident(ident) => normalized.push(ident.to_string()),
+            TokenTree::Literal(lit) => normalized.push(lit.to_string()),
+        }
+    }
+}
+
+/// Compares two token streams token by token, ignoring spans and whitespace, and describes the
+/// first difference, or returns [`None`] if they are equal.
+///
+/// Used by [`tokens_eq_ignoring_spans`] and [`assert_token_streams_eq!`].
+pub fn tokens_first_difference(a: &TokenStream2, b: &TokenStream2) -> Option<String> {
+    let mut a_tokens = Vec::new();
+    normalize_tokens(a.clone(), &mut a_tokens);
+    let mut b_tokens = Vec::new();
+    normalize_tokens(b.clone(), &mut b_tokens);
+    let len = a_tokens.len().max(b_tokens.len());
+    (0..len).find_map(|i| match (a_tokens.get(i), b_tokens.get(i)) {
+        (Some(a), Some(b)) if a == b => None,
+        (Some(a), Some(b)) => Some(format!("token {} differs: `{}` != `{}`", i, a, b)),
+        (Some(a), None) => Some(format!("token {} (`{}`) is missing on the right", i, a)),
+        (None, Some(b)) => Some(format!("token {} (`{}`) is missing on the left", i, b)),
+        (None, None) => None,
     })
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Returns `true` if the two token streams consist of the same tokens, regardless of their
+/// spans and of the whitespace in their string representations.
+pub fn tokens_eq_ignoring_spans(a: &TokenStream2, b: &TokenStream2) -> bool {
+    tokens_first_difference(a, b).is_none()
+}
 
-    #[test]
-    fn export_tokens_internal_missing_ident() {
-        assert!(
-            export_tokens_internal(quote!(), quote!(impl MyTrait for Something), true).is_err()
+/// Asserts that two token streams are equal ignoring spans and whitespace (see
+/// [`tokens_eq_ignoring_spans`]), reporting the first differing token on failure.
+///
+/// ```ignore
+/// assert_token_streams_eq!(generated, quote!(struct Foo;));
+/// ```
+#[macro_export]
+macro_rules! assert_token_streams_eq {
+    ($left:expr, $right:expr $(,)?) => {{
+        let left: $crate::__TokenStream2 = $left.into();
+        let right: $crate::__TokenStream2 = $right.into();
+        if let Some(difference) = $crate::tokens_first_difference(&left, &right) {
+            panic!(
+                "token streams are not equal: {}\n  left: `{}`\n right: `{}`",
+                difference, left, right
+            );
+        }
+    }};
+}
+
+#[doc(hidden)]
+pub use proc_macro2::TokenStream as __TokenStream2;
+
+/// Returns the specified string in snake_case
+pub fn to_snake_case(input: impl Into<String>) -> String {
+    let input: String = input.into();
+    if input.is_empty() {
+        return input;
+    }
+    let mut prev_lower = input.chars().next().unwrap().is_lowercase();
+    let mut prev_whitespace = true;
+    let mut first = true;
+    let mut output: Vec<char> = Vec::new();
+    for c in input.chars() {
+        if c == '_' {
+            prev_whitespace = true;
+            output.push('_');
+            continue;
+        }
+        if !c.is_ascii_alphanumeric() && c != '_' && !c.is_whitespace() {
+            continue;
+        }
+        if !first && c.is_whitespace() || c == '_' {
+            if !prev_whitespace {
+                output.push('_');
+            }
+            prev_whitespace = true;
+        } else {
+            let current_lower = c.is_lowercase();
+            if ((prev_lower != current_lower && prev_lower)
+                || (prev_lower == current_lower && !prev_lower))
+                && !first
+                && !prev_whitespace
+            {
+                output.push('_');
+            }
+            output.push(c.to_ascii_lowercase());
+            prev_lower = current_lower;
+            prev_whitespace = false;
+        }
+        first = false;
+    }
+    output.iter().collect::<String>()
+}
+
+/// Returns the specified string in SHOUTY_SNAKE_CASE, i.e. [`to_snake_case`] converted to
+/// ASCII uppercase. Like [`to_snake_case`], the output only ever contains ASCII alphanumeric
+/// characters and underscores.
+pub fn to_shouty_snake_case(input: impl Into<String>) -> String {
+    to_snake_case(input).to_ascii_uppercase()
+}
+
+/// Converts a string-like value (via [`Display`]) such that the sequence `~~` is safely escaped
+/// so that `~~` can be used as a list delimiter.
+///
+/// Every `~` is escaped (as `\-`), rather than just occurrences of `~~`, so that a value
+/// ending in `~` can't run into an adjacent delimiter. Backslashes are escaped as `\\`.
+///
+/// Used by [`forward_tokens_internal`] to escape items appearing in the `extra` variable.
+pub fn escape_extra<T: Display>(extra: T) -> String {
+    let extra = extra.to_string();
+    let mut escaped = String::with_capacity(extra.len());
+    for c in extra.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '~' => escaped.push_str("\\-"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Unescapes a `String` that has been escaped via [`escape_extra`].
+///
+/// Used by [`forward_tokens_internal`] to unescape items appearing in the `extra` variable.
+pub fn unescape_extra<T: Display>(extra: T) -> String {
+    let extra = extra.to_string();
+    let mut unescaped = String::with_capacity(extra.len());
+    let mut chars = extra.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => unescaped.push('\\'),
+            Some('-') => unescaped.push('~'),
+            Some(other) => {
+                unescaped.push('\\');
+                unescaped.push(other);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// Packs several string-like values (via [`Display`]) into a single string, escaping each
+/// with [`escape_extra`] and joining them with the `~~` delimiter.
+///
+/// The inverse of [`unpack_extra`]. Note that packing no values at all produces the same
+/// (empty) string as packing a single empty value.
+pub fn pack_extras<I: IntoIterator<Item = T>, T: Display>(fields: I) -> String {
+    fields
+        .into_iter()
+        .map(escape_extra)
+        .collect::<Vec<_>>()
+        .join("~~")
+}
+
+/// The error returned by [`unpack_extra`] and [`unpack_extra_exact`] when the packed `extra`
+/// string is malformed. Byte indices refer to the packed string.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExtraParseError {
+    /// A `\` that isn't followed by another `\` or a `-`, as produced by [`escape_extra`]
+    InvalidEscape { index: usize },
+    /// A `~` that isn't part of a `~~` delimiter (every `~` within a field is escaped)
+    UnescapedTilde { index: usize },
+    /// The packed string doesn't consist of the expected number of fields
+    FieldCount { expected: usize, found: usize },
+}
+
+impl Display for ExtraParseError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ExtraParseError::InvalidEscape { index } => {
+                write!(f, "invalid escape sequence at byte {}", index)
+            }
+            ExtraParseError::UnescapedTilde { index } => {
+                write!(f, "unescaped `~` at byte {}", index)
+            }
+            ExtraParseError::FieldCount { expected, found } => {
+                write!(f, "expected {} fields but found {}", expected, found)
+            }
+        }
+    }
+}
+
+/// Strictly splits a string packed by [`pack_extras`] on the unescaped `~~` delimiters and
+/// unescapes each field, returning the original values in order.
+///
+/// Input that [`pack_extras`] could never have produced (such as a stray `~` or an unknown
+/// escape sequence) results in an [`ExtraParseError`].
+pub fn unpack_extra(packed: &str) -> core::result::Result<Vec<String>, ExtraParseError> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = packed.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some((_, '\\')) => field.push('\\'),
+                Some((_, '-')) => field.push('~'),
+                _ => return Err(ExtraParseError::InvalidEscape { index }),
+            },
+            '~' => match chars.next() {
+                Some((_, '~')) => fields.push(core::mem::take(&mut field)),
+                _ => return Err(ExtraParseError::UnescapedTilde { index }),
+            },
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    Ok(fields)
+}
+
+/// Like [`unpack_extra`], but additionally requires the packed string to consist of exactly
+/// `count` fields, returning [`ExtraParseError::FieldCount`] otherwise.
+pub fn unpack_extra_exact(
+    packed: &str,
+    count: usize,
+) -> core::result::Result<Vec<String>, ExtraParseError> {
+    let fields = unpack_extra(packed)?;
+    if fields.len() != count {
+        return Err(ExtraParseError::FieldCount {
+            expected: count,
+            found: fields.len(),
+        });
+    }
+    Ok(fields)
+}
+
+/// Computes the 32-bit FNV-1a hash of a string-like value (via [`Display`]).
+///
+/// This is a small, dependency-free, non-cryptographic hash that is stable across platforms.
+/// Note that the [`Display`] output of a [`TokenStream2`] (in particular its spacing) can
+/// differ between compiler versions, so use [`tokens_hash_normalized`] to hash tokens.
+pub fn tokens_hash<T: Display>(tokens: T) -> u32 {
+    fnv1a(FNV1A_OFFSET_BASIS, tokens.to_string().as_bytes())
+}
+
+/// Computes the [`tokens_hash`] of the normalized tokens of `tokens`, i.e. the text of each
+/// ident, punct and literal along with the delimiters of groups, ignoring spacing and spans.
+///
+/// Unlike hashing the stringified tokens, the result doesn't depend on how the compiler
+/// happens to render whitespace, making it suitable for detecting changes in exported tokens
+/// across compiler versions.
+pub fn tokens_hash_normalized(tokens: TokenStream2) -> u32 {
+    let mut normalized = Vec::new();
+    normalize_tokens(tokens, &mut normalized);
+    normalized.iter().fold(FNV1A_OFFSET_BASIS, |hash, token| {
+        // `0xff` never occurs in UTF-8, so it unambiguously separates tokens
+        fnv1a(fnv1a(hash, token.as_bytes()), &[0xff])
+    })
+}
+
+/// The initial state of the FNV-1a hash computed by [`fnv1a`].
+const FNV1A_OFFSET_BASIS: u32 = 0x811c9dc5;
+
+/// Feeds `bytes` into the 32-bit FNV-1a hash state `hash`.
+fn fnv1a(hash: u32, bytes: &[u8]) -> u32 {
+    bytes.iter().fold(hash, |hash, byte| {
+        (hash ^ *byte as u32).wrapping_mul(0x01000193)
+    })
+}
+
+/// Returns the short hash suffix used by `#[export_tokens(hash)]`, i.e. the lower 16 bits of
+/// the [`tokens_hash_normalized`] of the specified [`Item`], encoded as one letter (`a`-`p`)
+/// per hex nibble. Letters are used rather than hex digits so that the suffix survives the
+/// [`to_snake_case`] conversion applied to export idents unchanged.
+///
+/// The suffix only changes when the tokens of the item change, so importing an item under its
+/// hashed name (e.g. `import_tokens!(let tokens = widget_jdfk)`) fails to compile once the
+/// upstream item has been modified.
+pub fn export_tokens_hash_suffix(item: &Item) -> String {
+    let hash = tokens_hash_normalized(item.to_token_stream());
+    (0..4)
+        .rev()
+        .map(|i| (b'a' + ((hash >> (i * 4)) & 0xf) as u8) as char)
+        .collect()
+}
+
+/// Marks the start of a back-reference in strings produced by [`compress_tokens`]. A literal
+/// occurrence of this character is encoded by doubling it.
+const COMPRESSION_MARKER: char = '`';
+
+/// Digits used to encode back-reference offsets and lengths in [`compress_tokens`]. Two digits
+/// are used per number, so offsets and lengths are limited to `64 * 64 - 1`.
+const COMPRESSION_DIGITS: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// The maximum distance (in chars) a back-reference can reach back, and also the maximum length
+/// of a single back-reference.
+const COMPRESSION_WINDOW: usize = 64 * 64 - 1;
+
+/// Back-references cost 5 chars, so shorter matches are emitted as literals instead.
+const COMPRESSION_MIN_MATCH: usize = 6;
+
+/// Bounds how many earlier candidate positions are examined for each char of input.
+const COMPRESSION_MAX_CHAIN: usize = 64;
+
+fn push_compression_number(output: &mut String, n: usize) {
+    output.push(COMPRESSION_DIGITS[n / 64] as char);
+    output.push(COMPRESSION_DIGITS[n % 64] as char);
+}
+
+fn read_compression_number(chars: &mut impl Iterator<Item = char>) -> Option<usize> {
+    let mut n = 0;
+    for _ in 0..2 {
+        let c = chars.next()?;
+        let digit = COMPRESSION_DIGITS.iter().position(|d| *d as char == c)?;
+        n = n * 64 + digit;
+    }
+    Some(n)
+}
+
+/// Compresses a string-like value (via [`Display`]) using a small, dependency-free LZ77-style
+/// scheme. The output only introduces printable ASCII characters, so it can be embedded
+/// directly in a string literal.
+///
+/// Used by [`export_tokens_internal`] to store the tokens of exported items when the `compress`
+/// feature is enabled. The result can be restored using [`decompress_tokens`].
+pub fn compress_tokens<T: Display>(tokens: T) -> String {
+    let input: Vec<char> = tokens.to_string().chars().collect();
+    let hash = |i: usize| {
+        (input[i] as usize)
+            .wrapping_mul(31)
+            .wrapping_add(input[i + 1] as usize)
+            .wrapping_mul(31)
+            .wrapping_add(input[i + 2] as usize)
+            % 4096
+    };
+    // hash chains over 3-char prefixes so we don't have to scan the entire window
+    let mut heads: Vec<Option<usize>> = alloc::vec![None; 4096];
+    let mut prev: Vec<Option<usize>> = alloc::vec![None; input.len()];
+    let insert = |i: usize, heads: &mut Vec<Option<usize>>, prev: &mut Vec<Option<usize>>| {
+        if i + 2 < input.len() {
+            let h = hash(i);
+            prev[i] = heads[h];
+            heads[h] = Some(i);
+        }
+    };
+    let mut output = String::new();
+    let mut i = 0;
+    while i < input.len() {
+        let (mut best_len, mut best_offset) = (0, 0);
+        if i + 2 < input.len() {
+            let mut candidate = heads[hash(i)];
+            let mut chain = 0;
+            while let Some(start) = candidate {
+                if i - start > COMPRESSION_WINDOW || chain >= COMPRESSION_MAX_CHAIN {
+                    break;
+                }
+                let mut len = 0;
+                while len < COMPRESSION_WINDOW
+                    && i + len < input.len()
+                    && input[start + len] == input[i + len]
+                {
+                    len += 1;
+                }
+                if len > best_len {
+                    best_len = len;
+                    best_offset = i - start;
+                }
+                candidate = prev[start];
+                chain += 1;
+            }
+        }
+        if best_len >= COMPRESSION_MIN_MATCH {
+            output.push(COMPRESSION_MARKER);
+            push_compression_number(&mut output, best_offset);
+            push_compression_number(&mut output, best_len);
+            for j in i..i + best_len {
+                insert(j, &mut heads, &mut prev);
+            }
+            i += best_len;
+        } else {
+            if input[i] == COMPRESSION_MARKER {
+                output.push(COMPRESSION_MARKER);
+            }
+            output.push(input[i]);
+            insert(i, &mut heads, &mut prev);
+            i += 1;
+        }
+    }
+    output
+}
+
+/// Restores a string that was compressed via [`compress_tokens`].
+///
+/// Returns an error if `compressed` is not a valid [`compress_tokens`] encoding.
+pub fn decompress_tokens<T: Display>(compressed: T) -> Result<String> {
+    let invalid = || Error::new(Span::call_site(), "invalid compressed tokens");
+    let compressed = compressed.to_string();
+    let mut chars = compressed.chars();
+    let mut output: Vec<char> = Vec::new();
+    while let Some(c) = chars.next() {
+        if c != COMPRESSION_MARKER {
+            output.push(c);
+            continue;
+        }
+        match chars.clone().next() {
+            Some(COMPRESSION_MARKER) => {
+                chars.next();
+                output.push(COMPRESSION_MARKER);
+            }
+            _ => {
+                let offset = read_compression_number(&mut chars).ok_or_else(invalid)?;
+                let len = read_compression_number(&mut chars).ok_or_else(invalid)?;
+                if offset == 0 || offset > output.len() {
+                    return Err(invalid());
+                }
+                for _ in 0..len {
+                    output.push(output[output.len() - offset]);
+                }
+            }
+        }
+    }
+    Ok(output.iter().collect())
+}
+
+/// Parses an [`Item`] as delivered by the callback `macro_rules!` generated by
+/// [`export_tokens_internal`], transparently decompressing it first if it was exported with the
+/// `compress` feature enabled (in which case it is delivered as a [`struct@LitStr`]).
+///
+/// Note that decompressed items lose their original spans.
+pub fn parse_exported_item(input: ParseStream) -> Result<Item> {
+    if !input.peek(LitStr) {
+        return input.parse();
+    }
+    let compressed = input.parse::<LitStr>()?;
+    let decompressed = decompress_tokens(compressed.value())
+        .map_err(|err| Error::new(compressed.span(), err.to_string()))?;
+    let tokens = decompressed
+        .parse::<TokenStream2>()
+        .map_err(|err| Error::new(compressed.span(), err.to_string()))?;
+    parse2::<Item>(tokens)
+}
+
+/// Like [`parse_exported_item`], but consumes the remainder of `input` as a raw
+/// [`TokenStream2`] instead of parsing it as an [`Item`], so the tokens (and their spans) are
+/// passed along exactly as they were stored.
+pub fn parse_exported_tokens(input: ParseStream) -> Result<TokenStream2> {
+    if !input.peek(LitStr) {
+        return input.parse();
+    }
+    let compressed = input.parse::<LitStr>()?;
+    let decompressed = decompress_tokens(compressed.value())
+        .map_err(|err| Error::new(compressed.span(), err.to_string()))?;
+    decompressed
+        .parse::<TokenStream2>()
+        .map_err(|err| Error::new(compressed.span(), err.to_string()))
+}
+
+/// Parses [`Generics`] as imported by `import_tokens_generics!`, i.e. the generic parameters
+/// followed by the optional where clause.
+///
+/// [`Generics`]'s own [`Parse`](syn::parse::Parse) implementation only parses the parameters,
+/// so this should be used when the imported generics may contain a where clause.
+pub fn parse_generics(input: ParseStream) -> Result<Generics> {
+    let mut generics = input.parse::<Generics>()?;
+    generics.where_clause = input.parse()?;
+    Ok(generics)
+}
+
+/// "Flattens" an [`struct@Ident`] by converting it to snake case.
+///
+/// Used by [`export_tokens_macro_ident`].
+pub fn flatten_ident(ident: &Ident) -> Ident {
+    Ident::new(to_snake_case(ident.to_string()).as_str(), ident.span())
+}
+
+/// Produces the full path for the auto-generated callback-based decl macro that allows us to
+/// forward tokens across crate boundaries.
+///
+/// Used by [`export_tokens_internal`] and several other functions.
+pub fn export_tokens_macro_ident(ident: &Ident) -> Ident {
+    export_tokens_macro_ident_with_prefix(ident, MACRO_MAGIC_EXPORT_PREFIX)
+}
+
+/// Like [`export_tokens_macro_ident`], but uses the specified `prefix` instead of
+/// [`MACRO_MAGIC_EXPORT_PREFIX`].
+pub fn export_tokens_macro_ident_with_prefix(ident: &Ident, prefix: &str) -> Ident {
+    let ident = flatten_ident(ident);
+    let ident_string = format!("{}{}", prefix, ident.to_token_stream());
+    Ident::new(ident_string.as_str(), Span::call_site())
+}
+
+/// Reverses [`export_tokens_macro_ident`] as far as possible, returning the export name
+/// encoded in the specified callback macro [`struct@Ident`], or [`None`] if the ident doesn't
+/// start with [`MACRO_MAGIC_EXPORT_PREFIX`].
+///
+/// Note that since [`flatten_ident`] is lossy, the result is the _flattened_ (snake_case) form
+/// of the export name rather than its original casing, i.e. both `SomeThing` and `some_thing`
+/// are recovered as `some_thing`.
+pub fn parse_export_tokens_macro_ident(ident: &Ident) -> Option<String> {
+    parse_export_tokens_macro_ident_with_prefix(ident, MACRO_MAGIC_EXPORT_PREFIX)
+}
+
+/// Like [`parse_export_tokens_macro_ident`], but expects the specified `prefix` instead of
+/// [`MACRO_MAGIC_EXPORT_PREFIX`].
+pub fn parse_export_tokens_macro_ident_with_prefix(ident: &Ident, prefix: &str) -> Option<String> {
+    let ident_string = ident.to_string();
+    match ident_string.strip_prefix(prefix) {
+        Some(name) if !name.is_empty() => Some(name.into()),
+        _ => None,
+    }
+}
+
+/// Resolves the path of an exported item to the path of its `#[export_tokens]` callback macro.
+///
+/// Because the callback macro is `#[macro_export]`-ed, it always lives at the root of the
+/// exporting crate, so only the first (crate) segment and the flattened last segment of the
+/// path are kept.
+///
+/// Items are exported by ident alone, so generic arguments on the last segment (e.g. `Foo<T>`)
+/// are ignored and resolve to the base item (`Foo`). Generic arguments on any other segment
+/// (e.g. `Foo<T>::Bar`) can't be dropped without changing which item is referred to, so they
+/// result in an error.
+pub fn export_tokens_macro_path(path: &Path) -> Result<TokenStream2> {
+    export_tokens_macro_path_with_prefix(path, MACRO_MAGIC_EXPORT_PREFIX)
+}
+
+/// Like [`export_tokens_macro_path`], but uses the specified `prefix` instead of
+/// [`MACRO_MAGIC_EXPORT_PREFIX`].
+pub fn export_tokens_macro_path_with_prefix(path: &Path, prefix: &str) -> Result<TokenStream2> {
+    let Some(source_ident_seg) = path.segments.last() else {
+        return Err(Error::new(
+            path.span(),
+            "expected a path with at least one segment",
+        ));
+    };
+    if let Some(seg) = path
+        .segments
+        .iter()
+        .take(path.segments.len() - 1)
+        .find(|seg| !seg.arguments.is_none())
+    {
+        return Err(Error::new(
+            seg.arguments.span(),
+            format!(
+                "generic arguments `{}` on `{}` cannot be used in the path of an exported \
+                item, only the last segment of the path may specify generic arguments",
+                seg.arguments.to_token_stream(),
+                seg.ident
+            ),
+        ));
+    }
+    let source_ident_seg = export_tokens_macro_ident_with_prefix(&source_ident_seg.ident, prefix);
+    if path.segments.len() > 1 {
+        let Some(crate_seg) = path.segments.first() else {
+            unreachable!("path has at least two segments, so there is a first segment");
+        };
+        let crate_ident = &crate_seg.ident;
+        Ok(quote!(#crate_ident::#source_ident_seg))
+    } else {
+        Ok(quote!(#source_ident_seg))
+    }
+}
+
+/// Produces the [`struct@Ident`] of the `const` emitted alongside exported items by
+/// [`export_tokens_internal`] when the `export_const` option is specified, i.e.
+/// `__EXPORT_TOKENS__` followed by the export name in SHOUTY_SNAKE_CASE (see
+/// [`to_shouty_snake_case`]), with any `r#` raw prefix removed.
+pub fn export_tokens_const_ident(ident: &Ident) -> Ident {
+    use syn::ext::IdentExt;
+    format_ident!(
+        "__EXPORT_TOKENS__{}",
+        to_shouty_snake_case(ident.unraw().to_string())
+    )
+}
+
+/// Produces the [`struct@Ident`] of the `const fn` emitted alongside exported items by
+/// [`export_tokens_internal`] when the `const_fn` option is specified, i.e. the export name in
+/// snake_case followed by `_tokens_str`, with any `r#` raw prefix removed.
+pub fn export_tokens_const_fn_ident(ident: &Ident) -> Ident {
+    use syn::ext::IdentExt;
+    format_ident!("{}_tokens_str", to_snake_case(ident.unraw().to_string()))
+}
+
+/// Returns the inherent [`struct@Ident`] of the specified [`Item`], or [`None`] for items that
+/// don't have one ([`syn::ItemForeignMod`], [`syn::ItemUse`], [`syn::ItemImpl`],
+/// [`Item::Verbatim`] and unnamed `macro_rules!`).
+pub fn item_ident(item: &Item) -> Option<Ident> {
+    match item {
+        Item::Const(item_const) => Some(item_const.ident.clone()),
+        Item::Enum(item_enum) => Some(item_enum.ident.clone()),
+        Item::ExternCrate(item_extern_crate) => Some(item_extern_crate.ident.clone()),
+        Item::Fn(item_fn) => Some(item_fn.sig.ident.clone()),
+        Item::Macro(item_macro) => item_macro.ident.clone(), // note this one might not have an Ident as well
+        Item::Mod(item_mod) => Some(item_mod.ident.clone()),
+        Item::Static(item_static) => Some(item_static.ident.clone()),
+        Item::Struct(item_struct) => Some(item_struct.ident.clone()),
+        Item::Trait(item_trait) => Some(item_trait.ident.clone()),
+        Item::TraitAlias(item_trait_alias) => Some(item_trait_alias.ident.clone()),
+        Item::Type(item_type) => Some(item_type.ident.clone()),
+        Item::Union(item_union) => Some(item_union.ident.clone()),
+        _ => None,
+    }
+}
+
+/// Like [`item_ident`], but returns a mutable reference to the inherent [`struct@Ident`] of the
+/// specified [`Item`], so that the item can be renamed.
+pub fn item_ident_mut(item: &mut Item) -> Option<&mut Ident> {
+    match item {
+        Item::Const(item_const) => Some(&mut item_const.ident),
+        Item::Enum(item_enum) => Some(&mut item_enum.ident),
+        Item::ExternCrate(item_extern_crate) => Some(&mut item_extern_crate.ident),
+        Item::Fn(item_fn) => Some(&mut item_fn.sig.ident),
+        Item::Macro(item_macro) => item_macro.ident.as_mut(),
+        Item::Mod(item_mod) => Some(&mut item_mod.ident),
+        Item::Static(item_static) => Some(&mut item_static.ident),
+        Item::Struct(item_struct) => Some(&mut item_struct.ident),
+        Item::Trait(item_trait) => Some(&mut item_trait.ident),
+        Item::TraitAlias(item_trait_alias) => Some(&mut item_trait_alias.ident),
+        Item::Type(item_type) => Some(&mut item_type.ident),
+        Item::Union(item_union) => Some(&mut item_union.ident),
+        _ => None,
+    }
+}
+
+/// Returns the kinds of [`Item`] (as named by [`item_kind`]) that have an inherent
+/// [`struct@Ident`] (see [`item_ident`]) and so can be exported without specifying an explicit
+/// name. Note that of the `"macro"` kind only `macro_rules!` definitions are supported, since
+/// other macro invocations are unnamed.
+pub fn supported_export_item_kinds() -> &'static [&'static str] {
+    &[
+        "const",
+        "enum",
+        "extern crate",
+        "fn",
+        "macro",
+        "mod",
+        "static",
+        "struct",
+        "trait",
+        "trait alias",
+        "type",
+        "union",
+    ]
+}
+
+/// Returns a short, human-readable name for the kind of the specified [`Item`], such as
+/// `"struct"`, `"fn"` or `"foreign mod"`.
+pub fn item_kind(item: &Item) -> &'static str {
+    match item {
+        Item::Const(_) => "const",
+        Item::Enum(_) => "enum",
+        Item::ExternCrate(_) => "extern crate",
+        Item::Fn(_) => "fn",
+        Item::ForeignMod(_) => "foreign mod",
+        Item::Impl(_) => "impl",
+        Item::Macro(_) => "macro",
+        Item::Mod(_) => "mod",
+        Item::Static(_) => "static",
+        Item::Struct(_) => "struct",
+        Item::Trait(_) => "trait",
+        Item::TraitAlias(_) => "trait alias",
+        Item::Type(_) => "type",
+        Item::Union(_) => "union",
+        Item::Use(_) => "use",
+        _ => "verbatim",
+    }
+}
+
+/// Returns the name of the `ExportedKind` variant (defined in the main `macro_magic` crate)
+/// corresponding to the kind of the specified [`Item`], such as `"Struct"` or `"ForeignMod"`.
+/// This is the [`item_kind`] of the item in PascalCase.
+pub fn item_kind_variant(item: &Item) -> &'static str {
+    match item {
+        Item::Const(_) => "Const",
+        Item::Enum(_) => "Enum",
+        Item::ExternCrate(_) => "ExternCrate",
+        Item::Fn(_) => "Fn",
+        Item::ForeignMod(_) => "ForeignMod",
+        Item::Impl(_) => "Impl",
+        Item::Macro(_) => "Macro",
+        Item::Mod(_) => "Mod",
+        Item::Static(_) => "Static",
+        Item::Struct(_) => "Struct",
+        Item::Trait(_) => "Trait",
+        Item::TraitAlias(_) => "TraitAlias",
+        Item::Type(_) => "Type",
+        Item::Union(_) => "Union",
+        Item::Use(_) => "Use",
+        _ => "Verbatim",
+    }
+}
+
+/// Returns the attributes of the specified [`Item`], including any inner attributes (which
+/// [`syn`] stores alongside the outer ones for modules, fns, impls and `extern` blocks).
+fn item_attrs(item: &Item) -> &[Attribute] {
+    match item {
+        Item::Const(item) => &item.attrs,
+        Item::Enum(item) => &item.attrs,
+        Item::ExternCrate(item) => &item.attrs,
+        Item::Fn(item) => &item.attrs,
+        Item::ForeignMod(item) => &item.attrs,
+        Item::Impl(item) => &item.attrs,
+        Item::Macro(item) => &item.attrs,
+        Item::Mod(item) => &item.attrs,
+        Item::Static(item) => &item.attrs,
+        Item::Struct(item) => &item.attrs,
+        Item::Trait(item) => &item.attrs,
+        Item::TraitAlias(item) => &item.attrs,
+        Item::Type(item) => &item.attrs,
+        Item::Union(item) => &item.attrs,
+        Item::Use(item) => &item.attrs,
+        _ => &[],
+    }
+}
+
+/// Returns the [`Generics`] of the specified [`Item`], or [`None`] for items that can't have
+/// any (such as `use` statements).
+fn item_generics(item: &Item) -> Option<&Generics> {
+    match item {
+        Item::Enum(item) => Some(&item.generics),
+        Item::Fn(item) => Some(&item.sig.generics),
+        Item::Impl(item) => Some(&item.generics),
+        Item::Struct(item) => Some(&item.generics),
+        Item::Trait(item) => Some(&item.generics),
+        Item::TraitAlias(item) => Some(&item.generics),
+        Item::Type(item) => Some(&item.generics),
+        Item::Union(item) => Some(&item.generics),
+        _ => None,
+    }
+}
+
+/// Returns a mutable reference to the [`Visibility`] of the specified [`Item`], or [`None`]
+/// for items that don't have one (such as `impl` blocks).
+fn item_visibility_mut(item: &mut Item) -> Option<&mut Visibility> {
+    match item {
+        Item::Const(item) => Some(&mut item.vis),
+        Item::Enum(item) => Some(&mut item.vis),
+        Item::ExternCrate(item) => Some(&mut item.vis),
+        Item::Fn(item) => Some(&mut item.vis),
+        Item::Mod(item) => Some(&mut item.vis),
+        Item::Static(item) => Some(&mut item.vis),
+        Item::Struct(item) => Some(&mut item.vis),
+        Item::Trait(item) => Some(&mut item.vis),
+        Item::TraitAlias(item) => Some(&mut item.vis),
+        Item::Type(item) => Some(&mut item.vis),
+        Item::Union(item) => Some(&mut item.vis),
+        Item::Use(item) => Some(&mut item.vis),
+        _ => None,
+    }
+}
+
+/// Returns the [`Visibility`] of the specified [`Item`], or [`Visibility::Inherited`] for
+/// items that don't have one (such as `impl` blocks).
+fn item_visibility(item: &Item) -> Visibility {
+    match item {
+        Item::Const(item) => item.vis.clone(),
+        Item::Enum(item) => item.vis.clone(),
+        Item::ExternCrate(item) => item.vis.clone(),
+        Item::Fn(item) => item.vis.clone(),
+        Item::Mod(item) => item.vis.clone(),
+        Item::Static(item) => item.vis.clone(),
+        Item::Struct(item) => item.vis.clone(),
+        Item::Trait(item) => item.vis.clone(),
+        Item::TraitAlias(item) => item.vis.clone(),
+        Item::Type(item) => item.vis.clone(),
+        Item::Union(item) => item.vis.clone(),
+        Item::Use(item) => item.vis.clone(),
+        _ => Visibility::Inherited,
+    }
+}
+
+/// The internal code behind the `#[export_tokens]` attribute macro.
+///
+/// The `attr` variable contains the tokens for the optional naming [`struct@Ident`] (necessary
+/// on [`Item`]s that don't have an inherent [`struct@Ident`]), and the `tokens` variable is
+/// the tokens for the [`Item`] the attribute macro can be attached to. The `attr` variable can
+/// be blank tokens for supported items, which include every valid [`syn::Item`] except for
+/// [`syn::ItemForeignMod`], [`syn::ItemUse`], [`syn::ItemImpl`], and [`Item::Verbatim`], which
+/// all require `attr` to be specified.
+///
+/// [`Item::Verbatim`] is what [`syn`] produces for items it can't fully model (such as a free
+/// function with no body). The raw tokens of such items are stored unchanged, so they are
+/// imported back exactly as they were written.
+///
+/// An empty [`TokenStream2`] is sufficient for opting out of using `attr`
+///
+/// When `emit` is true and the `export_const` option is specified, a `const` named after the
+/// export (see [`export_tokens_const_ident`]) containing the stringified tokens of the item is
+/// emitted alongside it, with the same visibility as the item.
+pub fn export_tokens_internal<T: Into<TokenStream2>, E: Into<TokenStream2>>(
+    attr: T,
+    tokens: E,
+    emit: bool,
+) -> Result<TokenStream2> {
+    let options = parse2::<ExportTokensArgs>(attr.into())?.into_options(emit);
+    export_tokens_with_options(tokens, options)
+}
+
+/// The internal implementation for the `export_tokens_file!` macro.
+///
+/// Emits each of the specified items exported via [`export_tokens_internal`] under its own
+/// name. Items with no inherent name (see [`item_ident`]), such as `impl` blocks and `use`
+/// statements, are emitted unchanged along with a warning pointing at them.
+pub fn export_tokens_file_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
+    let items = parse2::<ExportTokensFileArgs>(tokens.into())?.items;
+    let mut output = TokenStream2::new();
+    for item in items {
+        if item_ident(&item).is_some() {
+            output.extend(export_tokens_internal(
+                quote!(),
+                item.to_token_stream(),
+                true,
+            )?);
+            continue;
+        }
+        // stable proc macros can't emit warnings directly, so a deprecated marker is used
+        let note = format!(
+            "`export_tokens_file!` skipped this `{}` item because it has no inherent name, \
+            use `#[export_tokens(some_name)]` to export it",
+            item_kind(&item)
+        );
+        let marker = Ident::new("__ExportTokensFileSkipped", item.span());
+        output.extend(quote! {
+            #item
+            const _: () = {
+                #[deprecated(note = #note)]
+                struct __ExportTokensFileSkipped;
+                let _ = #marker;
+            };
+        });
+    }
+    Ok(output)
+}
+
+/// Returns the verbatim source text of the specified item tokens, including the original
+/// formatting and comments, if the compiler can provide it.
+///
+/// This requires joining the spans of the first and last tokens, which is currently only
+/// possible on nightly Rust. `#[export_tokens]` itself additionally falls back to reading the
+/// text out of the source file on stable Rust 1.88+, which requires `std`.
+pub fn verbatim_source_text(tokens: &TokenStream2) -> Option<String> {
+    let mut tokens = tokens.clone().into_iter();
+    let first = tokens.next()?.span();
+    let last = tokens.last().map_or(first, |token| token.span());
+    first.join(last)?.source_text()
+}
+
+/// Returns the size in bytes of the callback macro(s) that `#[export_tokens]` would generate
+/// for the specified `attr` and item `tokens` (see [`export_tokens_internal`]), as a rough
+/// measure of how much code an export adds to a crate.
+///
+/// Only the generated callback macros are measured. The item itself (and the `const` emitted
+/// alongside it) are excluded regardless of any `emit` option, since they would be present
+/// without `#[export_tokens]` as well. The size is that of the stringified tokens, so it
+/// doesn't depend on the formatting of the original source.
+pub fn estimate_export_size<T: Into<TokenStream2>, E: Into<TokenStream2>>(
+    attr: T,
+    tokens: E,
+) -> Result<usize> {
+    let options = parse2::<ExportTokensArgs>(attr.into())?
+        .into_options(false)
+        .emit(false);
+    Ok(export_tokens_with_options(tokens, options)?
+        .to_string()
+        .len())
+}
+
+/// Exports the tokens of the specified [`Item`] just like [`export_tokens_internal`], except
+/// the behavior is controlled by an [`ExportTokensOptions`] rather than by parsing `attr`.
+///
+/// This is the preferred entry point for external callers, since new options can be added to
+/// [`ExportTokensOptions`] without changing this signature.
+pub fn export_tokens_with_options<T: Into<TokenStream2>>(
+    tokens: T,
+    mut options: ExportTokensOptions,
+) -> Result<TokenStream2> {
+    let tokens = tokens.into();
+    let source_text = match (options.verbatim_source, options.source_text.take()) {
+        (true, Some(source_text)) => Some(source_text),
+        (true, None) => Some(verbatim_source_text(&tokens).unwrap_or_else(|| tokens.to_string())),
+        (false, _) => None,
+    };
+    let item = parse_attached_item(tokens)?;
+    export_item(item, source_text, options)
+}
+
+/// Generates the full `#[export_tokens]` output for an [`Item`] that has already been parsed
+/// or constructed in code, such as from a build script or a test harness, without having to
+/// go through a [`TokenStream2`] first.
+///
+/// The item is exported under `ident` if it is specified, and otherwise under the name set
+/// via [`ExportTokensOptions::name`], falling back to the name of the item itself. Since the
+/// item doesn't necessarily come from a source file, the source text stored by the
+/// `verbatim_source` option defaults to the stringified tokens of the item.
+///
+/// ```ignore
+/// let item: Item = parse_quote!(struct Foo;);
+/// let output = generate_export(None, item, ExportTokensOptions::new().emit(false))?;
+/// ```
+pub fn generate_export(
+    ident: Option<Ident>,
+    item: Item,
+    mut options: ExportTokensOptions,
+) -> Result<TokenStream2> {
+    if let Some(ident) = ident {
+        options = options.name(ident);
+    }
+    let source_text = match (options.verbatim_source, options.source_text.take()) {
+        (true, Some(source_text)) => Some(source_text),
+        (true, None) => Some(item.to_token_stream().to_string()),
+        (false, _) => None,
+    };
+    export_item(item, source_text, options)
+}
+
+/// Generates the output of `#[export_tokens]` for the parsed `item`, storing `source_text` as
+/// its verbatim source if specified.
+///
+/// Used by [`export_tokens_with_options`] and [`generate_export`].
+fn export_item(
+    item: Item,
+    source_text: Option<String>,
+    options: ExportTokensOptions,
+) -> Result<TokenStream2> {
+    // the `cfg_attr`s of the emitted item are left to the compiler, so if they are resolved
+    // for the exported tokens, the original item is kept aside to be emitted instead
+    let (item, emitted_item) = match options.cfg_attr {
+        CfgAttrResolution::Keep => (item, None),
+        resolution => (
+            parse2::<Item>(resolve_cfg_attrs(item.to_token_stream(), resolution))?,
+            Some(item),
+        ),
+    };
+    let prefix = options
+        .prefix
+        .as_deref()
+        .unwrap_or(MACRO_MAGIC_EXPORT_PREFIX);
+    let item_ident = item_ident(&item);
+    let ident = match (item_ident.clone(), options.name) {
+        (_, Some(name)) => name,
+        (Some(ident), None) => ident,
+        (None, None) if matches!(item, Item::Macro(_)) => {
+            return Err(Error::new(
+                item.span(),
+                "macro invocations have no inherent name, so an explicit one must be provided, \
+                e.g. `#[export_tokens(my_name)]` (only `macro_rules!` definitions can be \
+                exported without one)",
+            ));
+        }
+        (None, None) => {
+            return Err(Error::new(
+                item.span(),
+                format!(
+                    "this item has no inherent name, so an explicit one must be provided, \
+                    e.g. `#[export_tokens(my_name)]` (only the following kinds of items can be \
+                    exported without one: {})",
+                    supported_export_item_kinds().join(", ")
+                ),
+            ));
+        }
+    };
+    if let Some(attr) = stacked_export_attr(&item, &ident) {
+        return Err(Error::new(
+            attr.span(),
+            format!(
+                "`#[export_tokens]` is applied more than once to this item, which would export \
+                it twice under the same name `{}`; remove one of the attributes or give one of \
+                them a different name",
+                ident
+            ),
+        ));
+    }
+    if options.strict {
+        if let Some(attr) = item_attrs(&item)
+            .iter()
+            .find(|attr| matches!(attr.style, AttrStyle::Inner(_)))
+        {
+            return Err(Error::new(
+                attr.span(),
+                format!(
+                    "inner attributes are not allowed in `strict` mode, since they are only \
+                    valid when the tokens are expanded as a `{}` item",
+                    item_kind(&item)
+                ),
+            ));
+        }
+    }
+    // the un-flattened ident of the item itself, falling back to the explicit name
+    let original_ident = item_ident.unwrap_or_else(|| ident.clone());
+    let generics = match options.with_generics {
+        true => Some(item_generics(&item).ok_or_else(|| {
+            Error::new(
+                item.span(),
+                "the `with_generics` option can only be used on items that have generics",
+            )
+        })?),
+        false => None,
+    };
+    let value = match (options.value, &item) {
+        (true, Item::Const(item_const)) => Some(&*item_const.expr),
+        (true, _) => {
+            return Err(Error::new(
+                item.span(),
+                "the `value` option can only be used on consts",
+            ));
+        }
+        (false, _) => None,
+    };
+    // local callback macros aren't exported, and so may go unused
+    let mut macro_attrs = match options.local {
+        true => quote!(#[allow(unused_macros)]),
+        false => quote!(#[macro_export]),
+    };
+    if let Some(cfg) = &options.cfg {
+        macro_attrs.extend(quote!(#[cfg(#cfg)]));
+    }
+    let arms = ExportMacroArms {
+        kind: Some(&item),
+        generics,
+        value,
+        source: source_text.as_deref(),
+    };
+    let mut output = export_tokens_macro(
+        &ident,
+        prefix,
+        &original_ident,
+        &item,
+        arms,
+        options.version,
+        &macro_attrs,
+    );
+    if options.variants {
+        let Item::Enum(item_enum) = &item else {
+            return Err(Error::new(
+                item.span(),
+                "the `variants` option can only be used on enums",
+            ));
+        };
+        for variant in &item_enum.variants {
+            let variant_ident = format_ident!("{}_{}", ident, variant.ident);
+            let variant_item = enum_variant_struct(item_enum, variant);
+            output.extend(export_tokens_macro(
+                &variant_ident,
+                prefix,
+                &variant.ident,
+                &variant_item,
+                ExportMacroArms {
+                    kind: Some(&variant_item),
+                    ..Default::default()
+                },
+                options.version,
+                &macro_attrs,
+            ));
+        }
+    }
+    if options.body || options.sig {
+        let Item::Fn(item_fn) = &item else {
+            return Err(Error::new(
+                item.span(),
+                "the `body` and `sig` options can only be used on functions",
+            ));
+        };
+        if options.body {
+            let body_ident = format_ident!("{}_body", ident);
+            output.extend(export_tokens_macro(
+                &body_ident,
+                prefix,
+                &original_ident,
+                &item_fn.block,
+                ExportMacroArms::default(),
+                options.version,
+                &macro_attrs,
+            ));
+        }
+        if options.sig {
+            let sig_ident = format_ident!("{}_sig", ident);
+            output.extend(export_tokens_macro(
+                &sig_ident,
+                prefix,
+                &original_ident,
+                &item_fn.sig,
+                ExportMacroArms::default(),
+                options.version,
+                &macro_attrs,
+            ));
+        }
+    }
+    if options.items {
+        let Item::Trait(item_trait) = &item else {
+            return Err(Error::new(
+                item.span(),
+                "the `items` option can only be used on traits",
+            ));
+        };
+        for trait_item in &item_trait.items {
+            let trait_item_ident = match trait_item {
+                TraitItem::Const(item) => &item.ident,
+                TraitItem::Fn(item) => &item.sig.ident,
+                TraitItem::Type(item) => &item.ident,
+                _ => continue,
+            };
+            let export_ident = format_ident!("{}_{}", ident, trait_item_ident);
+            output.extend(export_tokens_macro(
+                &export_ident,
+                prefix,
+                trait_item_ident,
+                trait_item,
+                ExportMacroArms::default(),
+                options.version,
+                &macro_attrs,
+            ));
+        }
+    }
+    if options.fields {
+        let fields =
+            match &item {
+                Item::Union(item_union) => &item_union.fields.named,
+                Item::Struct(ItemStruct {
+                    fields: Fields::Named(fields),
+                    ..
+                }) => &fields.named,
+                _ => return Err(Error::new(
+                    item.span(),
+                    "the `fields` option can only be used on unions and structs with named fields",
+                )),
+            };
+        // each field is exported along with its own attributes (e.g. `#[doc]`s or `#[cfg]`s),
+        // while those of the item itself (e.g. `#[repr(C)]`) remain part of the item's tokens
+        for field in fields {
+            let Some(field_ident) = &field.ident else {
+                return Err(Error::new(
+                    field.span(),
+                    "the `fields` option requires every field to be named",
+                ));
+            };
+            let export_ident = format_ident!("{}_{}", ident, field_ident);
+            output.extend(export_tokens_macro(
+                &export_ident,
+                prefix,
+                field_ident,
+                field,
+                ExportMacroArms::default(),
+                options.version,
+                &macro_attrs,
+            ));
+        }
+    }
+    if options.hash {
+        let hashed_ident = format_ident!("{}_{}", ident, export_tokens_hash_suffix(&item));
+        output.extend(export_tokens_macro(
+            &hashed_ident,
+            prefix,
+            &original_ident,
+            &item,
+            arms,
+            options.version,
+            &macro_attrs,
+        ));
+    }
+    if options.const_fn && !options.emit {
+        return Err(Error::new(
+            item.span(),
+            "the `const_fn` option can't be used without emitting the item, since the \
+            `const fn` is emitted along with it",
+        ));
+    }
+    if options.export_const && !options.emit {
+        return Err(Error::new(
+            item.span(),
+            "the `export_const` option can't be used without emitting the item, since the \
+            `const` is emitted along with it",
+        ));
+    }
+    let mut item = emitted_item.unwrap_or(item);
+    if options.make_pub {
+        let Some(vis) = item_visibility_mut(&mut item) else {
+            return Err(Error::new(
+                item.span(),
+                "the `make_pub` option can only be used on items that have a visibility",
+            ));
+        };
+        *vis = parse_quote!(pub);
+    }
+    if options.emit {
+        let vis = item_visibility(&item);
+        let allow_unused = match options.allow_unused {
+            true => quote!(#[allow(unused)]),
+            false => quote!(),
+        };
+        let item_str = item.to_token_stream().to_string();
+        let const_ident = export_tokens_const_ident(&ident);
+        if options.export_const {
+            let const_doc = format!(
+                "The tokens of the `{}` item, as exported by `#[export_tokens]`.",
+                original_ident
+            );
+            output.extend(quote! {
+                #[doc = #const_doc]
+                #[allow(unused)]
+                #vis const #const_ident: &str = #item_str;
+            });
+        }
+        output.extend(quote! {
+            #allow_unused
+            #item
+        });
+        if options.const_fn {
+            let fn_ident = export_tokens_const_fn_ident(&ident);
+            let fn_doc = format!(
+                "Returns the tokens of the `{}` item, as exported by `#[export_tokens]`.",
+                original_ident
+            );
+            // refer to the const when there is one, rather than storing the string twice
+            let tokens_str = match options.export_const {
+                true => const_ident.to_token_stream(),
+                false => item_str.to_token_stream(),
+            };
+            output.extend(quote! {
+                #[doc = #fn_doc]
+                #[allow(unused)]
+                #vis const fn #fn_ident() -> &'static str {
+                    #tokens_str
+                }
+            });
+        }
+    }
+    // pretty_print(&output);
+    Ok(output)
+}
+
+/// The optional arms of the callback macro generated by [`export_tokens_macro`], each of which
+/// is only emitted if the corresponding field is set.
+#[derive(Clone, Copy, Default)]
+struct ExportMacroArms<'a> {
+    /// The item whose kind is stored, for the `@kind` arms (see [`item_kind`] and
+    /// [`item_kind_variant`])
+    kind: Option<&'a Item>,
+    /// The generics of the item, for the `@generics` arm
+    generics: Option<&'a Generics>,
+    /// The value expression of a const, for the `@value` arm
+    value: Option<&'a syn::Expr>,
+    /// The verbatim source text of the item, for the `@source` arm
+    source: Option<&'a str>,
+}
+
+/// Parses the item `#[export_tokens]` is attached to, replacing the error produced by [`syn`]
+/// with a more targeted message for common mistakes, such as passing several items or a
+/// statement instead of a single item.
+///
+/// Used by [`export_tokens_with_options`].
+fn parse_attached_item(tokens: TokenStream2) -> Result<Item> {
+    let err = match parse2::<Item>(tokens.clone()) {
+        Ok(item) => return Ok(item),
+        Err(err) => err,
+    };
+    let parse_items = |input: ParseStream| {
+        let mut items = Vec::new();
+        while !input.is_empty() {
+            items.push(input.parse::<Item>()?);
+        }
+        Ok(items)
+    };
+    if let Ok(items) = syn::parse::Parser::parse2(parse_items, tokens.clone()) {
+        return Err(Error::new(
+            tokens.span(),
+            format!(
+                "`#[export_tokens]` must be attached to a single item; found {} items",
+                items.len()
+            ),
+        ));
+    }
+    let parse_stmts = |input: ParseStream| syn::Block::parse_within(input);
+    if syn::parse::Parser::parse2(parse_stmts, tokens.clone()).is_ok() {
+        return Err(Error::new(
+            tokens.span(),
+            "`#[export_tokens]` must be attached to an item, such as a struct or fn, but was \
+            attached to a statement or expression",
+        ));
+    }
+    Err(err)
+}
+
+/// Returns the `#[export_tokens]` (or `#[export_tokens_no_emit]`) attribute still attached to
+/// the specified `item` that would export it under the same (flattened) name as `ident`, if
+/// any. Such attributes are left over when the attribute is stacked on the same item.
+fn stacked_export_attr<'a>(item: &'a Item, ident: &Ident) -> Option<&'a Attribute> {
+    item_attrs(item).iter().find(|attr| {
+        let Some(last_seg) = attr.path().segments.last() else {
+            return false;
+        };
+        if last_seg.ident != "export_tokens" && last_seg.ident != "export_tokens_no_emit" {
+            return false;
+        }
+        let args = match &attr.meta {
+            syn::Meta::Path(_) => ExportTokensArgs::default(),
+            _ => match attr.parse_args::<ExportTokensArgs>() {
+                Ok(args) => args,
+                Err(_) => return false,
+            },
+        };
+        match args.name.or_else(|| item_ident(item)) {
+            Some(other) => flatten_ident(&other) == flatten_ident(ident),
+            None => false,
+        }
+    })
+}
+
+/// Generates the callback macro that stores the tokens of the specified `item` under the (to
+/// be flattened) name `ident`. The attributes of the macro (normally just `#[macro_export]`)
+/// are passed in via `macro_attrs`.
+///
+/// Used by [`export_tokens_internal`].
+fn export_tokens_macro<T: ToTokens>(
+    ident: &Ident,
+    prefix: &str,
+    original_ident: &Ident,
+    item: &T,
+    arms: ExportMacroArms,
+    version: u64,
+    macro_attrs: &TokenStream2,
+) -> TokenStream2 {
+    let version = syn::LitInt::new(version.to_string().as_str(), Span::call_site());
+    let ident = export_tokens_macro_ident_with_prefix(ident, prefix);
+    let stored = export_tokens_stored(item);
+    // paths are matched as an optional first segment followed by `::`-prefixed segments, so
+    // that they are reproduced exactly, including any leading `::` (`$crate` also counts as a
+    // segment). A `$callback:path` fragment can't be used here, since the resulting path
+    // couldn't be invoked as a macro
+    let tokens_var_matcher = quote!($($tokens_var_root:ident)?$(::$tokens_var:ident)*);
+    let tokens_var = quote!($($tokens_var_root)?$(::$tokens_var)*);
+    let callback_matcher = quote!($($callback_root:ident)?$(::$callback:ident)*);
+    let callback = quote!($($callback_root)?$(::$callback)*);
+    let kind_arm = arms.kind.map(|item| {
+        let kind = item_kind(item);
+        let variant = format_ident!("{}", item_kind_variant(item));
+        quote! {
+            // kind arm, expanding to a `&'static str` naming the kind of the item
+            (@kind) => {
+                #kind
+            };
+            // kind arm (used by import_tokens_kind), expanding to the matching variant of the
+            // `ExportedKind` enum at the specified path
+            (@kind, $($kind_root:ident)?$(::$kind_path:ident)*) => {
+                $($kind_root)?$(::$kind_path)*::#variant
+            };
+        }
+    });
+    let value_arm = arms.value.map(|value| {
+        quote! {
+            // value arm (used by import_tokens_value)
+            (@value) => {
+                #value
+            };
+        }
+    });
+    let source_arm = arms.source.map(|source| {
+        quote! {
+            // source arm (used by import_tokens_str with `verbatim_source`)
+            (@source) => {
+                #source
+            };
+        }
+    });
+    let generics_arm = arms.generics.map(|generics| {
+        let where_clause = &generics.where_clause;
+        let stored_generics = export_tokens_stored(&quote!(#generics #where_clause));
+        quote! {
+            // generics arm (used by import_tokens_generics)
+            (@generics, #tokens_var_matcher, #callback_matcher) => {
+                #callback! {
+                    #tokens_var,
+                    #stored_generics
+                }
+            };
+        }
+    });
+    quote! {
+        #[doc(hidden)]
+        #macro_attrs
+        macro_rules! #ident {
+            // ident-only arm (used by import_tokens_ident)
+            (@ident) => {
+                #original_ident
+            };
+            #kind_arm
+            #value_arm
+            #source_arm
+            // version arm (used by import_tokens with `min_version`)
+            (@version, #callback_matcher, $($args:tt)*) => {
+                #callback! { #version, $($args)* }
+            };
+            #generics_arm
+            // arm with extra support (used by attr). The extra is matched as raw token trees
+            // rather than as an `expr`, so that it may contain commas and is delivered verbatim
+            // instead of being wrapped in an invisible group
+            (#tokens_var_matcher, #callback_matcher, $($extra:tt)+) => {
+                #callback! {
+                    #tokens_var,
+                    #stored,
+                    $($extra)+
+                }
+            };
+            // regular arm (used by proc, import_tokens, etc)
+            (#tokens_var_matcher, #callback_matcher) => {
+                #callback! {
+                    #tokens_var,
+                    #stored
+                }
+            };
+        }
+    }
+}
+
+/// Returns the tokens to store in a callback macro for `item`, compressed into a
+/// [`struct@LitStr`] if the `compress` feature is enabled.
+///
+/// Tokens containing `$` (such as `macro_rules!` definitions) are always compressed, since
+/// their metavariables and repetitions would otherwise be interpreted by the callback macro.
+fn export_tokens_stored<T: ToTokens>(item: &T) -> TokenStream2 {
+    let tokens = item.to_token_stream();
+    if cfg!(feature = "compress") || contains_dollar(tokens.clone()) {
+        return LitStr::new(compress_tokens(tokens).as_str(), Span::call_site()).to_token_stream();
+    }
+    tokens
+}
+
+/// Returns whether the specified tokens contain a `$` anywhere, including within groups.
+fn contains_dollar(tokens: TokenStream2) -> bool {
+    tokens.into_iter().any(|tt| match tt {
+        TokenTree::Punct(punct) => punct.as_char() == '$',
+        TokenTree::Group(group) => contains_dollar(group.stream()),
+        _ => false,
+    })
+}
+
+/// Synthesizes a struct [`Item`] reflecting the fields of the specified enum variant, so the
+/// variant can be exported and imported on its own. The struct is named after the variant and
+/// carries the variant's attributes along with the visibility and generics of the enum.
+///
+/// Used by [`export_tokens_internal`] for `#[export_tokens(variants)]`.
+pub fn enum_variant_struct(item_enum: &ItemEnum, variant: &Variant) -> Item {
+    let attrs = &variant.attrs;
+    let vis = &item_enum.vis;
+    let ident = &variant.ident;
+    let generics = &item_enum.generics;
+    let where_clause = &generics.where_clause;
+    let fields = &variant.fields;
+    match fields {
+        Fields::Named(_) => parse_quote! {
+            #(#attrs)*
+            #vis struct #ident #generics #where_clause #fields
+        },
+        _ => parse_quote! {
+            #(#attrs)*
+            #vis struct #ident #generics #fields #where_clause;
+        },
+    }
+}
+
+/// Internal implementation of `export_tokens_alias!`. Allows creating a renamed/rebranded
+/// macro that does the same thing as `#[export_tokens]`
+///
+/// `emit` is only the default of the generated alias, which can be overridden at each use
+/// site via the `emit` and `no_emit` options (see [`ExportTokensArgs`]).
+///
+/// The alias ident can optionally be followed by `, prefix = "..."`, in which case the
+/// callback macros generated by the alias use that prefix instead of
+/// [`MACRO_MAGIC_EXPORT_PREFIX`], so its exports can't collide with those of
+/// `#[export_tokens]`. Items exported this way must be imported with the same prefix (see
+/// [`ImportTokensArgs::prefix`]).
+pub fn export_tokens_alias_internal<T: Into<TokenStream2>>(
+    tokens: T,
+    emit: bool,
+) -> Result<TokenStream2> {
+    let args = parse2::<ExportTokensAliasArgs>(tokens.into())?;
+    let alias = args.alias;
+    if let Some(prefix) = args.prefix {
+        if syn::parse_str::<Ident>(format!("{}item", prefix.value()).as_str()).is_err() {
+            return Err(Error::new(
+                prefix.span(),
+                "the prefix must be valid at the start of an identifier, e.g. `__my_lib_tt_`",
+            ));
+        }
+        let mm_path = macro_magic_root();
+        return Ok(quote! {
+            #[proc_macro_attribute]
+            pub fn #alias(attr: proc_macro::TokenStream, tokens: proc_macro::TokenStream) -> proc_macro::TokenStream {
+                let args = match #mm_path::__private::syn::parse::<#mm_path::mm_core::ExportTokensArgs>(attr) {
+                    Ok(args) => args,
+                    Err(err) => return err.to_compile_error().into(),
+                };
+                let options = args.into_options(#emit).prefix(#prefix);
+                match #mm_path::mm_core::export_tokens_with_options(tokens, options) {
+                    Ok(tokens) => tokens.into(),
+                    Err(err) => err.to_compile_error().into(),
+                }
+            }
+        });
+    }
+    let export_tokens_internal_path = macro_magic_path(&quote!(mm_core::export_tokens_internal));
+    Ok(quote! {
+        #[proc_macro_attribute]
+        pub fn #alias(attr: proc_macro::TokenStream, tokens: proc_macro::TokenStream) -> proc_macro::TokenStream {
+            match #export_tokens_internal_path(attr, tokens, #emit) {
+                Ok(tokens) => tokens.into(),
+                Err(err) => err.to_compile_error().into(),
+            }
+        }
+    })
+}
+
+/// The internal implementation for the `import_tokens` macro.
+///
+/// You can call this in your own proc macros to make use of the `import_tokens` functionality
+/// directly, though this approach is limited. The arguments should be a [`TokenStream2`] that
+/// can parse into an [`ImportTokensArgs`] successfully. That is a valid `let` variable
+/// declaration set to equal a path where an `#[export_tokens]` with the specified ident can be
+/// found.
+///
+/// ### Example:
+/// ```
+/// use macro_magic_core::*;
+/// use quote::quote;
+///
+/// let some_ident = quote!(my_tokens);
+/// let some_path = quote!(other_crate::exported_item);
+/// let tokens =
+///     import_tokens_internal(quote!(let #some_ident = other_crate::ExportedItem)).unwrap();
+/// assert_eq!(
+///     tokens.to_string(),
+///     "other_crate :: __export_tokens_tt_exported_item ! { my_tokens , \
+///     :: macro_magic :: __private :: import_tokens_inner }");
+/// ```
+/// If these tokens were emitted as part of a proc macro, they would expand to a variable
+/// declaration like:
+/// ```ignore
+/// let my_tokens: TokenStream2;
+/// ```
+/// where `my_tokens` contains the tokens of `ExportedItem`.
+pub fn import_tokens_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
+    let args = parse2::<ImportTokensArgs>(tokens.into()).map_err(|err| {
+        Error::new(
+            err.span(),
+            "expected `let <ident> = <path>` or `extend <ident> = <path>`, optionally followed by \
+            `as <new name>`, `, min_version = <version>`, `, prefix = \"<prefix>\"`, `, local` \
+            and/or `, strip = [<attr>, ..]`, e.g. \
+            `import_tokens!(let tokens = my_crate::SomeItem)`",
+        )
+    })?;
+    let prefix = args
+        .prefix
+        .as_ref()
+        .map(LitStr::value)
+        .unwrap_or_else(|| MACRO_MAGIC_EXPORT_PREFIX.to_string());
+    let mut source_path = export_tokens_macro_path_with_prefix(&args.source_path, &prefix)?;
+    if args.local {
+        // local callback macros aren't at the crate root, so they can only be named directly
+        let Some(last_seg) = args.source_path.segments.last() else {
+            unreachable!("must have at least one segment")
+        };
+        source_path =
+            export_tokens_macro_ident_with_prefix(&last_seg.ident, &prefix).to_token_stream();
+    }
+    let root = macro_magic_root();
+    let inner_macro_path = match args.extend {
+        true => private_path_in(&root, &quote!(import_tokens_extend_inner)),
+        false => private_path_in(&root, &quote!(import_tokens_inner)),
+    };
+    let tokens_var_ident = args.tokens_var_ident;
+    let version_check = match args.min_version {
+        Some(min_version) => {
+            min_version.base10_parse::<u64>()?;
+            let check_macro_path = private_path_in(&root, &quote!(import_tokens_version_check));
+            let source_str = args.source_path.to_token_stream().to_string();
+            quote!(#source_path! { @version, #check_macro_path, #min_version, #source_str })
+        }
+        None => quote!(),
+    };
+    // the attributes to strip and the new name of the item (empty if it isn't renamed) are
+    // passed on to `import_tokens_inner` packed into an `extra` string
+    let extra = match (&args.rename, args.strip.is_empty()) {
+        (None, true) => quote!(),
+        (rename, _) => {
+            let paths = args.strip.iter();
+            let strip = quote!(#(#paths),*).to_string();
+            let rename = rename.as_ref().map(Ident::to_string).unwrap_or_default();
+            let extra = LitStr::new(&pack_extras([strip, rename]), Span::call_site());
+            quote!(, #extra)
+        }
+    };
+    Ok(quote! {
+        #version_check
+        #source_path! { #tokens_var_ident, #inner_macro_path #extra }
+    })
+}
+
+/// Removes all attributes (including inner attributes and doc comments, which are `doc`
+/// attributes) whose path is one of the specified `paths` from `tokens`, at any depth.
+///
+/// Attributes are matched by their full path as written, so `serde` matches `#[serde(..)]`
+/// but not `#[my_crate::serde(..)]`.
+pub fn strip_attributes(tokens: TokenStream2, paths: &[Path]) -> TokenStream2 {
+    let paths = paths
+        .iter()
+        .map(|path| path.to_token_stream().to_string())
+        .collect::<Vec<_>>();
+    strip_attributes_matching(tokens, &paths)
+}
+
+fn strip_attributes_matching(tokens: TokenStream2, paths: &[String]) -> TokenStream2 {
+    map_attributes(tokens, &|attr| {
+        let meta = parse2::<syn::Meta>(attr.clone()).ok()?;
+        paths
+            .contains(&meta.path().to_token_stream().to_string())
+            .then(Vec::new)
+    })
+}
+
+/// Resolves the `#[cfg_attr(..)]` attributes within `tokens`, at any depth, as specified by
+/// `resolution` (see [`CfgAttrResolution`]).
+///
+/// Nested `cfg_attr`s (e.g. `#[cfg_attr(a, cfg_attr(b, derive(Debug)))]`) are resolved
+/// recursively. `cfg_attr`s that can't be parsed are left as-is.
+pub fn resolve_cfg_attrs(tokens: TokenStream2, resolution: CfgAttrResolution) -> TokenStream2 {
+    if resolution == CfgAttrResolution::Keep {
+        return tokens;
+    }
+    fn resolve(attr: &TokenStream2, resolution: CfgAttrResolution) -> Option<Vec<TokenStream2>> {
+        let syn::Meta::List(list) = parse2::<syn::Meta>(attr.clone()).ok()? else {
+            return None;
+        };
+        if !list.path.is_ident("cfg_attr") {
+            return None;
+        }
+        // the predicate followed by the attributes to apply if it holds
+        let args = list
+            .parse_args_with(Punctuated::<syn::Meta, Comma>::parse_terminated)
+            .ok()?;
+        if resolution == CfgAttrResolution::Strip {
+            return Some(Vec::new());
+        }
+        Some(
+            args.into_iter()
+                .skip(1)
+                .flat_map(|meta| {
+                    let attr = meta.to_token_stream();
+                    resolve(&attr, resolution).unwrap_or_else(|| alloc::vec![attr])
+                })
+                .collect(),
+        )
+    }
+    map_attributes(tokens, &|attr| resolve(attr, resolution))
+}
+
+/// Rewrites the attributes (`#[..]` or `#![..]`) within `tokens`, at any depth, using `f`.
+///
+/// `f` is called with the contents of the brackets of each attribute, and returns either
+/// `None` to keep the attribute as-is, or the contents of the attributes (of the same style)
+/// to replace it with, if any.
+fn map_attributes<F>(tokens: TokenStream2, f: &F) -> TokenStream2
+where
+    F: Fn(&TokenStream2) -> Option<Vec<TokenStream2>>,
+{
+    let tokens = tokens.into_iter().collect::<Vec<_>>();
+    let mut output = TokenStream2::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        if let TokenTree::Punct(punct) = &tokens[i] {
+            if punct.as_char() == '#' {
+                let bang =
+                    matches!(&tokens.get(i + 1), Some(TokenTree::Punct(p)) if p.as_char() == '!');
+                let group_index = i + 1 + bang as usize;
+                if let Some(TokenTree::Group(group)) = tokens.get(group_index) {
+                    if group.delimiter() == Delimiter::Bracket {
+                        if let Some(replacements) = f(&group.stream()) {
+                            for replacement in replacements {
+                                output.extend(tokens[i..group_index].iter().cloned());
+                                let mut replaced =
+                                    proc_macro2::Group::new(Delimiter::Bracket, replacement);
+                                replaced.set_span(group.span());
+                                output.extend([TokenTree::Group(replaced)]);
+                            }
+                            i = group_index + 1;
+                            continue;
+                        }
+                    }
+                }
+            }
+        }
+        output.extend([match &tokens[i] {
+            TokenTree::Group(group) => {
+                let mut mapped =
+                    proc_macro2::Group::new(group.delimiter(), map_attributes(group.stream(), f));
+                mapped.set_span(group.span());
+                TokenTree::Group(mapped)
+            }
+            tt => tt.clone(),
+        }]);
+        i += 1;
+    }
+    output
+}
+
+/// Used to parse the args for the [`import_tokens_version_check_internal`] function.
+///
+/// You shouldn't need to use this directly.
+#[derive(Parse)]
+pub struct ImportTokensVersionCheckArgs {
+    /// The version the item was exported with
+    pub version: syn::LitInt,
+    _comma1: Comma,
+    /// The minimum version required by the importer
+    pub min_version: syn::LitInt,
+    _comma2: Comma,
+    /// The path of the imported item, for use in error messages
+    pub source: LitStr,
+}
+
+/// The internal implementation for the `import_tokens_version_check` macro, which is called
+/// back by the `#[export_tokens]` macro of an item imported with a `min_version`.
+///
+/// Expands to nothing if the exported version is at least the minimum version, and to a
+/// `compile_error!` otherwise.
+pub fn import_tokens_version_check_internal<T: Into<TokenStream2>>(
+    tokens: T,
+) -> Result<TokenStream2> {
+    let args = parse2::<ImportTokensVersionCheckArgs>(tokens.into())?;
+    let version = args.version.base10_parse::<u64>()?;
+    let min_version = args.min_version.base10_parse::<u64>()?;
+    if version >= min_version {
+        return Ok(quote!());
+    }
+    Err(Error::new(
+        args.min_version.span(),
+        format!(
+            "`{}` was exported with version {}, but at least version {} is required",
+            args.source.value(),
+            version,
+            min_version
+        ),
+    ))
+}
+
+/// The internal implementation for the `import_tokens_merge` macro.
+///
+/// Imports each of the listed items like [`import_tokens_internal`] and binds the
+/// concatenation of their tokens, in the order the paths were listed, to the specified
+/// variable.
+pub fn import_tokens_merge_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
+    let args = parse2::<ImportTokensMergeArgs>(tokens.into())?;
+    if args.source_paths.is_empty() {
+        return Err(Error::new(
+            args._bracket.span.join(),
+            "expected at least one path to merge",
+        ));
+    }
+    let root = macro_magic_root();
+    let inner_macro_path = private_path_in(&root, &quote!(import_tokens_inner));
+    let token_stream_2 = private_path_in(&root, &quote!(TokenStream2));
+    let tokens_var_ident = args.tokens_var_ident;
+    let part_idents = (0..args.source_paths.len())
+        .map(|i| format_ident!("__merged_tokens_{}", i))
+        .collect::<Vec<_>>();
+    let imports = args
+        .source_paths
+        .iter()
+        .zip(&part_idents)
+        .map(|(source_path, part_ident)| {
+            let source_path = export_tokens_macro_path(source_path)?;
+            Ok(quote!(#source_path! { #part_ident, #inner_macro_path }))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(quote! {
+        let #tokens_var_ident = {
+            #(#imports)*
+            let mut merged = #token_stream_2::new();
+            #(merged.extend(#part_idents);)*
+            merged
+        };
+    })
+}
+
+/// The internal implementation for the `import_tokens_env` macro.
+///
+/// Looks up the specified environment variable using `lookup_env`, parses its value as the
+/// [`Path`] of an exported item, and then expands exactly like [`import_tokens_internal`].
+/// Errors if the environment variable is not set or does not contain a valid path.
+pub fn import_tokens_env_internal<T: Into<TokenStream2>, F: Fn(&str) -> Option<String>>(
+    tokens: T,
+    lookup_env: F,
+) -> Result<TokenStream2> {
+    let args = parse2::<ImportTokensEnvArgs>(tokens.into())?;
+    let env_var = args.env_var_ident.to_string();
+    let Some(env_value) = lookup_env(env_var.as_str()) else {
+        return Err(Error::new(
+            args.env_var_ident.span(),
+            format!("environment variable `{}` is not set", env_var),
+        ));
+    };
+    let source_path = syn::parse_str::<Path>(env_value.as_str()).map_err(|err| {
+        Error::new(
+            args.env_var_ident.span(),
+            format!(
+                "environment variable `{}` must contain the path of an exported item, found `{}`: {}",
+                env_var, env_value, err
+            ),
+        )
+    })?;
+    let tokens_var_ident = args.tokens_var_ident;
+    import_tokens_internal(quote!(let #tokens_var_ident = #source_path))
+}
+
+/// The internal implementation for the `import_tokens_ident` macro.
+///
+/// Expands to the original (un-flattened) [`struct@Ident`] of the exported item at the
+/// specified path.
+pub fn import_tokens_ident_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
+    let source_path = export_tokens_macro_path(&parse2::<Path>(tokens.into())?)?;
+    Ok(quote!(#source_path! { @ident }))
+}
+
+/// The internal implementation for the `import_tokens_kind` macro.
+///
+/// Expands to the path of the `ExportedKind` variant matching the kind of the exported item at
+/// the specified path, as returned by [`item_kind_variant`] (e.g.
+/// `::macro_magic::ExportedKind::Fn`).
+pub fn import_tokens_kind_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
+    let source_path = export_tokens_macro_path(&parse2::<Path>(tokens.into())?)?;
+    let mm_path = macro_magic_root();
+    Ok(quote!(#source_path! { @kind, #mm_path::ExportedKind }))
+}
+
+/// The internal implementation for the `import_tokens_value` macro.
+///
+/// Expands to the value expression of the const at the specified path, which must have been
+/// exported with `#[export_tokens(value)]`.
+pub fn import_tokens_value_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
+    let source_path = export_tokens_macro_path(&parse2::<Path>(tokens.into())?)?;
+    Ok(quote!(#source_path! { @value }))
+}
+
+/// Used to parse the args for the [`import_tokens_str_internal`] function.
+///
+/// You shouldn't need to use this directly.
+pub struct ImportTokensStrArgs {
+    /// The path of the exported item
+    pub source_path: Path,
+    /// Whether the verbatim source text stored by `#[export_tokens(verbatim_source)]` should be
+    /// imported rather than the stringified tokens.
+    pub verbatim_source: bool,
+}
+
+impl syn::parse::Parse for ImportTokensStrArgs {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let source_path = input.parse()?;
+        let mut verbatim_source = false;
+        if !input.is_empty() {
+            input.parse::<Comma>()?;
+            input.parse::<keywords::verbatim_source>()?;
+            verbatim_source = true;
+        }
+        Ok(ImportTokensStrArgs {
+            source_path,
+            verbatim_source,
+        })
+    }
+}
+
+/// The internal implementation for the `import_tokens_str` macro.
+///
+/// Expands to a call to the `macro_rules!` exported for the item at the specified path,
+/// which in turn calls `import_tokens_str_inner` with the stored tokens of the item. With the
+/// `verbatim_source` option, the source text stored for the item is used instead.
+pub fn import_tokens_str_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
+    let args = parse2::<ImportTokensStrArgs>(tokens.into())?;
+    let source_path = export_tokens_macro_path(&args.source_path)?;
+    if args.verbatim_source {
+        return Ok(quote!(#source_path! { @source }));
+    }
+    let inner_macro_path = private_path(&quote!(import_tokens_str_inner));
+    Ok(quote! {
+        #source_path! { tokens, #inner_macro_path }
+    })
+}
+
+/// The internal implementation for the `import_tokens_str_inner` macro.
+///
+/// Expands to a `&'static str` literal containing the tokens of the imported item, so the
+/// result can be used in `const` and `static` initializers.
+pub fn import_tokens_str_inner_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
+    let parsed = parse2::<ImportedRawTokens>(tokens.into())?;
+    let tokens_string = LitStr::new(parsed.tokens.to_string().as_str(), Span::call_site());
+    Ok(quote!(#tokens_string))
+}
+
+/// The internal implementation for the `import_tokens_docs` macro.
+///
+/// Expands to a call to the `macro_rules!` exported for the item at the specified path, which
+/// in turn calls `import_tokens_docs_inner` with the stored tokens of the item.
+pub fn import_tokens_docs_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
+    let source_path = export_tokens_macro_path(&parse2::<Path>(tokens.into())?)?;
+    let inner_macro_path = private_path(&quote!(import_tokens_docs_inner));
+    Ok(quote! {
+        #source_path! { tokens, #inner_macro_path }
+    })
+}
+
+/// The internal implementation for the `import_tokens_docs_inner` macro.
+///
+/// Expands to a `&'static str` literal containing the values of the `#[doc = ".."]`
+/// attributes (i.e. doc comments) of the imported item, joined by newlines, or an empty
+/// string if the item is undocumented. For exports that aren't full items (such as struct
+/// fields or trait items), the outer attributes preceding them are used.
+pub fn import_tokens_docs_inner_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
+    let tokens = parse2::<ImportedRawTokens>(tokens.into())?.tokens;
+    let attrs = match parse2::<Item>(tokens.clone()) {
+        Ok(item) => item_attrs(&item).to_vec(),
+        Err(_) => syn::parse::Parser::parse2(
+            |input: ParseStream| {
+                let attrs = Attribute::parse_outer(input)?;
+                input.parse::<TokenStream2>()?;
+                Ok(attrs)
+            },
+            tokens,
+        )?,
+    };
+    let mut docs = Vec::new();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("doc")) {
+        let doc = match &attr.meta {
+            syn::Meta::NameValue(syn::MetaNameValue {
+                value:
+                    syn::Expr::Lit(syn::ExprLit {
+                        lit: Lit::Str(doc), ..
+                    }),
+                ..
+            }) => doc.value(),
+            // e.g. `#[doc = include_str!("..")]`, which can't be evaluated here
+            _ => {
+                return Err(Error::new(
+                    attr.span(),
+                    "only doc attributes with a string literal value (such as doc comments) \
+                    can be imported",
+                ))
+            }
+        };
+        docs.push(doc);
+    }
+    let docs = LitStr::new(docs.join("\n").as_str(), Span::call_site());
+    Ok(quote!(#docs))
+}
+
+/// The internal implementation for the `import_tokens_mod` macro.
+///
+/// Expands to a call to the `macro_rules!` exported for the item at the specified path, which
+/// in turn calls `import_tokens_mod_inner` with the name of the module, the tokens of the item
+/// and the visibility of the module (as a string).
+pub fn import_tokens_mod_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
+    let args = parse2::<ImportTokensModArgs>(tokens.into()).map_err(|err| {
+        Error::new(
+            err.span(),
+            "expected `<module name>, <path>`, optionally preceded by a visibility, e.g. \
+            `import_tokens_mod!(pub my_mod, my_crate::SomeItem)`",
+        )
+    })?;
+    let source_path = export_tokens_macro_path(&args.source_path)?;
+    let inner_macro_path = private_path(&quote!(import_tokens_mod_inner));
+    let mod_ident = args.mod_ident;
+    let vis = LitStr::new(&args.vis.to_token_stream().to_string(), Span::call_site());
+    Ok(quote! {
+        #source_path! { #mod_ident, #inner_macro_path, #vis }
+    })
+}
+
+/// The internal implementation for the `import_tokens_mod_inner` macro.
+///
+/// Emits the imported item within a module of the specified name and visibility. The module
+/// glob-imports its parent, so the item can refer to the same names as if it had been emitted
+/// in place, and items without an explicit visibility are made `pub(super)`, so they remain
+/// accessible from the parent.
+pub fn import_tokens_mod_inner_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
+    let parsed = parse2::<ForwardedTokens>(tokens.into())?;
+    let Some(mod_ident) = parsed.target_path.get_ident() else {
+        return Err(Error::new(
+            parsed.target_path.span(),
+            "expected the name of the module to import the item into",
+        ));
+    };
+    let vis = match &parsed.extra {
+        Some(vis) => vis.parse::<Visibility>().map_err(|err| {
+            Error::new(
+                vis.span(),
+                format!("malformed `extra`: invalid module visibility: {}", err),
+            )
+        })?,
+        None => Visibility::Inherited,
+    };
+    let mut item = parsed.item;
+    if let Some(item_vis @ Visibility::Inherited) = item_visibility_mut(&mut item) {
+        *item_vis = parse_quote!(pub(super));
+    }
+    Ok(quote! {
+        #vis mod #mod_ident {
+            #[allow(unused_imports)]
+            use super::*;
+
+            #item
+        }
+    })
+}
+
+/// The internal implementation for the `assert_tokens_eq` macro.
+///
+/// Expands to a call to the `macro_rules!` exported for the left item, which in turn calls
+/// `assert_tokens_eq_inner` with the tokens of the left item and the path of the right item
+/// (as a string).
+pub fn assert_tokens_eq_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
+    let args = parse2::<AssertTokensEqArgs>(tokens.into()).map_err(|err| {
+        Error::new(
+            err.span(),
+            "expected `<path>, <path>`, e.g. \
+            `assert_tokens_eq!(my_crate::SomeItem, other_crate::SomeItem)`",
+        )
+    })?;
+    let left_path = export_tokens_macro_path(&args.left)?;
+    // resolve the right path up front, so mistakes in it are reported at the call site
+    let right_path = export_tokens_macro_path(&args.right)?;
+    let inner_macro_path = private_path(&quote!(assert_tokens_eq_inner));
+    let right_path = LitStr::new(&right_path.to_string(), Span::call_site());
+    Ok(quote! {
+        #left_path! { __assert_tokens_eq, #inner_macro_path, #right_path }
+    })
+}
+
+/// The internal implementation for the `assert_tokens_eq_inner` macro.
+///
+/// Receives the tokens of the left item along with the callback macro path of the right item,
+/// and expands to a call to the latter, which in turn calls `assert_tokens_eq_compare` with the
+/// tokens of the right item and those of the left item (as a string).
+pub fn assert_tokens_eq_inner_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
+    let (tokens, extra) = split_trailing_extra(tokens.into());
+    let Some(right_path) = extra else {
+        return Err(Error::new(
+            Span::call_site(),
+            "expected the path of the right item as `extra`",
+        ));
+    };
+    let right_path = right_path.parse::<Path>().map_err(|err| {
+        Error::new(
+            right_path.span(),
+            format!("malformed `extra`: invalid right item path: {}", err),
+        )
+    })?;
+    let parsed = parse2::<ImportedRawTokens>(tokens)?;
+    let left = LitStr::new(&parsed.tokens.to_string(), Span::call_site());
+    let compare_macro_path = private_path(&quote!(assert_tokens_eq_compare));
+    Ok(quote! {
+        #right_path! { __assert_tokens_eq, #compare_macro_path, #left }
+    })
+}
+
+/// The internal implementation for the `assert_tokens_eq_compare` macro.
+///
+/// Compares the tokens of the right item with those of the left item (passed as a string),
+/// ignoring spans and whitespace (see [`tokens_first_difference`]). Expands to nothing if they
+/// are equal, and to a `compile_error!` describing the first difference otherwise.
+pub fn assert_tokens_eq_compare_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
+    let (tokens, extra) = split_trailing_extra(tokens.into());
+    let Some(left) = extra else {
+        return Err(Error::new(
+            Span::call_site(),
+            "expected the tokens of the left item as `extra`",
+        ));
+    };
+    let left_tokens = left.parse::<TokenStream2>().map_err(|err| {
+        Error::new(
+            left.span(),
+            format!("malformed `extra`: invalid left item tokens: {}", err),
+        )
+    })?;
+    let right_tokens = parse2::<ImportedRawTokens>(tokens)?.tokens;
+    match tokens_first_difference(&left_tokens, &right_tokens) {
+        Some(difference) => Ok(compile_error(
+            Span::call_site(),
+            &format!(
+                "exported tokens are not equal: {}\n  left: `{}`\n right: `{}`",
+                difference, left_tokens, right_tokens
+            ),
+        )),
+        None => Ok(quote!()),
+    }
+}
+
+/// The internal implementation for the `import_tokens_doc` macro.
+///
+/// Expands to the specified item with a `#[doc = ..]` attribute added after its own attributes,
+/// whose value is a
+/// call to the `macro_rules!` exported for the item at the specified path, which in turn calls
+/// `import_tokens_doc_inner` to produce the doc string.
+pub fn import_tokens_doc_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
+    let args = parse2::<ImportTokensDocArgs>(tokens.into()).map_err(|err| {
+        Error::new(
+            err.span(),
+            "expected `<path> => <item>`, e.g. \
+            `import_tokens_doc!(my_crate::SomeItem => pub struct Documented;)`",
+        )
+    })?;
+    let source_path = export_tokens_macro_path(&args.source_path)?;
+    let inner_macro_path = private_path(&quote!(import_tokens_doc_inner));
+    let attrs = args.attrs;
+    let item = args.item;
+    Ok(quote! {
+        #(#attrs)*
+        #[doc = #source_path! { tokens, #inner_macro_path }]
+        #item
+    })
+}
+
+/// The internal implementation for the `import_tokens_doc_inner` macro.
+///
+/// Expands to a string literal containing the tokens of the imported item wrapped in a
+/// `text` code block, so rustdoc doesn't interpret them as markdown. The fence is made longer
+/// than any run of backticks within the tokens, so it can't be closed early.
+pub fn import_tokens_doc_inner_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
+    let parsed = parse2::<ImportedRawTokens>(tokens.into())?;
+    let tokens_string = parsed.tokens.to_string();
+    let longest_run = tokens_string
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat(longest_run.max(2) + 1);
+    let doc = LitStr::new(
+        &format!("{fence}text\n{tokens_string}\n{fence}"),
+        Span::call_site(),
+    );
+    Ok(quote!(#doc))
+}
+
+/// The internal implementation for the `import_tokens_inner` macro.
+///
+/// You shouldn't need to call this in any circumstances but it is provided just in case.
+pub fn import_tokens_inner_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
+    let (ident, tokens) = import_tokens_inner_parts(tokens.into())?;
+    Ok(quote! {
+        let #ident = #tokens;
+    })
+}
+
+/// The internal implementation for the `import_tokens_extend_inner` macro, used by
+/// [`import_tokens_internal`] for `import_tokens!(extend <ident> = <path>)`.
+///
+/// Like [`import_tokens_inner_internal`], but appends the imported tokens to the existing,
+/// mutable `TokenStream2` variable via `.extend(..)` instead of binding them to a new one.
+pub fn import_tokens_extend_inner_internal<T: Into<TokenStream2>>(
+    tokens: T,
+) -> Result<TokenStream2> {
+    let (ident, tokens) = import_tokens_inner_parts(tokens.into())?;
+    Ok(quote! {
+        #ident.extend(#tokens);
+    })
+}
+
+/// Parses the `tokens_var, <tokens>` (optionally followed by the attributes to strip and the
+/// new name of the item) passed to the inner macros of [`import_tokens_internal`], returning
+/// the variable ident along with an expression evaluating to the imported tokens as a
+/// `TokenStream2`.
+fn import_tokens_inner_parts(tokens: TokenStream2) -> Result<(Ident, TokenStream2)> {
+    // the attributes to strip and the new name, if any, are packed into the `extra` literal
+    let (tokens, extra) = split_trailing_extra(tokens);
+    let mut strip = Vec::new();
+    let mut rename = None;
+    if let Some(extra) = extra {
+        let malformed =
+            |err: &dyn Display| Error::new(extra.span(), format!("malformed `extra`: {}", err));
+        let fields = unpack_extra_exact(&extra.value(), 2).map_err(|err| malformed(&err))?;
+        let (paths, new_name) = (&fields[0], &fields[1]);
+        strip = syn::parse::Parser::parse_str(Punctuated::<Path, Comma>::parse_terminated, paths)
+            .map_err(|err| malformed(&err))?
+            .into_iter()
+            .collect();
+        if !new_name.is_empty() {
+            let mut new_name = syn::parse_str::<Ident>(new_name).map_err(|err| malformed(&err))?;
+            new_name.set_span(extra.span());
+            rename = Some(new_name);
+        }
+    }
+    let parsed = parse2::<ImportedRawTokens>(tokens)?;
+    let mut tokens = strip_attributes(parsed.tokens, &strip);
+    if let Some(rename) = rename {
+        let mut item = parse2::<Item>(tokens)?;
+        let Some(ident) = item_ident_mut(&mut item) else {
+            return Err(Error::new(
+                rename.span(),
+                format!(
+                    "cannot rename the imported item to `{}`, since `{}` items have no name",
+                    rename,
+                    item_kind(&item)
+                ),
+            ));
+        };
+        *ident = rename;
+        tokens = item.to_token_stream();
+    }
+    let tokens_string = tokens.to_string();
+    let token_stream_2 = private_path(&quote!(TokenStream2));
+    Ok((
+        parsed.tokens_var_ident,
+        quote!(#tokens_string.parse::<#token_stream_2>().expect("failed to parse quoted tokens")),
+    ))
+}
+
+/// Splits the trailing `, <extra>` string literal passed by the `extra` arm of an
+/// `#[export_tokens]` callback macro off the raw tokens forwarded to an inner macro, i.e.
+/// `tokens_var, <tokens>, "extra"`.
+///
+/// The stored tokens are never followed by a comma themselves (an item ends with `;` or `}`,
+/// and compressed tokens are a single literal), so a trailing comma and string literal can
+/// only be the `extra`. The literal may be wrapped in an invisible group if it was passed
+/// through an `expr` fragment along the way (as done by the callback macros generated by older
+/// versions of `macro_magic`).
+fn split_trailing_extra(tokens: TokenStream2) -> (TokenStream2, Option<LitStr>) {
+    let mut tokens = tokens.into_iter().collect::<Vec<_>>();
+    let mut extra = None;
+    if let [_, _, _, .., TokenTree::Punct(comma), last] = tokens.as_slice() {
+        if comma.as_char() == ',' {
+            if let Ok(lit) = parse2::<LitStr>(last.to_token_stream()) {
+                extra = Some(lit);
+                tokens.truncate(tokens.len() - 2);
+            }
+        }
+    }
+    (tokens.into_iter().collect(), extra)
+}
+
+/// The internal implementation for the `import_tokens_fresh` macro.
+///
+/// Works like [`import_tokens_internal`], except it takes just the [`Path`] of the exported
+/// item and expands to an expression evaluating to its tokens, so no variable name has to be
+/// chosen by the caller.
+pub fn import_tokens_fresh_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
+    let source_path = export_tokens_macro_path(&parse2::<Path>(tokens.into())?)?;
+    let inner_macro_path = private_path(&quote!(import_tokens_fresh_inner));
+    Ok(quote! {
+        #source_path! { __imported_tokens, #inner_macro_path }
+    })
+}
+
+/// The internal implementation for the `import_tokens_fn` macro.
+///
+/// Expands to a function with the specified visibility and name that returns the tokens of
+/// the exported item at the specified path as a freshly parsed `TokenStream2`. The function
+/// is defined wherever the macro is invoked, like any other item.
+pub fn import_tokens_fn_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
+    let args = parse2::<ImportTokensFnArgs>(tokens.into()).map_err(|err| {
+        Error::new(
+            err.span(),
+            "expected `fn <ident> = <path>`, optionally preceded by a visibility, e.g. \
+            `import_tokens_fn!(pub fn some_item_tokens = my_crate::SomeItem)`",
+        )
+    })?;
+    let vis = args.vis;
+    let fn_ident = args.fn_ident;
+    let token_stream_2 = private_path(&quote!(TokenStream2));
+    let import = import_tokens_fresh_internal(args.source_path.to_token_stream())?;
+    Ok(quote! {
+        #vis fn #fn_ident() -> #token_stream_2 {
+            #import
+        }
+    })
+}
+
+/// The internal implementation for the `import_tokens_raw` macro.
+///
+/// Like [`import_tokens_fresh_internal`], except the specified [`Path`] is used as-is as the
+/// path of the callback macro generated by `#[export_tokens]` (e.g.
+/// `my_crate::__export_tokens_tt_my_item`), rather than being flattened via
+/// [`export_tokens_macro_path`].
+pub fn import_tokens_raw_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
+    let macro_path = parse2::<Path>(tokens.into())?;
+    let inner_macro_path = private_path(&quote!(import_tokens_fresh_inner));
+    Ok(quote! {
+        #macro_path! { __imported_tokens, #inner_macro_path }
+    })
+}
+
+/// The internal implementation for the `import_tokens_generics` macro.
+///
+/// Expands to an expression evaluating to the generics stored for the item at the specified
+/// path by `#[export_tokens(with_generics)]` (see [`parse_generics`]).
+pub fn import_tokens_generics_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
+    let source_path = export_tokens_macro_path(&parse2::<Path>(tokens.into())?)?;
+    let inner_macro_path = private_path(&quote!(import_tokens_fresh_inner));
+    Ok(quote! {
+        #source_path! { @generics, __imported_tokens, #inner_macro_path }
+    })
+}
+
+/// The internal implementation for the `import_tokens_fresh_inner` macro.
+///
+/// Like [`import_tokens_inner_internal`], but emits a block expression evaluating to the
+/// imported tokens rather than a bare `let` statement. The binding lives inside the block, so
+/// it can't collide with anything at the call site.
+pub fn import_tokens_fresh_inner_internal<T: Into<TokenStream2>>(
+    tokens: T,
+) -> Result<TokenStream2> {
+    let parsed = parse2::<ImportedRawTokens>(tokens.into())?;
+    let tokens_string = parsed.tokens.to_string();
+    let ident = parsed.tokens_var_ident;
+    let token_stream_2 = private_path(&quote!(TokenStream2));
+    Ok(quote! {
+        {
+            let #ident = #tokens_string.parse::<#token_stream_2>().expect("failed to parse quoted tokens");
+            #ident
+        }
+    })
+}
+
+/// The internal implementation for the `import_tokens_field` macro.
+///
+/// Takes the path of an exported struct followed by the name of one of its fields (e.g.
+/// `my_crate::MyStruct::field_name`) and expands to an expression evaluating to the tokens of
+/// that field (its name and type, e.g. `field_name: u32`).
+pub fn import_tokens_field_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
+    let mut path = parse2::<Path>(tokens.into())?;
+    if path.segments.len() < 2 {
+        return Err(Error::new(
+            path.span(),
+            "expected the path of an exported struct followed by the name of one of its \
+            fields, e.g. `import_tokens_field!(my_crate::MyStruct::field_name)`",
+        ));
+    }
+    let Some(field_seg) = path.segments.pop() else {
+        unreachable!("path has at least two segments");
+    };
+    let field_seg = field_seg.into_value();
+    if !field_seg.arguments.is_none() {
+        return Err(Error::new(
+            field_seg.arguments.span(),
+            "field names cannot have generic arguments",
+        ));
+    }
+    path.segments.pop_punct();
+    let source_path = export_tokens_macro_path(&path)?;
+    let field_ident = field_seg.ident;
+    let inner_macro_path = private_path(&quote!(import_tokens_field_inner));
+    Ok(quote! {
+        #source_path! { #field_ident, #inner_macro_path }
+    })
+}
+
+/// The internal implementation for the `import_tokens_field_inner` macro.
+///
+/// Receives the name of the requested field in place of the usual tokens variable, along with
+/// the tokens of the exported struct, and emits a block expression evaluating to the tokens of
+/// the field.
+pub fn import_tokens_field_inner_internal<T: Into<TokenStream2>>(
+    tokens: T,
+) -> Result<TokenStream2> {
+    let parsed = parse2::<ImportedRawTokens>(tokens.into())?;
+    let field_ident = parsed.tokens_var_ident;
+    let item = parse2::<Item>(parsed.tokens)?;
+    let Item::Struct(item_struct) = item else {
+        return Err(Error::new(
+            field_ident.span(),
+            format!(
+                "expected the tokens of a struct, but the exported item is a `{}`",
+                item_kind(&item)
+            ),
+        ));
+    };
+    let Some(field) = item_struct
+        .fields
+        .iter()
+        .find(|field| field.ident.as_ref() == Some(&field_ident))
+    else {
+        let field_names = item_struct
+            .fields
+            .iter()
+            .filter_map(|field| field.ident.as_ref().map(|ident| format!("`{}`", ident)))
+            .collect::<Vec<_>>();
+        return Err(Error::new(
+            field_ident.span(),
+            match field_names.is_empty() {
+                true => format!("`{}` has no named fields", item_struct.ident),
+                false => format!(
+                    "`{}` has no field named `{}` (available fields: {})",
+                    item_struct.ident,
+                    field_ident,
+                    field_names.join(", ")
+                ),
+            },
+        ));
+    };
+    let ty = &field.ty;
+    let field_string = quote!(#field_ident: #ty).to_string();
+    let token_stream_2 = private_path(&quote!(TokenStream2));
+    Ok(quote! {
+        {
+            #field_string.parse::<#token_stream_2>().expect("failed to parse quoted tokens")
+        }
+    })
+}
+
+/// The internal implementation for the `forward_tokens` macro.
+///
+/// You shouldn't need to call this in any circumstances but it is provided just in case.
+pub fn forward_tokens_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
+    let args = parse2::<ForwardTokensArgs>(tokens.into())?;
+    let mm_path = match args.mm_path {
+        Some(path) => path,
+        None => macro_magic_root(),
+    };
+    let source_path = export_tokens_macro_path(&args.source)?;
+    let target_path = args.target;
+    if let Some(transform) = args.transform {
+        if let Some(extra) = args.extras.first() {
+            return Err(Error::new(
+                extra.span(),
+                "`extra` is not supported when forwarding through a transform",
+            ));
+        }
+        let transform_extra = LitStr::new(
+            quote!(#transform, #mm_path).to_string().as_str(),
+            Span::call_site(),
+        );
+        Ok(quote! {
+            #source_path! {
+                #target_path,
+                #mm_path::__private::forward_tokens_transform_inner,
+                #transform_extra
+            }
+        })
+    } else if let [Lit::Str(extra)] = args.extras.as_slice() {
+        Ok(quote! {
+            #source_path! {
+                #target_path,
+                #mm_path::__private::forward_tokens_inner,
+                #extra
+            }
+        })
+    } else if !args.extras.is_empty() {
+        // the callback only has room for a single `extra`, so multiple literals are packed
+        // into one string and unpacked again by `forward_tokens_extras_inner`
+        let packed = pack_extras(args.extras.iter().map(ToTokens::to_token_stream));
+        let packed = LitStr::new(packed.as_str(), Span::call_site());
+        Ok(quote! {
+            #source_path! {
+                #target_path,
+                #mm_path::__private::forward_tokens_extras_inner,
+                #packed
+            }
+        })
+    } else {
+        Ok(quote! {
+            #source_path! { #target_path, #mm_path::__private::forward_tokens_inner }
+        })
+    }
+}
+
+/// Used by [`forward_tokens_internal`].
+pub fn forward_tokens_inner_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
+    let parsed = parse2::<ForwardedTokens>(tokens.into())?;
+    let target_path = parsed.target_path;
+    let imported_tokens = parsed.item;
+    let combined_tokens = match parsed.extra {
+        Some(extra) => quote! {
+            #imported_tokens,
+            #extra
+        },
+        None => quote!(#imported_tokens),
+    };
+    Ok(quote! {
+        #target_path! {
+            #combined_tokens
+        }
+    })
+}
+
+/// The internal implementation for the `forward_tokens_dynamic` macro.
+///
+/// Expands to a call to the `macro_rules!` exported for the source item, which in turn calls
+/// `forward_tokens_dynamic_inner` with the path of the table and the key (as a string).
+pub fn forward_tokens_dynamic_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
+    let args = parse2::<ForwardTokensDynamicArgs>(tokens.into()).map_err(|err| {
+        Error::new(
+            err.span(),
+            "expected `<source path>, <table path>, <key>`, e.g. \
+            `forward_tokens_dynamic!(my_crate::SomeItem, my_table, some_key)`",
+        )
+    })?;
+    let source_path = export_tokens_macro_path(&args.source)?;
+    let table_path = args.table;
+    let inner_macro_path = private_path(&quote!(forward_tokens_dynamic_inner));
+    let key = LitStr::new(&args.key.to_string(), args.key.span());
+    Ok(quote! {
+        #source_path! {
+            #table_path,
+            #inner_macro_path,
+            #key
+        }
+    })
+}
+
+/// The internal implementation for the `forward_tokens_dynamic_inner` macro.
+///
+/// Invokes the table macro with the key followed by the tokens of the forwarded item, i.e.
+/// `table! { key, item }`, leaving it to the table to dispatch to the target for that key.
+pub fn forward_tokens_dynamic_inner_internal<T: Into<TokenStream2>>(
+    tokens: T,
+) -> Result<TokenStream2> {
+    let parsed = parse2::<ForwardedTokens>(tokens.into())?;
+    let Some(key) = parsed.extra else {
+        return Err(Error::new(
+            Span::call_site(),
+            "expected the key to look up in the table as `extra`",
+        ));
+    };
+    let key = key.parse::<Ident>().map_err(|err| {
+        Error::new(
+            key.span(),
+            format!("malformed `extra`: invalid key: {}", err),
+        )
+    })?;
+    let table_path = parsed.target_path;
+    let item = parsed.item;
+    Ok(quote! {
+        #table_path! {
+            #key,
+            #item
+        }
+    })
+}
+
+/// Used by [`forward_tokens_internal`] when forwarding multiple extra literals.
+///
+/// Unpacks the extra literals that were packed into a single [`struct@LitStr`] by
+/// [`forward_tokens_internal`] and delivers them to the target after the item, in order.
+pub fn forward_tokens_extras_inner_internal<T: Into<TokenStream2>>(
+    tokens: T,
+) -> Result<TokenStream2> {
+    let parsed = parse2::<ForwardedTokens>(tokens.into())?;
+    let target_path = parsed.target_path;
+    let imported_tokens = parsed.item;
+    let Some(packed) = parsed.extra else {
+        return Err(Error::new(
+            Span::call_site(),
+            "expected packed extra literals to be forwarded",
+        ));
+    };
+    let extras = unpack_extra(&packed.value())
+        .map_err(|err| Error::new(packed.span(), format!("malformed `extra`: {}", err)))?
+        .into_iter()
+        .map(|extra| {
+            syn::parse_str::<Lit>(extra.as_str()).map_err(|err| {
+                Error::new(
+                    packed.span(),
+                    format!("malformed `extra`: invalid literal: {}", err),
+                )
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(quote! {
+        #target_path! {
+            #imported_tokens,
+            #(#extras),*
+        }
+    })
+}
+
+/// Used by [`forward_tokens_internal`] when forwarding through a transform.
+///
+/// Applies the transform attribute macro to the item, followed by the hidden
+/// `forward_tokens_transformed` attribute, which then delivers the transformed item to the
+/// target (see [`forward_tokens_transformed_internal`]).
+pub fn forward_tokens_transform_inner_internal<T: Into<TokenStream2>>(
+    tokens: T,
+) -> Result<TokenStream2> {
+    let parsed = parse2::<ForwardedTokens>(tokens.into())?;
+    let target_path = parsed.target_path;
+    let item = parsed.item;
+    let Some(extra) = parsed.extra else {
+        return Err(Error::new(
+            target_path.span(),
+            "missing the transform to forward the tokens through",
+        ));
+    };
+    let malformed = |name: &str, err: Error| {
+        Error::new(
+            extra.span(),
+            format!("malformed `extra`: invalid {}: {}", name, err),
+        )
+    };
+    let (transform, mm_path) = extra
+        .parse_with(|input: ParseStream| {
+            let transform = input
+                .parse::<Path>()
+                .map_err(|err| malformed("transform path", err))?;
+            let mm_path = input
+                .parse::<Comma>()
+                .and_then(|_| input.parse::<Path>())
+                .map_err(|err| malformed("`macro_magic` path", err))?;
+            Ok((transform, mm_path))
+        })
+        .map_err(
+            |err| match err.to_string().starts_with("malformed `extra`") {
+                true => err,
+                false => malformed("transform", err),
+            },
+        )?;
+    Ok(quote! {
+        #[#transform]
+        #[#mm_path::__private::forward_tokens_transformed(#target_path)]
+        #item
+    })
+}
+
+/// The internal implementation for the hidden `#[forward_tokens_transformed]` attribute,
+/// which receives an item after it has been transformed and forwards it to the target macro
+/// specified in `attr`.
+pub fn forward_tokens_transformed_internal<T1: Into<TokenStream2>, T2: Into<TokenStream2>>(
+    attr: T1,
+    tokens: T2,
+) -> Result<TokenStream2> {
+    let target_path = parse2::<Path>(attr.into())?;
+    let tokens = tokens.into();
+    let item = parse2::<Item>(tokens.clone()).map_err(|err| {
+        Error::new(
+            tokens.span(),
+            format!("the transform macro must emit a valid item: {}", err),
+        )
+    })?;
+    Ok(quote! {
+        #target_path! {
+            #item
+        }
+    })
+}
+
+/// The internal implementation for the `forward_tokens_spanned` macro.
+///
+/// Works like [`forward_tokens_internal`], except the forwarded tokens are never re-parsed as
+/// an [`Item`] along the way (see [`forward_tokens_spanned_inner_internal`]). The optional
+/// `extra` argument is not supported.
+pub fn forward_tokens_spanned_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
+    let args = parse2::<ForwardTokensArgs>(tokens.into())?;
+    if let Some(extra) = args.extras.first() {
+        return Err(Error::new(
+            extra.span(),
+            "`forward_tokens_spanned!` does not support forwarding extra data",
+        ));
+    }
+    if let Some(transform) = args.transform {
+        return Err(Error::new(
+            transform.span(),
+            "`forward_tokens_spanned!` does not support forwarding through a transform",
+        ));
+    }
+    let mm_path = match args.mm_path {
+        Some(path) => path,
+        None => macro_magic_root(),
+    };
+    let source_path = export_tokens_macro_path(&args.source)?;
+    let target_path = args.target;
+    Ok(quote! {
+        #source_path! { #target_path, #mm_path::__private::forward_tokens_spanned_inner }
+    })
+}
+
+/// Used by [`forward_tokens_spanned_internal`].
+///
+/// Hands the stored tokens to the target macro exactly as they were received from the
+/// callback, rather than round-tripping them through [`Item`] like
+/// [`forward_tokens_inner_internal`] does.
+pub fn forward_tokens_spanned_inner_internal<T: Into<TokenStream2>>(
+    tokens: T,
+) -> Result<TokenStream2> {
+    let parsed = parse2::<ForwardedTokensSpanned>(tokens.into())?;
+    let target_path = parsed.target_path;
+    let forwarded_tokens = parsed.tokens;
+    Ok(quote! {
+        #target_path! {
+            #forwarded_tokens
+        }
+    })
+}
+
+/// The internal implementation for the `#[derive(ForeignPath)]` derive macro.
+///
+/// Generates a [`ForeignPath`] impl for the struct in `tokens`, returning the field marked
+/// with `#[foreign_path]`, or, if no field is marked, the field named `foreign_path`.
+pub fn derive_foreign_path_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
+    let item_struct = parse2::<ItemStruct>(tokens.into())
+        .map_err(|err| Error::new(err.span(), "`ForeignPath` can only be derived for structs"))?;
+    let marked = item_struct
+        .fields
+        .iter()
+        .enumerate()
+        .filter(|(_, field)| {
+            field
+                .attrs
+                .iter()
+                .any(|attr| attr.path().is_ident("foreign_path"))
+        })
+        .collect::<Vec<_>>();
+    if let Some((_, field)) = marked.get(1) {
+        return Err(Error::new(
+            field.span(),
+            "only one field can be marked with `#[foreign_path]`",
+        ));
+    }
+    let (index, field) = match marked.first() {
+        Some(marked) => *marked,
+        None => item_struct
+            .fields
+            .iter()
+            .enumerate()
+            .find(|(_, field)| matches!(&field.ident, Some(ident) if ident == "foreign_path"))
+            .ok_or_else(|| {
+                Error::new(
+                    item_struct.ident.span(),
+                    "expected a field named `foreign_path` or a field marked with \
+                    `#[foreign_path]`",
+                )
+            })?,
+    };
+    let member = match &field.ident {
+        Some(ident) => quote!(#ident),
+        None => syn::Index::from(index).to_token_stream(),
+    };
+    let mm_path = macro_magic_root();
+    let ident = &item_struct.ident;
+    let (impl_generics, ty_generics, where_clause) = item_struct.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #mm_path::mm_core::ForeignPath for #ident #ty_generics #where_clause {
+            fn foreign_path(&self) -> &#mm_path::__private::syn::Path {
+                &self.#member
+            }
+        }
+    })
+}
+
+/// The item kinds that can be used as keys in `#[with_custom_parsing(kind => Parser, ..)]`.
+const CUSTOM_PARSING_ITEM_KINDS: &[&str] = &[
+    "const", "enum", "fn", "impl", "macro", "mod", "static", "struct", "trait", "type", "union",
+    "use",
+];
+
+/// The identifiers bound by the code [`import_tokens_attr_internal`] generates around the
+/// user's proc macro body, which therefore can't be used as names for its parameters.
+///
+/// `attached_item` and `raw_attr` shadow the parameters before the generated code is done
+/// using them, `__combined_args` and `__output` are used internally, and the rest are the magic
+/// variables documented on `#[import_tokens_attr]`, which are bound alongside the parameters.
+pub const IMPORT_TOKENS_ATTR_RESERVED_IDENTS: &[&str] = &[
+    "attached_item",
+    "raw_attr",
+    "__combined_args",
+    "__output",
+    "__source_path",
+    "__custom_tokens",
+    "__raw_attr",
+    "__foreign_items",
+    "__context",
+];
+
+/// Errors if the specified proc macro parameter is named after one of the
+/// [`IMPORT_TOKENS_ATTR_RESERVED_IDENTS`].
+fn check_reserved_param_ident(ident: &Ident) -> Result<()> {
+    if !IMPORT_TOKENS_ATTR_RESERVED_IDENTS
+        .iter()
+        .any(|reserved| ident == reserved)
+    {
+        return Ok(());
+    }
+    Err(Error::new(
+        ident.span(),
+        format!(
+            "`{}` is reserved by the code generated by `#[import_tokens_attr]`, please rename \
+            this parameter (reserved names: {})",
+            ident,
+            IMPORT_TOKENS_ATTR_RESERVED_IDENTS
+                .iter()
+                .map(|reserved| format!("`{}`", reserved))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    ))
+}
+
+/// Parsed arguments of `#[with_custom_parsing(..)]`.
+///
+/// Either a single parser path (`#[with_custom_parsing(MyParser)]`), used regardless of what
+/// the attribute is attached to, or a list of `kind => Parser` pairs (optionally ending with a
+/// `_ => Parser` fallback) selecting a parser based on the [`item_kind`] of the attached item.
+enum CustomParsingArgs {
+    Single(Path),
+    ByKind(Vec<(String, Path)>, Option<Path>),
+}
+
+impl syn::parse::Parse for CustomParsingArgs {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let fork = input.fork();
+        if fork.parse::<Path>().is_ok() && fork.is_empty() {
+            return Ok(CustomParsingArgs::Single(input.parse()?));
+        }
+        let mut parsers: Vec<(String, Path)> = Vec::new();
+        let mut fallback: Option<Path> = None;
+        while !input.is_empty() {
+            if fallback.is_some() {
+                return Err(input.error("the `_` fallback parser must come last"));
+            }
+            if input.peek(Token![_]) {
+                input.parse::<Token![_]>()?;
+                input.parse::<Token![=>]>()?;
+                fallback = Some(input.parse()?);
+            } else {
+                let kind = input.call(<Ident as syn::ext::IdentExt>::parse_any)?;
+                let kind_str = kind.to_string();
+                if !CUSTOM_PARSING_ITEM_KINDS.contains(&kind_str.as_str()) {
+                    return Err(Error::new(
+                        kind.span(),
+                        format!(
+                            "unknown item kind `{}`, expected one of: {}, or `_`",
+                            kind_str,
+                            CUSTOM_PARSING_ITEM_KINDS.join(", ")
+                        ),
+                    ));
+                }
+                if parsers.iter().any(|(existing, _)| *existing == kind_str) {
+                    return Err(Error::new(
+                        kind.span(),
+                        format!("a parser for `{}` items was already specified", kind_str),
+                    ));
+                }
+                input.parse::<Token![=>]>()?;
+                parsers.push((kind_str, input.parse()?));
+            }
+            if input.is_empty() {
+                break;
+            }
+            input.parse::<Comma>()?;
+        }
+        if parsers.is_empty() && fallback.is_none() {
+            return Err(input.error(
+                "expected a parser path, e.g. `MyParser`, or `kind => Parser` pairs, e.g. \
+                `struct => MyStructParser, fn => MyFnParser`",
+            ));
+        }
+        Ok(CustomParsingArgs::ByKind(parsers, fallback))
+    }
+}
+
+impl CustomParsingArgs {
+    /// Generates statements asserting that each parser implements the traits required by the
+    /// generated code, so that a missing impl is reported at the parser path rather than deep
+    /// within the generated code.
+    fn parser_assertions(&self) -> TokenStream2 {
+        let paths: Vec<&Path> = match self {
+            CustomParsingArgs::Single(path) => alloc::vec![path],
+            CustomParsingArgs::ByKind(parsers, fallback) => parsers
+                .iter()
+                .map(|(_, path)| path)
+                .chain(fallback.iter())
+                .collect(),
+        };
+        let calls = paths
+            .into_iter()
+            .map(|path| quote_spanned!(path.span()=> __assert_custom_parser_impls::<#path>();));
+        quote! {
+            fn __assert_custom_parser_impls<T: ForeignPath + quote::ToTokens + syn::parse::Parse>() {}
+            #(#calls)*
+        }
+    }
+}
+
+/// The internal implementation for the `#[with_custom_parsing(..)` attribute macro.
+///
+/// Note that this implementation just does parsing and re-orders the attributes of the
+/// attached proc macro attribute definition such that the `#[import_tokens_attr]` attribute
+/// comes before this attribute. The real implementation for `#[with_custom_parsing(..)]` can
+/// be found in [`import_tokens_attr_internal`]. The purpose of this is to allow programmers to
+/// use either ordering and still have the proper compiler errors when something is invalid.
+///
+/// `attr` is either a single parser path or a list of `kind => Parser` pairs, optionally ending
+/// with a `_ => Parser` fallback.
+///
+/// The `import_tokens_att_name` argument is used when generating error messages and matching
+/// against the `#[import_tokens_attr]` macro this is to be used with. If you use a
+/// renamed/rebranded version of `#[import_tokens_attr]`, you should change this value to match
+/// the name of your macro.
+pub fn with_custom_parsing_internal<T1: Into<TokenStream2>, T2: Into<TokenStream2>>(
+    attr: T1,
+    tokens: T2,
+    import_tokens_attr_name: &'static str,
+) -> Result<TokenStream2> {
+    // verify that we are attached to a valid #[import_tokens_attr] proc macro def
+    let proc_macro = parse_proc_macro_variant(tokens, ProcMacroType::Attribute)?;
+    if proc_macro
+        .proc_fn
+        .attrs
+        .iter()
+        .find(|attr| {
+            if let Some(seg) = attr.meta.path().segments.last() {
+                return seg.ident == import_tokens_attr_name;
+            }
+            false
+        })
+        .is_none()
+    {
+        return Err(Error::new(
+            Span::call_site(),
+            format!(
+                "Can only be attached to an attribute proc macro marked with `#[{}]`",
+                import_tokens_attr_name
+            ),
+        ));
+    }
+
+    // ensure there is only one `#[with_custom_parsing]`
+    if proc_macro
+        .proc_fn
+        .attrs
+        .iter()
+        .find(|attr| {
+            if let Some(seg) = attr.meta.path().segments.last() {
+                return seg.ident == "with_custom_parsing_internal";
+            }
+            false
+        })
+        .is_some()
+    {
+        return Err(Error::new(
+            Span::call_site(),
+            "Only one instance of #[with_custom_parsing] can be attached at a time.",
+        ));
+    }
+
+    // parse attr to ensure it is a valid parser path or set of parsers
+    let attr = attr.into();
+    parse2::<CustomParsingArgs>(attr.clone())?;
+
+    // emit original item unchanged now that parsing has passed
+    let mut item_fn = proc_macro.proc_fn;
+    item_fn
+        .attrs
+        .push(parse_quote!(#[with_custom_parsing(#attr)]));
+
+    Ok(quote!(#item_fn))
+}
+
+/// Checks that the specified function definition is compatible with `#[import_tokens_attr]`,
+/// i.e. that it is a public `#[proc_macro_attribute]` function taking exactly two arguments,
+/// without performing the full transformation done by [`import_tokens_attr_internal`].
+///
+/// The errors returned are the same ones [`import_tokens_attr_internal`] would produce, which
+/// lets rebranded versions of `#[import_tokens_attr]` validate their input up front.
+pub fn check_import_tokens_attr_compat<T: Into<TokenStream2>>(tokens: T) -> Result<()> {
+    parse_importer_proc_macro_variant(tokens, ProcMacroType::Attribute).map(|_| ())
+}
+
+/// Parses the optional `macro_magic` root override passed to `#[import_tokens_attr]` and
+/// `#[import_tokens_proc]`, falling back to the already resolved `root` when `attr` is empty.
+///
+/// The override is the path at which `macro_magic` can be found from the crate where the
+/// generated proc macro is _used_, so it is only used for the `forward_tokens!` calls emitted
+/// by the generated macro. `__private` paths that are resolved within the proc macro crate
+/// itself always use [`macro_magic_root`].
+fn parse_mm_override_path<T: Into<TokenStream2>>(attr: T, root: &Path) -> Result<Path> {
+    let attr = attr.into();
+    if attr.is_empty() {
+        return Ok(root.clone());
+    }
+    parse2::<Path>(attr).map_err(|err| {
+        Error::new(
+            err.span(),
+            "expected the path to a re-export of `macro_magic`, e.g. `my_crate::macro_magic`",
+        )
+    })
+}
+
+/// Internal implementation for the `#[import_tokens_attr]` attribute.
+///
+/// You shouldn't need to use this directly, but it may be useful if you wish to rebrand/rename
+/// the `#[import_tokens_attr]` macro without extra indirection.
+pub fn import_tokens_attr_internal<T1: Into<TokenStream2>, T2: Into<TokenStream2>>(
+    attr: T1,
+    tokens: T2,
+) -> Result<TokenStream2> {
+    let mm_path = macro_magic_root();
+    let ImportTokensAttrArgs {
+        mm_override_path,
+        dry_run,
+        then,
+    } = syn::parse::Parser::parse2(
+        |input: ParseStream| ImportTokensAttrArgs::parse_with_root(input, || mm_path.clone()),
+        attr.into(),
+    )?;
+    let mut proc_macro = parse_importer_proc_macro_variant(tokens, ProcMacroType::Attribute)?;
+
+    // params
+    let attr_ident = proc_macro.attr_ident.unwrap();
+    let tokens_ident = proc_macro.tokens_ident;
+    check_reserved_param_ident(&attr_ident)?;
+    check_reserved_param_ident(&tokens_ident)?;
+
+    // handle custom parsing, if applicable
+    let path_resolver = if let Some(index) = proc_macro.proc_fn.attrs.iter().position(|attr| {
+        if let Some(seg) = attr.meta.path().segments.last() {
+            return seg.ident == "with_custom_parsing";
+        }
+        false
+    }) {
+        let custom_attr = &proc_macro.proc_fn.attrs[index];
+        let custom_args: CustomParsingArgs = custom_attr.parse_args()?;
+
+        proc_macro.proc_fn.attrs.remove(index);
+        let parser_assertions = custom_args.parser_assertions();
+        let path_resolver = match custom_args {
+            CustomParsingArgs::Single(custom_struct_path) => quote! {
+                let custom_parsed = syn::parse_macro_input!(#attr_ident as #custom_struct_path);
+                let paths = (&custom_parsed as &dyn ForeignPath).foreign_paths();
+                let _ = (&custom_parsed as &dyn quote::ToTokens);
+            },
+            CustomParsingArgs::ByKind(parsers, fallback) => {
+                let arms = parsers.iter().map(|(kind, custom_struct_path)| {
+                    quote! {
+                        #kind => {
+                            let custom_parsed = syn::parse_macro_input!(#attr_ident as #custom_struct_path);
+                            let paths = (&custom_parsed as &dyn ForeignPath).foreign_paths();
+                            (custom_parsed.to_token_stream(), paths)
+                        }
+                    }
+                });
+                let macro_name = proc_macro.proc_fn.sig.ident.to_string();
+                let fallback_arm = match fallback {
+                    Some(custom_struct_path) => quote! {
+                        _ => {
+                            let custom_parsed = syn::parse_macro_input!(#attr_ident as #custom_struct_path);
+                            let paths = (&custom_parsed as &dyn ForeignPath).foreign_paths();
+                            (custom_parsed.to_token_stream(), paths)
+                        }
+                    },
+                    None => quote! {
+                        kind => {
+                            return #mm_path::mm_core::compile_error(
+                                proc_macro2::Span::call_site(),
+                                &format!("`#[{}]` cannot be attached to `{}` items", #macro_name, kind),
+                            ).into();
+                        }
+                    },
+                };
+                quote! {
+                    let (custom_parsed, paths): (TokenStream2, Vec<syn::Path>) = match item_kind(&attached_item) {
+                        #(#arms)*
+                        #fallback_arm
+                    };
+                }
+            }
+        };
+        quote! {
+            #parser_assertions
+            #path_resolver
+        }
+    } else {
+        quote! {
+            let custom_parsed = quote::quote!();
+            let paths: Vec<syn::Path> = match syn::parse::Parser::parse(
+                syn::punctuated::Punctuated::<syn::Path, syn::Token![,]>::parse_separated_nonempty,
+                #attr_ident,
+            ) {
+                Ok(paths) => paths.into_iter().collect(),
+                Err(err) => return err.to_compile_error().into(),
+            };
+        }
+    };
+
+    // outer macro
+    let orig_sig = proc_macro.proc_fn.sig;
+    let orig_stmts = proc_macro.proc_fn.block.stmts;
+    let orig_attrs = proc_macro.proc_fn.attrs;
+
+    // inner macro
+    let inner_macro_ident = format_ident!("__import_tokens_attr_{}_inner", orig_sig.ident);
+    let mut inner_sig = orig_sig.clone();
+    inner_sig.ident = inner_macro_ident.clone();
+    inner_sig.inputs.pop().unwrap();
+
+    let pound = Punct::new('#', Spacing::Alone);
+
+    // in dry run mode the attr is still parsed, but the attached item is emitted unchanged
+    // instead of being forwarded to the inner macro
+    let outer_body = if dry_run {
+        quote! {
+            let _ = (&custom_parsed, &paths, &raw_attr);
+            attached_item.to_token_stream().into()
+        }
+    } else {
+        quote! {
+            let context = ImportTokensAttrContext::new(
+                attached_item,
+                paths,
+                custom_parsed.to_token_stream(),
+                raw_attr,
+            );
+            let Some(path) = context.next_source_path() else {
+                return #mm_path::mm_core::compile_error(
+                    proc_macro2::Span::call_site(),
+                    "expected at least one foreign path",
+                ).into();
+            };
+            let extra = context.to_extra();
+            quote::quote! {
+                #mm_override_path::forward_tokens! {
+                    #pound path,
+                    #inner_macro_ident,
+                    #mm_override_path,
+                    #pound extra
+                }
+            }.into()
+        }
+    };
+
+    // with `then`, the output of the user's body is passed on to the specified attribute macro
+    let inner_body = match then {
+        Some(then) => quote! {
+            // a closure, so early `return`s in the body still produce output for `then`
+            #[allow(clippy::redundant_closure_call)]
+            let __output: #mm_path::__private::TokenStream2 = (move || {
+                #(#orig_stmts)
+                *
+            })().into();
+            #mm_path::__private::quote::quote! {
+                #pound[#then]
+                #pound __output
+            }.into()
+        },
+        None => quote! {
+            #(#orig_stmts)
+            *
+        },
+    };
+
+    // final quoted tokens
+    Ok(quote! {
+        #(#orig_attrs)
+        *
+        pub #orig_sig {
+            use #mm_path::__private::*;
+            use #mm_path::__private::quote::ToTokens;
+            use #mm_path::mm_core::*;
+            let attached_item = syn::parse_macro_input!(#tokens_ident as syn::Item);
+            let raw_attr: TokenStream2 = #attr_ident.clone().into();
+            #path_resolver
+            #outer_body
+        }
+
+        #[doc(hidden)]
+        #[proc_macro]
+        pub #inner_sig {
+            let __combined_args = #mm_path::__private::syn::parse_macro_input!(#attr_ident as #mm_path::mm_core::AttrItemWithExtra);
+            let (#attr_ident, #tokens_ident, __source_path, __custom_tokens, __raw_attr, __foreign_items, __context) = {
+                use #mm_path::__private::*;
+                use #mm_path::__private::quote::ToTokens;
+                let mut context = match #mm_path::mm_core::ImportTokensAttrContext::from_extra(&__combined_args.extra) {
+                    Ok(context) => context,
+                    Err(err) => return err.to_compile_error().into(),
+                };
+                context.foreign_items.push(__combined_args.imported_item);
+                if let Some(next_path) = context.next_source_path() {
+                    let extra = context.to_extra();
+                    return quote::quote! {
+                        #mm_override_path::forward_tokens! {
+                            #pound next_path,
+                            #inner_macro_ident,
+                            #mm_override_path,
+                            #pound extra
+                        }
+                    }.into();
+                }
+                let first_item: proc_macro::TokenStream = context.foreign_items[0].to_token_stream().into();
+                let tokens: proc_macro::TokenStream = context.item.to_token_stream().into();
+                let source_paths = &context.source_paths;
+                let source_path: proc_macro::TokenStream = quote::quote!(#pound(#pound source_paths),*).into();
+                let custom_parsed_tokens: proc_macro::TokenStream = context.custom_parsed.clone().into();
+                let raw_attr: proc_macro::TokenStream = context.raw_attr.clone().into();
+                let foreign_items = context.foreign_items.clone();
+                (first_item, tokens, source_path, custom_parsed_tokens, raw_attr, foreign_items, context)
+            };
+            #inner_body
+        }
+    })
+}
+
+/// Internal implementation for the `#[import_tokens_proc]` attribute.
+///
+/// You shouldn't need to use this directly, but it may be useful if you wish to rebrand/rename
+/// the `#[import_tokens_proc]` macro without extra indirection.
+pub fn import_tokens_proc_internal<T1: Into<TokenStream2>, T2: Into<TokenStream2>>(
+    attr: T1,
+    tokens: T2,
+) -> Result<TokenStream2> {
+    let mm_path = macro_magic_root();
+    let mm_override_path = parse_mm_override_path(attr, &mm_path)?;
+    let proc_macro = parse_importer_proc_macro_variant(tokens, ProcMacroType::Normal)?;
+
+    // outer macro
+    let orig_sig = proc_macro.proc_fn.sig;
+    let orig_stmts = proc_macro.proc_fn.block.stmts;
+    let orig_attrs = proc_macro.proc_fn.attrs;
+
+    // inner macro
+    let inner_macro_ident = format_ident!("__import_tokens_proc_{}_inner", orig_sig.ident);
+    let mut inner_sig = orig_sig.clone();
+    inner_sig.ident = inner_macro_ident.clone();
+    inner_sig.inputs = inner_sig.inputs.iter().rev().cloned().collect();
+
+    // params
+    let tokens_ident = proc_macro.tokens_ident;
+
+    let pound = Punct::new('#', Spacing::Alone);
+
+    // TODO: add support for forwarding source_path for these as well
+
+    Ok(quote! {
+        #(#orig_attrs)
+        *
+        pub #orig_sig {
+            use #mm_path::__private::*;
+            use #mm_path::__private::quote::ToTokens;
+            let source_path = match syn::parse::<syn::Path>(#tokens_ident) {
+                Ok(path) => path,
+                Err(e) => return e.to_compile_error().into(),
+            };
+            quote::quote! {
+                #mm_override_path::forward_tokens! {
+                    #pound source_path,
+                    #inner_macro_ident,
+                    #mm_override_path
+                }
+            }.into()
+        }
+
+        #[doc(hidden)]
+        #[proc_macro]
+        pub #inner_sig {
+            #(#orig_stmts)
+            *
+        }
+    })
+}
+
+/// Returns the first helper attribute declared in the `attributes(..)` list of the
+/// `#[proc_macro_derive]` attribute of the specified derive macro function.
+///
+/// Used by [`import_tokens_derive_internal`] to determine which helper attribute holds the
+/// path of the item to import.
+fn derive_source_helper(proc_fn: &ItemFn) -> Result<Ident> {
+    let Some(derive_attr) = proc_fn
+        .attrs
+        .iter()
+        .find(|attr| attr.path().is_ident("proc_macro_derive"))
+    else {
+        return Err(Error::new(
+            proc_fn.sig.ident.span(),
+            "expected a function definition with #[proc_macro_derive]",
+        ));
+    };
+    let helpers = derive_attr.parse_args_with(|input: ParseStream| {
+        input.parse::<Ident>()?;
+        if input.parse::<Option<Comma>>()?.is_none() || input.is_empty() {
+            return Ok(Vec::new());
+        }
+        let list = input.parse::<syn::MetaList>()?;
+        input.parse::<Option<Comma>>()?;
+        if !list.path.is_ident("attributes") {
+            return Err(Error::new(list.path.span(), "expected `attributes(..)`"));
+        }
+        let helpers = list.parse_args_with(Punctuated::<Ident, Comma>::parse_terminated)?;
+        Ok(helpers.into_iter().collect::<Vec<_>>())
+    })?;
+    match helpers.into_iter().next() {
+        Some(helper) => Ok(helper),
+        None => Err(Error::new(
+            derive_attr.span(),
+            "derive macros that import tokens must declare a helper attribute that specifies \
+            the path of the item to import, e.g. `#[proc_macro_derive(MyDerive, \
+            attributes(my_source))]`",
+        )),
+    }
+}
+
+/// Internal implementation for the `#[import_tokens_derive]` attribute.
+///
+/// Mirrors [`import_tokens_proc_internal`] for `#[proc_macro_derive]` functions. Since the
+/// input of a derive macro is the item being derived rather than a path, the path of the item
+/// to import is read from the first helper attribute declared by the derive, e.g.
+/// `#[derive(MyDerive)] #[my_source(other_crate::SomeItem)] struct Foo;`. The `tokens`
+/// argument of the derive function then contains the tokens of the imported item, and the
+/// tokens of the item being derived are made available via the magic variable
+/// `__derived_item`.
+///
+/// As with [`import_tokens_proc_internal`], an override path for `macro_magic` can be passed
+/// as `attr`.
+pub fn import_tokens_derive_internal<T1: Into<TokenStream2>, T2: Into<TokenStream2>>(
+    attr: T1,
+    tokens: T2,
+) -> Result<TokenStream2> {
+    let mm_path = macro_magic_root();
+    let mm_override_path = parse_mm_override_path(attr, &mm_path)?;
+    let proc_macro = parse_importer_proc_macro_variant(tokens, ProcMacroType::Derive)?;
+    let helper = derive_source_helper(&proc_macro.proc_fn)?.to_string();
+    let missing_helper_msg = format!(
+        "expected a `#[{}(..)]` attribute specifying the path of the item to import",
+        helper
+    );
+
+    // outer macro
+    let orig_sig = proc_macro.proc_fn.sig;
+    let orig_stmts = proc_macro.proc_fn.block.stmts;
+    let orig_attrs = proc_macro.proc_fn.attrs;
+
+    // inner macro
+    let inner_macro_ident = format_ident!("__import_tokens_derive_{}_inner", orig_sig.ident);
+    let mut inner_sig = orig_sig.clone();
+    inner_sig.ident = inner_macro_ident.clone();
+
+    // params
+    let tokens_ident = proc_macro.tokens_ident;
+
+    let pound = Punct::new('#', Spacing::Alone);
+
+    Ok(quote! {
+        #(#orig_attrs)
+        *
+        pub #orig_sig {
+            use #mm_path::__private::*;
+            use #mm_path::__private::quote::ToTokens;
+            let derived_item = match syn::parse::<syn::DeriveInput>(#tokens_ident) {
+                Ok(derived_item) => derived_item,
+                Err(e) => return e.to_compile_error().into(),
+            };
+            let Some(source_attr) = derived_item.attrs.iter().find(|attr| attr.path().is_ident(#helper)) else {
+                return #mm_path::mm_core::compile_error(derived_item.ident.span(), #missing_helper_msg).into();
+            };
+            let source_path = match source_attr.parse_args::<syn::Path>() {
+                Ok(path) => path,
+                Err(e) => return e.to_compile_error().into(),
+            };
+            let derived_item_str = derived_item.to_token_stream().to_string();
+            quote::quote! {
+                #mm_override_path::forward_tokens! {
+                    #pound source_path,
+                    #inner_macro_ident,
+                    #mm_override_path,
+                    #pound derived_item_str
+                }
+            }.into()
+        }
+
+        #[doc(hidden)]
+        #[proc_macro]
+        pub #inner_sig {
+            let (#tokens_ident, __derived_item) = {
+                use #mm_path::__private::*;
+                use #mm_path::__private::quote::ToTokens;
+                let combined = syn::parse_macro_input!(#tokens_ident as #mm_path::mm_core::AttrItemWithExtra);
+                let derived_item: proc_macro::TokenStream = match combined.extra.value().parse() {
+                    Ok(derived_item) => derived_item,
+                    Err(e) => return #mm_path::mm_core::compile_error(combined.extra.span(), &e.to_string()).into(),
+                };
+                let imported_item: proc_macro::TokenStream = combined.imported_item.to_token_stream().into();
+                (imported_item, derived_item)
+            };
+            #(#orig_stmts)
+            *
+        }
+    })
+}
+
+/// Internal implementation for the `#[use_proc]` and `#[use_attr]` attribute macros
+pub fn use_internal<T1: Into<TokenStream2>, T2: Into<TokenStream2>>(
+    attr: T1,
+    tokens: T2,
+    mode: ProcMacroType,
+) -> Result<TokenStream2> {
+    parse2::<Nothing>(attr.into())?;
+    let orig_stmt = parse2::<BasicUseStmt>(tokens.into())?;
+    let orig_path = orig_stmt.path.clone();
+    let orig_attrs = orig_stmt.attrs;
+    let vis = orig_stmt.vis;
+    let ident = &orig_stmt
+        .path
+        .segments
+        .last()
+        .expect("path must have at least one segment")
+        .ident;
+    let hidden_ident = match mode {
+        ProcMacroType::Normal => format_ident!("__import_tokens_proc_{}_inner", ident),
+        ProcMacroType::Attribute => format_ident!("__import_tokens_attr_{}_inner", ident),
+        ProcMacroType::Derive => format_ident!("__import_tokens_derive_{}_inner", ident),
+    };
+    let mut hidden_path: Path = orig_stmt.path.clone();
+    hidden_path.segments.last_mut().unwrap().ident = hidden_ident;
+    Ok(quote! {
+        #(#orig_attrs)
+        *
+        #vis use #orig_path;
+        #[doc(hidden)]
+        #vis use #hidden_path;
+    })
+}
+
+/// If the specified type is an `Option<T>`, returns `T`, otherwise returns `None`.
+///
+/// Only the last path segment is checked, so `Option<T>`, `core::option::Option<T>` and
+/// `std::option::Option<T>` are all detected.
+pub fn option_inner_type(ty: &Type) -> Option<&Type> {
+    let Type::Path(type_path) = ty else {
+        return None;
+    };
+    if type_path.qself.is_some() {
+        return None;
+    }
+    let last_seg = type_path.path.segments.last()?;
+    if last_seg.ident != "Option" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &last_seg.arguments else {
+        return None;
+    };
+    if args.args.len() != 1 {
+        return None;
+    }
+    match args.args.first()? {
+        GenericArgument::Type(inner_ty) => Some(inner_ty),
+        _ => None,
+    }
+}
+
+/// The internal implementation for the `generate_builder` macro.
+///
+/// Forwards the tokens of the specified exported struct to
+/// [`generate_builder_inner_internal`].
+pub fn generate_builder_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
+    let source_path = parse2::<Path>(tokens.into())?;
+    let mm_path = macro_magic_root();
+    Ok(quote! {
+        #mm_path::forward_tokens! {
+            #source_path,
+            #mm_path::__private::generate_builder_inner,
+            #mm_path
+        }
+    })
+}
+
+/// Used by [`generate_builder_internal`]. Expands to a `<Name>Builder` struct for the struct
+/// whose tokens are provided.
+///
+/// Fields whose type is an `Option<T>` are considered optional: their setter takes a `T`, and
+/// they are left as `None` if they are never set. All other fields are required, and `build`
+/// returns an error naming the first required field that was not set.
+///
+/// Fields named `new` or `build` are rejected, since their setters would clash with the
+/// builder's own methods. The generated code refers to prelude items by their full paths, so
+/// it also works in modules that shadow `Option` or `Result`.
+pub fn generate_builder_inner_internal<T: Into<TokenStream2>>(tokens: T) -> Result<TokenStream2> {
+    let item_struct = parse2::<ItemStruct>(tokens.into())?;
+    let Fields::Named(fields) = &item_struct.fields else {
+        return Err(Error::new(
+            item_struct.fields.span(),
+            "`generate_builder!` only supports structs with named fields",
+        ));
+    };
+    let vis = &item_struct.vis;
+    let struct_ident = &item_struct.ident;
+    let builder_ident = format_ident!("{}Builder", struct_ident);
+    let generics = &item_struct.generics;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let mut builder_fields = Vec::new();
+    let mut setters = Vec::new();
+    let mut defaults = Vec::new();
+    let mut builds = Vec::new();
+    for field in &fields.named {
+        let Some(field_ident) = &field.ident else {
+            unreachable!("named fields always have an ident");
+        };
+        if field_ident == "new" || field_ident == "build" {
+            return Err(Error::new(
+                field_ident.span(),
+                format!(
+                    "`generate_builder!` does not support fields named `{}`, since their setter \
+                    would clash with the builder's own `{}` method",
+                    field_ident, field_ident
+                ),
+            ));
+        }
+        let field_ty = &field.ty;
+        defaults.push(quote!(#field_ident: ::core::option::Option::None));
+        if let Some(inner_ty) = option_inner_type(field_ty) {
+            builder_fields.push(quote!(#field_ident: #field_ty));
+            setters.push(quote! {
+                #vis fn #field_ident(mut self, v: #inner_ty) -> Self {
+                    self.#field_ident = ::core::option::Option::Some(v);
+                    self
+                }
+            });
+            builds.push(quote!(#field_ident: self.#field_ident));
+        } else {
+            let missing_msg = format!("missing required field `{}`", field_ident);
+            builder_fields.push(quote!(#field_ident: ::core::option::Option<#field_ty>));
+            setters.push(quote! {
+                #vis fn #field_ident(mut self, v: #field_ty) -> Self {
+                    self.#field_ident = ::core::option::Option::Some(v);
+                    self
+                }
+            });
+            builds.push(quote! {
+                #field_ident: match self.#field_ident {
+                    ::core::option::Option::Some(v) => v,
+                    ::core::option::Option::None => {
+                        return ::core::result::Result::Err(#missing_msg)
+                    }
+                }
+            });
+        }
+    }
+    let builder_doc = format!("A builder for [`{}`].", struct_ident);
+    Ok(quote! {
+        #[doc = #builder_doc]
+        #vis struct #builder_ident #generics #where_clause {
+            #(#builder_fields),*
+        }
+
+        impl #impl_generics #builder_ident #ty_generics #where_clause {
+            /// Creates a new builder with none of the fields set.
+            #vis fn new() -> Self {
+                Self {
+                    #(#defaults),*
+                }
+            }
+
+            #(#setters)*
+
+            /// Builds the struct, failing if any required field was not set.
+            #vis fn build(
+                self,
+            ) -> ::core::result::Result<#struct_ident #ty_generics, &'static str> {
+                ::core::result::Result::Ok(#struct_ident {
+                    #(#builds),*
+                })
+            }
+        }
+
+        impl #impl_generics ::core::default::Default for #builder_ident #ty_generics #where_clause {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_tokens_internal_missing_ident() {
+        assert!(
+            export_tokens_internal(quote!(), quote!(impl MyTrait for Something), true).is_err()
+        );
+    }
+
+    #[test]
+    fn export_tokens_internal_macro_rules() {
+        let item = quote! {
+            macro_rules! sum_all {
+                ($($x:expr),*) => {
+                    0 $(+ $x)*
+                };
+            }
+        };
+        let tokens = export_tokens_internal(quote!(), item.clone(), true).unwrap();
+        let file = parse2::<syn::File>(tokens).unwrap();
+        let Item::Macro(export) = &file.items[0] else {
+            panic!("expected the callback macro to be emitted first");
+        };
+        assert_eq!(export.ident.as_ref().unwrap(), "__export_tokens_tt_sum_all");
+        // stored as a literal so the `$`s never reach the callback macro's transcribers
+        let stored = parse2::<LitStr>(export_tokens_stored(&item)).unwrap();
+        let restored =
+            syn::parse::Parser::parse2(parse_exported_item, stored.to_token_stream()).unwrap();
+        assert!(tokens_eq_ignoring_spans(&restored.to_token_stream(), &item));
+        let err = export_tokens_internal(quote!(), quote!(my_macro! { a b c }), true).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("macro invocations have no inherent name"));
+        assert!(export_tokens_internal(quote!(my_name), quote!(my_macro! { a b c }), true).is_ok());
+    }
+
+    #[test]
+    fn export_tokens_internal_foreign_mod() {
+        let item = quote! {
+            extern "C" {
+                fn abs(input: i32) -> i32;
+            }
+        };
+        let err = export_tokens_internal(quote!(), item.clone(), true).unwrap_err();
+        assert!(err.to_string().contains("explicit one must be provided"));
+        let tokens = export_tokens_internal(quote!(c_bindings), item.clone(), true).unwrap();
+        let file = parse2::<syn::File>(tokens).unwrap();
+        let mut found_macro = false;
+        let mut found_foreign_mod = false;
+        for item in file.items {
+            match item {
+                Item::Macro(item_macro) => {
+                    found_macro = item_macro
+                        .mac
+                        .tokens
+                        .to_string()
+                        .contains("(@ ident) => { c_bindings }");
+                }
+                Item::ForeignMod(_) => found_foreign_mod = true,
+                _ => (),
+            }
+        }
+        assert!(found_macro && found_foreign_mod);
+        #[cfg(not(feature = "compress"))]
+        {
+            let imported = import_tokens_inner_internal(quote!(tokens, #item)).unwrap();
+            assert!(imported.to_string().contains("extern \\\"C\\\" { fn abs"));
+        }
+    }
+
+    #[test]
+    fn supported_export_item_kinds_match_item_ident() {
+        let items: [Item; 16] = [
+            parse_quote!(
+                const A: u8 = 0;
+            ),
+            parse_quote!(
+                enum A {}
+            ),
+            parse_quote!(
+                extern crate a;
+            ),
+            parse_quote!(
+                fn a() {}
+            ),
+            parse_quote!(
+                extern "C" {}
+            ),
+            parse_quote!(impl A {}),
+            parse_quote!(
+                macro_rules! a {
+                    () => {};
+                }
+            ),
+            parse_quote!(
+                mod a {}
+            ),
+            parse_quote!(
+                static A: u8 = 0;
+            ),
+            parse_quote!(
+                struct A;
+            ),
+            parse_quote!(
+                trait A {}
+            ),
+            parse_quote!(
+                trait A = B;
+            ),
+            parse_quote!(
+                type A = u8;
+            ),
+            parse_quote!(union A { a: u8 }),
+            parse_quote!(
+                use a::b;
+            ),
+            parse_quote!(
+                fn a();
+            ),
+        ];
+        let mut kinds = Vec::new();
+        for item in &items {
+            let kind = item_kind(item);
+            assert_eq!(
+                to_snake_case(item_kind_variant(item)).replace('_', " "),
+                kind
+            );
+            assert_eq!(
+                item_ident(item).is_some(),
+                supported_export_item_kinds().contains(&kind),
+                "{}",
+                kind
+            );
+            if item_ident(item).is_some() {
+                kinds.push(kind);
+            }
+        }
+        assert_eq!(kinds, supported_export_item_kinds());
+        let err = export_tokens_internal(
+            quote!(),
+            quote!(
+                use a::b;
+            ),
+            true,
+        )
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("without one: const, enum, extern crate, fn"));
+    }
+
+    #[test]
+    fn export_tokens_internal_verbatim() {
+        let item = quote!(
+            fn no_body(x: u32) -> u32;
+        );
+        assert!(matches!(
+            parse2::<Item>(item.clone()).unwrap(),
+            Item::Verbatim(_)
+        ));
+        let err = export_tokens_internal(quote!(), item.clone(), false).unwrap_err();
+        assert!(err.to_string().contains("explicit one must be provided"));
+        let tokens = export_tokens_internal(quote!(verbatim_blob), item.clone(), false).unwrap();
+        assert!(tokens
+            .to_string()
+            .contains("__export_tokens_tt_verbatim_blob"));
+        #[cfg(not(feature = "compress"))]
+        assert!(tokens.to_string().contains(item.to_string().as_str()));
+    }
+
+    #[test]
+    fn export_tokens_internal_normal_no_ident() {
+        assert!(export_tokens_internal(
+            quote!(),
+            quote!(
+                struct MyStruct {}
+            ),
+            true
+        )
+        .unwrap()
+        .to_string()
+        .contains("my_struct"));
+    }
+
+    #[test]
+    fn export_tokens_internal_normal_ident() {
+        assert!(export_tokens_internal(
+            quote!(some_name),
+            quote!(
+                struct Something {}
+            ),
+            true,
+        )
+        .unwrap()
+        .to_string()
+        .contains("some_name"));
+    }
+
+    #[test]
+    fn export_tokens_internal_generics_no_ident() {
+        assert!(export_tokens_internal(
+            quote!(),
+            quote!(
+                struct MyStruct<T> {}
+            ),
+            true,
+        )
+        .unwrap()
+        .to_string()
+        .contains("__export_tokens_tt_my_struct"));
+    }
+
+    #[test]
+    fn export_tokens_internal_bad_ident() {
+        assert!(export_tokens_internal(
+            quote!(Something<T>),
+            quote!(
+                struct MyStruct {}
+            ),
+            true,
+        )
+        .is_err());
+        assert!(export_tokens_internal(
+            quote!(some::path),
+            quote!(
+                struct MyStruct {}
+            ),
+            true,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_export_tokens_no_emit() {
+        assert!(export_tokens_internal(
+            quote!(some_name),
+            quote!(
+                struct Something {}
+            ),
+            false,
+        )
+        .unwrap()
+        .to_string()
+        .contains("some_name"));
+    }
+
+    #[test]
+    fn import_tokens_internal_simple_path() {
+        assert!(
+            import_tokens_internal(quote!(let tokens = my_crate::SomethingCool))
+                .unwrap()
+                .to_string()
+                .contains("__export_tokens_tt_something_cool")
+        );
+    }
+
+    #[test]
+    fn import_tokens_internal_flatten_long_paths() {
+        assert!(import_tokens_internal(
+            quote!(let tokens = my_crate::some_mod::complex::SomethingElse)
+        )
+        .unwrap()
+        .to_string()
+        .contains("__export_tokens_tt_something_else"));
+    }
+
+    #[test]
+    fn import_tokens_internal_extend() {
+        let tokens = import_tokens_internal(quote!(extend buf = my_crate::Foo))
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("__export_tokens_tt_foo ! { buf ,"));
+        assert!(tokens.contains("import_tokens_extend_inner"));
+        let imported = import_tokens_extend_inner_internal(quote!(buf, struct Foo;))
+            .unwrap()
+            .to_string();
+        assert!(imported.starts_with("buf . extend (\"struct Foo ;\" . parse ::"));
+        assert!(import_tokens_internal(quote!(extend = my_crate::Foo)).is_err());
+    }
+
+    #[test]
+    fn import_tokens_internal_invalid_token_ident() {
+        assert!(import_tokens_internal(quote!(let 3 * 2 = my_crate::something)).is_err());
+    }
+
+    #[test]
+    fn import_tokens_min_version() {
+        let tokens = export_tokens_internal(
+            quote!(version = 2),
+            quote!(
+                struct Foo;
+            ),
+            true,
+        )
+        .unwrap()
+        .to_string();
+        assert!(tokens.contains("(@ version , $ ($ callback_root : ident) ? $ (:: $ callback : ident) * , $ ($ args : tt) *) => { $ ($ callback_root) ? $ (:: $ callback) * ! { 2 , $ ($ args) * } }"));
+        // unversioned exports default to version 0
+        let tokens = export_tokens_internal(
+            quote!(),
+            quote!(
+                struct Foo;
+            ),
+            true,
+        )
+        .unwrap()
+        .to_string();
+        assert!(tokens.contains("! { 0 , $ ($ args) * }"));
+        assert!(export_tokens_internal(
+            quote!(version = -1),
+            quote!(
+                struct Foo;
+            ),
+            true
+        )
+        .is_err());
+        assert!(export_tokens_internal(
+            quote!(version = 1, version = 2),
+            quote!(
+                struct Foo;
+            ),
+            true
+        )
+        .is_err());
+
+        let tokens = import_tokens_internal(quote!(let tokens = my_crate::Foo, min_version = 2))
+            .unwrap()
+            .to_string();
+        assert!(tokens.starts_with(
+            format!(
+                "my_crate :: __export_tokens_tt_foo ! {{ @ version , {} :: __private :: import_tokens_version_check , 2 , \"my_crate :: Foo\" }}",
+                macro_magic_root().to_token_stream()
+            )
+            .as_str()
+        ));
+        assert!(!import_tokens_internal(quote!(let tokens = my_crate::Foo))
+            .unwrap()
+            .to_string()
+            .contains("@ version"));
+        assert!(import_tokens_internal(quote!(let tokens = Foo, max_version = 2)).is_err());
+
+        assert!(import_tokens_version_check_internal(quote!(2, 2, "Foo"))
+            .unwrap()
+            .is_empty());
+        assert!(import_tokens_version_check_internal(quote!(3, 2, "Foo"))
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            import_tokens_version_check_internal(quote!(0, 2, "Foo"))
+                .unwrap_err()
+                .to_string(),
+            "`Foo` was exported with version 0, but at least version 2 is required"
+        );
+    }
+
+    #[test]
+    fn import_tokens_field_internal_paths() {
+        let tokens = import_tokens_field_internal(quote!(my_crate::a::MyStruct::field_name))
+            .unwrap()
+            .to_string();
+        assert!(tokens.starts_with("my_crate :: __export_tokens_tt_my_struct ! { field_name ,"));
+        assert!(tokens.ends_with("import_tokens_field_inner }"));
+        let tokens = import_tokens_field_internal(quote!(MyStruct::r#type))
+            .unwrap()
+            .to_string();
+        assert!(tokens.starts_with("__export_tokens_tt_my_struct ! { r#type ,"));
+        assert!(import_tokens_field_internal(quote!(MyStruct)).is_err());
+        assert!(import_tokens_field_internal(quote!(MyStruct::field<T>)).is_err());
+    }
+
+    #[test]
+    fn import_tokens_field_inner_internal_extraction() {
+        let item = quote! {
+            struct MyStruct {
+                #[some_attr]
+                pub first: u8,
+                second: Option<Vec<u32>>,
+            }
+        };
+        let tokens = import_tokens_field_inner_internal(quote!(second, #item))
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("\"second : Option < Vec < u32 > >\""));
+        let tokens = import_tokens_field_inner_internal(quote!(first, #item))
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("\"first : u8\""));
+        let err = import_tokens_field_inner_internal(quote!(third, #item)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`MyStruct` has no field named `third` (available fields: `first`, `second`)"
+        );
+        let err = import_tokens_field_inner_internal(quote!(first, struct Tuple(u8);)).unwrap_err();
+        assert_eq!(err.to_string(), "`Tuple` has no named fields");
+        let err = import_tokens_field_inner_internal(quote!(first, fn first() {})).unwrap_err();
+        assert!(err.to_string().contains("the exported item is a `fn`"));
+    }
+
+    #[test]
+    fn forwarded_tokens_round_trip() {
+        let item: Item = parse_quote!(
+            struct Foo(u8);
+        );
+        for extra in [None, Some(LitStr::new("some extra", Span::call_site()))] {
+            let forwarded = ForwardedTokens::new(parse_quote!(a::b), item.clone(), extra);
+            let tokens = forwarded.to_token_stream();
+            let reparsed = parse2::<ForwardedTokens>(tokens.clone()).unwrap();
+            assert!(tokens_eq_ignoring_spans(
+                &reparsed.target_path.to_token_stream(),
+                &forwarded.target_path.to_token_stream()
+            ));
+            assert!(tokens_eq_ignoring_spans(
+                &reparsed.item.to_token_stream(),
+                &forwarded.item.to_token_stream()
+            ));
+            assert_eq!(
+                reparsed.extra.as_ref().map(|extra| extra.value()),
+                forwarded.extra.as_ref().map(|extra| extra.value())
+            );
+            assert_eq!(reparsed.to_token_stream().to_string(), tokens.to_string());
+        }
+        let tokens = quote!(a::b, struct Foo(u8);, "some extra");
+        let parsed = parse2::<ForwardedTokens>(tokens.clone()).unwrap();
+        assert_eq!(parsed.to_token_stream().to_string(), tokens.to_string());
+    }
+
+    #[test]
+    fn attr_item_with_extra_round_trip() {
+        let item: Item = parse_quote!(
+            fn foo() {}
+        );
+        let extra = LitStr::new("packed", Span::call_site());
+        let with_extra = AttrItemWithExtra::new(item, extra);
+        let tokens = with_extra.to_token_stream();
+        assert_eq!(tokens.to_string(), "fn foo () { } , \"packed\"");
+        let reparsed = parse2::<AttrItemWithExtra>(tokens.clone()).unwrap();
+        assert!(tokens_eq_ignoring_spans(
+            &reparsed.imported_item.to_token_stream(),
+            &with_extra.imported_item.to_token_stream()
+        ));
+        assert_eq!(reparsed.extra.value(), with_extra.extra.value());
+        assert_eq!(reparsed.to_token_stream().to_string(), tokens.to_string());
+    }
+
+    #[test]
+    fn export_tokens_internal_trait_alias() {
+        let item = quote!(
+            pub trait ShareAlias<T> = Send + Sync + AsRef<T>;
+        );
+        let tokens = export_tokens_internal(quote!(with_generics), item.clone(), false)
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("macro_rules ! __export_tokens_tt_share_alias"));
+        assert!(tokens.contains("(@ kind) => { \"trait alias\" }"));
+        assert!(tokens.contains("(@ generics"));
+        let imported = import_tokens_inner_internal(quote!(tokens, #item)).unwrap();
+        assert!(imported
+            .to_string()
+            .contains("\"pub trait ShareAlias < T > = Send + Sync + AsRef < T > ;\""));
+        let Item::TraitAlias(alias) = parse2::<Item>(item).unwrap() else {
+            panic!("expected a trait alias");
+        };
+        assert_eq!(alias.bounds.len(), 3);
+    }
+
+    #[test]
+    fn export_tokens_internal_local() {
+        let item = quote!(
+            struct Foo;
+        );
+        let tokens = export_tokens_internal(quote!(local, hash), item.clone(), true)
+            .unwrap()
+            .to_string();
+        assert!(!tokens.contains("macro_export"));
+        assert_eq!(tokens.matches("# [allow (unused_macros)]").count(), 2);
+        let tokens = export_tokens_internal(quote!(), item, true)
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("# [macro_export]"));
+        assert!(!tokens.contains("unused_macros"));
+        let tokens = import_tokens_internal(quote!(let tokens = my_crate::a::Foo, local))
+            .unwrap()
+            .to_string();
+        assert!(tokens.starts_with("__export_tokens_tt_foo !"));
+        let tokens = import_tokens_internal(quote!(let tokens = Foo, prefix = "__x_", local))
+            .unwrap()
+            .to_string();
+        assert!(tokens.starts_with("__x_foo !"));
+        assert!(import_tokens_internal(quote!(let tokens = Foo, local, local)).is_err());
+    }
+
+    #[test]
+    fn estimate_export_size_scales() {
+        let small = estimate_export_size(
+            quote!(),
+            quote! {
+                fn add_one(n: u32) -> u32 {
+                    n + 1
+                }
+            },
+        )
+        .unwrap();
+        assert!(small > 0);
+        let large = estimate_export_size(
+            quote!(),
+            quote! {
+                fn add_one(n: u32) -> u32 {
+                    let doubled = n * 2;
+                    let halved = doubled / 2;
+                    halved + 1
+                }
+            },
+        )
+        .unwrap();
+        assert!(large > small);
+        // the item itself isn't counted, even if it is emitted
+        let emitted = estimate_export_size(
+            quote!(emit),
+            quote! {
+                fn add_one(n: u32) -> u32 {
+                    n + 1
+                }
+            },
+        )
+        .unwrap();
+        assert_eq!(emitted, small);
+        // additional callback macros are counted
+        let hashed = estimate_export_size(
+            quote!(hash),
+            quote! {
+                fn add_one(n: u32) -> u32 {
+                    n + 1
+                }
+            },
+        )
+        .unwrap();
+        assert!(hashed > small);
+        assert!(estimate_export_size(quote!(), quote!(impl Foo {})).is_err());
+    }
+
+    #[test]
+    fn export_tokens_verbatim_source() {
+        let item = quote!(
+            struct Foo;
+        );
+        let options = ExportTokensOptions::new().source_text("struct Foo; // a comment");
+        let tokens = export_tokens_with_options(item.clone(), options)
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("(@ source) => { \"struct Foo; // a comment\" } ;"));
+        // without any source text available, the stringified tokens are stored
+        let tokens = export_tokens_internal(quote!(verbatim_source), item.clone(), true)
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("(@ source) =>"));
+        let tokens = export_tokens_internal(quote!(), item, true)
+            .unwrap()
+            .to_string();
+        assert!(!tokens.contains("@ source"));
+        let tokens = import_tokens_str_internal(quote!(a::Foo, verbatim_source))
+            .unwrap()
+            .to_string();
+        assert_eq!(tokens, "a :: __export_tokens_tt_foo ! { @ source }");
+        let tokens = import_tokens_str_internal(quote!(a::Foo))
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("import_tokens_str_inner"));
+        assert!(import_tokens_str_internal(quote!(a::Foo, verbatim)).is_err());
+    }
+
+    #[test]
+    fn export_tokens_internal_stacked() {
+        let item = quote! {
+            #[export_tokens]
+            struct Foo;
+        };
+        let err = export_tokens_internal(quote!(), item.clone(), true).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`#[export_tokens]` is applied more than once to this item, which would export it \
+            twice under the same name `Foo`; remove one of the attributes or give one of them a \
+            different name"
+        );
+        let err = export_tokens_internal(
+            quote!(),
+            quote! {
+                #[macro_magic::export_tokens_no_emit(foo)]
+                struct Foo;
+            },
+            true,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("applied more than once"));
+        // exporting under a different name is fine
+        assert!(export_tokens_internal(quote!(OtherFoo), item, true).is_ok());
+        assert!(export_tokens_internal(
+            quote!(),
+            quote! {
+                #[export_tokens(other_foo)]
+                struct Foo;
+            },
+            true
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn export_tokens_internal_cfg() {
+        let item = quote!(
+            struct Foo;
+        );
+        let tokens =
+            export_tokens_internal(quote!(cfg(feature = "extra"), hash), item.clone(), true)
+                .unwrap()
+                .to_string();
+        assert_eq!(
+            tokens
+                .matches("# [macro_export] # [cfg (feature = \"extra\")] macro_rules !")
+                .count(),
+            2
+        );
+        // the item itself and its const aren't gated
+        assert_eq!(tokens.matches("# [cfg").count(), 2);
+        assert!(tokens.contains("struct Foo ;"));
+        let tokens = export_tokens_internal(quote!(cfg(not(test)), local), item.clone(), true)
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("# [allow (unused_macros)] # [cfg (not (test))] macro_rules !"));
+        let tokens = export_tokens_internal(quote!(), item.clone(), true)
+            .unwrap()
+            .to_string();
+        assert!(!tokens.contains("# [cfg"));
+        assert!(export_tokens_internal(quote!(cfg(a), cfg(b)), item.clone(), true).is_err());
+        assert!(export_tokens_internal(quote!(cfg = "a"), item, true).is_err());
+    }
+
+    #[test]
+    fn import_tokens_internal_friendly_error() {
+        let expected = "expected `let <ident> = <path>` or `extend <ident> = <path>`, \
+            optionally followed by `as <new name>`, `, min_version = <version>`, \
+            `, prefix = \"<prefix>\"`, `, local` and/or `, strip = [<attr>, ..]`, e.g. \
+            `import_tokens!(let tokens = my_crate::SomeItem)`";
+        for tokens in [
+            quote!(let 3 * 2 = my_crate::something),
+            quote!(my_tokens = my_crate::something),
+            quote!(let my_tokens my_crate::something),
+            quote!(let my_tokens = my_crate::something extra),
+            quote!(),
+        ] {
+            let err = import_tokens_internal(tokens).unwrap_err();
+            assert_eq!(err.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn import_tokens_internal_invalid_path() {
+        assert!(import_tokens_internal(quote!(let my_tokens = 2 - 2)).is_err());
+    }
+
+    #[test]
+    fn import_tokens_inner_internal_basic() {
+        assert!(import_tokens_inner_internal(quote! {
+            my_ident,
+            fn my_function() -> u32 {
+                33
+            }
+        })
+        .unwrap()
+        .to_string()
+        .contains("my_ident"));
+    }
+
+    #[test]
+    fn import_tokens_inner_internal_impl() {
+        assert!(import_tokens_inner_internal(quote! {
+            another_ident,
+            impl Something for MyThing {
+                fn something() -> CoolStuff {
+                    CoolStuff {}
+                }
+            }
+        })
+        .unwrap()
+        .to_string()
+        .contains("something ()"));
+    }
+
+    #[test]
+    fn import_tokens_internal_strip() {
+        let tokens =
+            import_tokens_internal(quote!(let tokens = my_crate::Item, strip = [serde, doc]))
+                .unwrap()
+                .to_string();
+        assert!(tokens.ends_with(
+            "{ tokens , :: macro_magic :: __private :: import_tokens_inner , \"serde , doc~~\" }"
+        ));
+        let tokens = import_tokens_internal(quote!(let tokens = my_crate::Item, strip = []))
+            .unwrap()
+            .to_string();
+        assert!(tokens.ends_with("import_tokens_inner }"));
+        assert!(
+            import_tokens_internal(quote!(let tokens = Item, strip = [a], strip = [b])).is_err()
+        );
+        assert!(import_tokens_internal(quote!(let tokens = Item, strip = serde)).is_err());
+    }
+
+    #[test]
+    fn import_tokens_internal_rename() {
+        let tokens = import_tokens_internal(quote!(let tokens = my_crate::Foo as Bar))
+            .unwrap()
+            .to_string();
+        assert!(tokens.ends_with("import_tokens_inner , \"~~Bar\" }"));
+        let tokens = import_tokens_internal(quote!(let tokens = Foo as Bar, strip = [doc]))
+            .unwrap()
+            .to_string();
+        assert!(tokens.ends_with("import_tokens_inner , \"doc~~Bar\" }"));
+        assert!(import_tokens_internal(quote!(let tokens = Foo as)).is_err());
+        let renamed = import_tokens_inner_internal(quote! {
+            tokens,
+            /// Docs
+            struct Foo {
+                foo: Foo,
+            },
+            "doc~~Bar"
+        })
+        .unwrap()
+        .to_string();
+        assert!(renamed.contains("\"struct Bar { foo : Foo , }\""));
+        let err = import_tokens_inner_internal(quote!(tokens, impl Foo {}, "~~Bar"))
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "cannot rename the imported item to `Bar`, since `impl` items have no name"
+        );
+        let err = import_tokens_inner_internal(quote!(tokens, struct Foo;, "~~Bar~~Baz"))
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "malformed `extra`: expected 2 fields but found 3");
+        let err = import_tokens_inner_internal(quote!(tokens, struct Foo;, "doc"))
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "malformed `extra`: expected 2 fields but found 1");
+    }
+
+    #[test]
+    fn import_tokens_inner_internal_strip() {
+        let item = quote! {
+            /// Some docs
+            #[derive(Serialize)]
+            #[serde(rename_all = "camelCase")]
+            pub struct Foo {
+                #[serde(skip)]
+                #[other::serde]
+                field: u32,
+            }
+        };
+        let tokens = import_tokens_inner_internal(quote!(tokens, #item, "serde, doc~~"))
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains(
+            "\"# [derive (Serialize)] pub struct Foo { # [other :: serde] field : u32 , }\""
+        ));
+        // compressed tokens are stripped just the same
+        let stored = LitStr::new(&compress_tokens(item.clone()), Span::call_site());
+        assert_eq!(
+            import_tokens_inner_internal(quote!(tokens, #stored, "serde, doc~~"))
+                .unwrap()
+                .to_string(),
+            tokens
+        );
+        // as forwarded through an `expr` fragment
+        let extra = proc_macro2::Group::new(Delimiter::None, quote!("serde, doc~~"));
+        assert_eq!(
+            import_tokens_inner_internal(quote!(tokens, #item, #extra))
+                .unwrap()
+                .to_string(),
+            tokens
+        );
+        let tokens = import_tokens_inner_internal(quote!(tokens, #stored))
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("serde (skip)"));
+        assert!(import_tokens_inner_internal(quote!(tokens, #item, "not a path!~~")).is_err());
+        let stripped = strip_attributes(
+            quote!(
+                mod foo {
+                    #![allow(unused)]
+                    #![doc = "inner"]
+                    fn bar() {}
+                }
+            ),
+            &[parse_quote!(doc)],
+        );
+        assert_eq!(
+            stripped.to_string(),
+            "mod foo { # ! [allow (unused)] fn bar () { } }"
+        );
+    }
+
+    #[test]
+    fn import_tokens_inner_internal_missing_comma() {
+        assert!(import_tokens_inner_internal(quote! {
+            {
+                another_ident
+                impl Something for MyThing {
+                    fn something() -> CoolStuff {
+                        CoolStuff {}
+                    }
+                }
+            }
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn import_tokens_inner_internal_non_item() {
+        assert!(import_tokens_inner_internal(quote! {
+            {
+                another_ident,
+                2 + 2
+            }
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_use_stmt() {
+        assert!(use_internal(
+            quote!(),
+            quote!(
+                use some::path;
+            ),
+            ProcMacroType::Attribute,
+        )
+        .is_ok());
+        assert!(use_internal(
+            quote!(),
+            quote!(
+                use some::path
+            ),
+            ProcMacroType::Normal,
+        )
+        .is_err());
+        assert!(use_internal(
+            quote!(),
+            quote!(
+                use some::
+            ),
+            ProcMacroType::Attribute,
+        )
+        .is_err());
+        assert!(use_internal(
+            quote!(),
+            quote!(
+                pub use some::long::path;
+            ),
+            ProcMacroType::Attribute,
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn compress_tokens_round_trip() {
+        for input in [
+            "",
+            "a",
+            "`",
+            "``` `` `",
+            "struct Foo { a : u32, b : u32, c : u32, d : u32, e : u32 }",
+            "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+            "fn 🪄() { \"🪄🪄🪄🪄🪄🪄🪄🪄🪄🪄🪄🪄\" ; `` }",
+        ] {
+            assert_eq!(decompress_tokens(compress_tokens(input)).unwrap(), input);
+        }
+    }
+
+    #[test]
+    fn decompress_tokens_invalid() {
+        assert!(decompress_tokens("`").is_err());
+        assert!(decompress_tokens("`AB").is_err());
+        assert!(decompress_tokens("`ABAB").is_err());
+        assert!(decompress_tokens("ab`A!AB").is_err());
+    }
+
+    #[test]
+    fn compress_tokens_large_item() {
+        let fields = (0..300usize).map(|i| format_ident!("field_{}", i));
+        let item = quote! {
+            pub struct SomeLargeStruct {
+                #(pub #fields: Option<alloc::vec::Vec<u32>>),*
+            }
+        };
+        let uncompressed = item.to_string();
+        let compressed = compress_tokens(&uncompressed);
+        assert!(compressed.len() * 4 < uncompressed.len());
+        assert_eq!(decompress_tokens(&compressed).unwrap(), uncompressed);
+
+        // importing the compressed form yields the same tokens as importing the raw item
+        let compressed = LitStr::new(compressed.as_str(), Span::call_site());
+        assert_eq!(
+            import_tokens_inner_internal(quote!(tokens, #compressed))
+                .unwrap()
+                .to_string(),
+            import_tokens_inner_internal(quote!(tokens, #item))
+                .unwrap()
+                .to_string(),
+        );
+    }
+
+    #[cfg(feature = "compress")]
+    #[test]
+    fn export_tokens_internal_compressed() {
+        let fields = (0..300usize).map(|i| format_ident!("field_{}", i));
+        let item = quote! {
+            struct SomeLargeStruct {
+                #(#fields: u32),*
+            }
+        };
+        let output = export_tokens_internal(quote!(), item.clone(), false)
+            .unwrap()
+            .to_string();
+        assert!(output.contains("__export_tokens_tt_some_large_struct"));
+        assert!(!output.contains("field_299"));
+        assert!(output.len() < item.to_string().len());
+        // when emitted, the item itself is the only uncompressed copy of the tokens
+        let output = export_tokens_internal(quote!(), item.clone(), true)
+            .unwrap()
+            .to_string();
+        assert_eq!(output.matches("field_299").count(), 1);
+        assert!(!output.contains("__EXPORT_TOKENS__"));
+        assert!(output.len() < item.to_string().len() * 2);
+    }
+
+    #[test]
+    fn forward_tokens_spanned_internal_basic() {
+        let tokens = forward_tokens_spanned_internal(quote!(my_crate::SomeStruct, receiver))
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("my_crate :: __export_tokens_tt_some_struct"));
+        assert!(tokens.contains("forward_tokens_spanned_inner"));
+        assert!(forward_tokens_spanned_internal(quote!(SomeStruct, receiver, mm, "a")).is_err());
+    }
+
+    #[test]
+    fn forward_tokens_spanned_inner_internal_raw_tokens() {
+        let tokens = forward_tokens_spanned_inner_internal(quote! {
+            receiver,
+            struct Foo {
+                field: u32,
+            }
+        })
+        .unwrap();
+        assert_eq!(
+            tokens.to_string(),
+            "receiver ! { struct Foo { field : u32 , } }"
+        );
+    }
+
+    #[test]
+    fn option_inner_type_detection() {
+        let ty: Type = parse_quote!(Option<u32>);
+        assert_eq!(
+            option_inner_type(&ty)
+                .unwrap()
+                .to_token_stream()
+                .to_string(),
+            "u32"
+        );
+        let ty: Type = parse_quote!(core::option::Option<Vec<u8>>);
+        assert!(option_inner_type(&ty).is_some());
+        let ty: Type = parse_quote!(Vec<u32>);
+        assert!(option_inner_type(&ty).is_none());
+        let ty: Type = parse_quote!(Option);
+        assert!(option_inner_type(&ty).is_none());
+    }
+
+    #[test]
+    fn generate_builder_inner_internal_fields() {
+        let tokens = generate_builder_inner_internal(quote! {
+            pub struct Foo {
+                required: u32,
+                optional: Option<bool>,
+            }
+        })
+        .unwrap()
+        .to_string();
+        assert!(tokens.contains("pub struct FooBuilder"));
+        assert!(tokens.contains("required : :: core :: option :: Option < u32 >"));
+        assert!(tokens.contains("optional : Option < bool >"));
+        assert!(tokens.contains("pub fn optional (mut self , v : bool)"));
+        assert!(tokens.contains("\"missing required field `required`\""));
+        assert!(!tokens.contains("missing required field `optional`"));
+        assert!(generate_builder_inner_internal(quote!(
+            struct Foo(u32);
+        ))
+        .is_err());
+        assert!(generate_builder_inner_internal(quote!(
+            fn foo() {}
+        ))
+        .is_err());
+        for name in ["new", "build"] {
+            let field = format_ident!("{}", name);
+            let err = generate_builder_inner_internal(quote! {
+                struct Foo {
+                    #field: u32,
+                }
+            })
+            .unwrap_err()
+            .to_string();
+            assert_eq!(
+                err,
+                format!(
+                    "`generate_builder!` does not support fields named `{0}`, since their \
+                    setter would clash with the builder's own `{0}` method",
+                    name
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn import_tokens_env_internal_lookup() {
+        let tokens = import_tokens_env_internal(quote!(let tokens = SOME_VAR), |key| {
+            assert_eq!(key, "SOME_VAR");
+            Some("my_crate::SomeStruct".to_string())
+        })
+        .unwrap()
+        .to_string();
+        assert!(tokens.contains("my_crate :: __export_tokens_tt_some_struct ! { tokens ,"));
+        let err = import_tokens_env_internal(quote!(let tokens = SOME_VAR), |_| None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "environment variable `SOME_VAR` is not set"
+        );
+        assert!(
+            import_tokens_env_internal(quote!(let tokens = SOME_VAR), |_| {
+                Some("not a path".to_string())
+            })
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn proc_macro_from_malformed_signatures() {
+        let err = ProcMacro::from(quote! {
+            pub fn no_attr(tokens: TokenStream) -> TokenStream {}
+        })
+        .err()
+        .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "can only be attached to a proc macro function definition"
+        );
+        let err = ProcMacro::from(quote! {
+            #[proc_macro]
+            pub fn no_args() -> TokenStream {}
+        })
+        .err()
+        .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "a #[proc_macro] function must take exactly 1 argument(s)"
+        );
+        let err = ProcMacro::from(quote! {
+            #[proc_macro]
+            pub fn ref_self(&self) -> TokenStream {}
+        })
+        .err()
+        .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "proc macro functions cannot take a `self` argument"
+        );
+        let err = ProcMacro::from(quote! {
+            #[proc_macro_attribute]
+            pub fn one_arg(tokens: TokenStream) -> TokenStream {}
+        })
+        .err()
+        .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "a #[proc_macro_attribute] function must take exactly 2 argument(s)"
+        );
+        assert!(ProcMacro::from(quote! {
+            #[proc_macro]
+            pub fn tuple_arg((a, b): TokenStream) -> TokenStream {}
+        })
+        .is_err());
+        let derive = ProcMacro::from(quote! {
+            #[proc_macro_derive(Foo)]
+            pub fn derive_foo(tokens: TokenStream) -> TokenStream {}
+        })
+        .unwrap();
+        assert_eq!(derive.macro_type, ProcMacroType::Derive);
+        assert_eq!(derive.tokens_ident, "tokens");
+        assert!(derive.attr_ident.is_none());
+    }
+
+    #[test]
+    fn macro_magic_root_is_stable() {
+        let root = macro_magic_root().to_token_stream().to_string();
+        assert_eq!(root, macro_magic_root().to_token_stream().to_string());
+        assert_eq!(
+            root,
+            MACRO_MAGIC_ROOT
+                .parse::<TokenStream2>()
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            private_path(&quote!(import_tokens_inner))
+                .to_token_stream()
+                .to_string(),
+            format!("{} :: __private :: import_tokens_inner", root)
+        );
+    }
+
+    #[test]
+    fn import_tokens_fn_internal_signature() {
+        let tokens = import_tokens_fn_internal(quote!(pub(crate) fn foo_tokens = a::Foo))
+            .unwrap()
+            .to_string();
+        assert!(tokens.starts_with("pub (crate) fn foo_tokens () ->"));
+        assert!(tokens.contains("a :: __export_tokens_tt_foo !"));
+        assert!(import_tokens_fn_internal(quote!(foo_tokens = a::Foo)).is_err());
+    }
+
+    #[test]
+    fn import_tokens_merge_internal_order() {
+        let tokens = import_tokens_merge_internal(quote!(let merged = [a::Foo, FooImpl]))
+            .unwrap()
+            .to_string();
+        let foo = tokens.find("a :: __export_tokens_tt_foo !").unwrap();
+        let foo_impl = tokens.find("__export_tokens_tt_foo_impl !").unwrap();
+        assert!(foo < foo_impl);
+        assert!(tokens.contains(
+            "merged . extend (__merged_tokens_0) ; merged . extend (__merged_tokens_1) ;"
+        ));
+        assert!(import_tokens_merge_internal(quote!(let merged = [])).is_err());
+        assert!(import_tokens_merge_internal(quote!(let merged = a::Foo)).is_err());
+    }
+
+    #[test]
+    fn export_tokens_alias_internal_prefix() {
+        let tokens = export_tokens_alias_internal(quote!(my_export), true)
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("export_tokens_internal"));
+        let tokens = export_tokens_alias_internal(quote!(my_export, prefix = "__my_lib_tt_"), true)
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains(". prefix (\"__my_lib_tt_\")"));
+        assert!(export_tokens_alias_internal(quote!(my_export, prefix = "1abc"), true).is_err());
+        let options = ExportTokensOptions::new().prefix("__my_lib_tt_");
+        let tokens = export_tokens_with_options(
+            quote!(
+                struct Foo;
+            ),
+            options,
+        )
+        .unwrap()
+        .to_string();
+        assert!(tokens.contains("macro_rules ! __my_lib_tt_foo"));
+        let tokens = import_tokens_internal(quote!(let tokens = a::Foo, prefix = "__my_lib_tt_"))
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("a :: __my_lib_tt_foo !"));
+        let tokens = import_tokens_internal(quote!(
+            let tokens = Foo, prefix = "__my_lib_tt_", min_version = 1
+        ))
+        .unwrap()
+        .to_string();
+        assert!(tokens.contains("__my_lib_tt_foo ! { @ version"));
+        assert!(
+            import_tokens_internal(quote!(let tokens = Foo, prefix = "a", prefix = "b")).is_err()
+        );
+    }
+
+    #[test]
+    fn import_tokens_attr_compat() {
+        assert!(check_import_tokens_attr_compat(quote! {
+            #[proc_macro_attribute]
+            pub fn my_attr(attr: TokenStream, tokens: TokenStream) -> TokenStream {
+                tokens
+            }
+        })
+        .is_ok());
+        let err = check_import_tokens_attr_compat(quote! {
+            #[proc_macro]
+            pub fn my_macro(tokens: TokenStream) -> TokenStream {
+                tokens
+            }
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains("use `#[import_tokens_proc]`"));
+        let err = check_import_tokens_attr_compat(quote! {
+            #[proc_macro_attribute]
+            fn my_attr(attr: TokenStream, tokens: TokenStream) -> TokenStream {
+                tokens
+            }
+        })
+        .unwrap_err();
+        assert_eq!(err.to_string(), "Visibility must be public");
+        let err = check_import_tokens_attr_compat(quote! {
+            #[proc_macro_attribute]
+            pub fn my_attr(tokens: TokenStream) -> TokenStream {
+                tokens
+            }
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains("must take exactly 2 argument(s)"));
+    }
+
+    #[test]
+    fn export_tokens_internal_not_an_item() {
+        let err = export_tokens_internal(
+            quote!(),
+            quote! {
+                struct Foo;
+                struct Bar;
+            },
+            true,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`#[export_tokens]` must be attached to a single item; found 2 items"
+        );
+        let err = export_tokens_internal(quote!(), quote!(), true).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`#[export_tokens]` must be attached to a single item; found 0 items"
+        );
+        for tokens in [quote!(let x = 5;), quote!(1 + 1), quote!(foo(bar))] {
+            let err = export_tokens_internal(quote!(), tokens, true).unwrap_err();
+            assert!(err
+                .to_string()
+                .contains("attached to a statement or expression"));
+        }
+        // tokens that are neither keep the original syn error
+        let err = export_tokens_internal(quote!(), quote!(struct), true).unwrap_err();
+        assert!(!err.to_string().contains("`#[export_tokens]`"));
+    }
+
+    #[test]
+    fn export_tokens_internal_value() {
+        let tokens = export_tokens_internal(
+            quote!(value),
+            quote!(
+                const N: u32 = 2 + 3;
+            ),
+            true,
+        )
+        .unwrap()
+        .to_string();
+        assert!(tokens.contains("(@ value) => { 2 + 3 } ;"));
+        let tokens = export_tokens_internal(
+            quote!(),
+            quote!(
+                const N: u32 = 2 + 3;
+            ),
+            true,
+        )
+        .unwrap()
+        .to_string();
+        assert!(!tokens.contains("(@ value)"));
+        assert!(export_tokens_internal(
+            quote!(value),
+            quote!(
+                static N: u32 = 5;
+            ),
+            true
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn export_tokens_internal_warn_unused() {
+        let item = quote!(
+            struct Foo;
+        );
+        let tokens = export_tokens_internal(quote!(), item.clone(), true)
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("# [allow (unused)] struct Foo ;"));
+        let tokens = export_tokens_internal(quote!(warn_unused), item, true)
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("struct Foo ;"));
+        assert!(!tokens.contains("# [allow (unused)] struct Foo ;"));
+    }
+
+    #[test]
+    fn export_tokens_internal_extra_arm() {
+        let tokens = export_tokens_internal(
+            quote!(),
+            quote!(
+                struct Foo;
+            ),
+            true,
+        )
+        .unwrap()
+        .to_string();
+        // the extra is matched as token trees, so it may contain commas
+        assert!(tokens.contains(&format!(
+            "($ ($ tokens_var_root : ident) ? $ (:: $ tokens_var : ident) * , $ ($ callback_root : ident) ? $ (:: $ callback : ident) * , $ ($ extra : tt) +) => {{ $ ($ callback_root) ? $ (:: $ callback) * ! {{ $ ($ tokens_var_root) ? $ (:: $ tokens_var) * , {} , $ ($ extra) + }} }}",
+            export_tokens_stored(&quote!(struct Foo;))
+        )));
+        assert!(!tokens.contains("expr"));
+    }
+
+    #[test]
+    fn resolve_cfg_attrs_resolutions() {
+        let tokens = quote! {
+            #[cfg_attr(feature = "serde", derive(Serialize), serde(rename_all = "camelCase"))]
+            struct Foo {
+                #[cfg_attr(all(unix, test), allow(dead_code))]
+                #[cfg_attr(a, cfg_attr(b, doc = "nested"))]
+                #[cfg(test)]
+                field: u8,
+            }
+        };
+        assert_eq!(
+            resolve_cfg_attrs(tokens.clone(), CfgAttrResolution::Keep).to_string(),
+            tokens.to_string()
+        );
+        assert_eq!(
+            resolve_cfg_attrs(tokens.clone(), CfgAttrResolution::Apply).to_string(),
+            quote! {
+                #[derive(Serialize)]
+                #[serde(rename_all = "camelCase")]
+                struct Foo {
+                    #[allow(dead_code)]
+                    #[doc = "nested"]
+                    #[cfg(test)]
+                    field: u8,
+                }
+            }
+            .to_string()
+        );
+        assert_eq!(
+            resolve_cfg_attrs(tokens, CfgAttrResolution::Strip).to_string(),
+            quote! {
+                struct Foo {
+                    #[cfg(test)]
+                    field: u8,
+                }
+            }
+            .to_string()
+        );
+        // inner attributes keep their style
+        assert_eq!(
+            resolve_cfg_attrs(
+                quote!(
+                    mod foo {
+                        #![cfg_attr(test, allow(unused))]
+                    }
+                ),
+                CfgAttrResolution::Apply
+            )
+            .to_string(),
+            quote!(
+                mod foo {
+                    #![allow(unused)]
+                }
+            )
+            .to_string()
+        );
+    }
+
+    #[test]
+    fn export_tokens_internal_cfg_attr() {
+        let item = quote! {
+            struct Foo {
+                #[cfg_attr(feature = "a", allow(dead_code))]
+                field: u8,
+            }
+        };
+        for (attr, exported) in [
+            (
+                quote!(),
+                quote!(
+                    struct Foo {
+                        #[cfg_attr(feature = "a", allow(dead_code))]
+                        field: u8,
+                    }
+                ),
+            ),
+            (
+                quote!(cfg_attr = keep),
+                quote!(
+                    struct Foo {
+                        #[cfg_attr(feature = "a", allow(dead_code))]
+                        field: u8,
+                    }
+                ),
+            ),
+            (
+                quote!(cfg_attr = apply),
+                quote!(
+                    struct Foo {
+                        #[allow(dead_code)]
+                        field: u8,
+                    }
+                ),
+            ),
+            (
+                quote!(cfg_attr = strip),
+                quote!(
+                    struct Foo {
+                        field: u8,
+                    }
+                ),
+            ),
+        ] {
+            let tokens = export_tokens_internal(attr, item.clone(), true)
+                .unwrap()
+                .to_string();
+            assert!(tokens.contains(&export_tokens_stored(&exported).to_string()));
+            // the emitted item is left as-is
+            assert!(tokens.ends_with(
+                "# [allow (unused)] struct Foo { # [cfg_attr (feature = \"a\" , allow (dead_code))] field : u8 , }"
+            ));
+        }
+        let options = parse2::<ExportTokensArgs>(quote!(cfg_attr = strip, Bar)).unwrap();
+        assert_eq!(options.cfg_attr, Some(CfgAttrResolution::Strip));
+        assert_eq!(
+            options.into_options(true).cfg_attr,
+            CfgAttrResolution::Strip
+        );
+        assert_eq!(ExportTokensOptions::new().cfg_attr, CfgAttrResolution::Keep);
+        assert!(parse2::<ExportTokensArgs>(quote!(cfg_attr = always)).is_err());
+        assert!(parse2::<ExportTokensArgs>(quote!(cfg_attr = keep, cfg_attr = apply)).is_err());
+    }
+
+    #[test]
+    fn export_tokens_internal_kind_arm() {
+        let tokens = export_tokens_internal(
+            quote!(body),
+            quote!(
+                fn add_stuff() {}
+            ),
+            true,
+        )
+        .unwrap()
+        .to_string();
+        assert_eq!(tokens.matches("(@ kind) => { \"fn\" } ;").count(), 1);
+        let tokens = export_tokens_internal(
+            quote!(),
+            quote!(
+                enum Shape {}
+            ),
+            true,
+        )
+        .unwrap()
+        .to_string();
+        assert!(tokens.contains("(@ kind) => { \"enum\" } ;"));
+        assert!(tokens.contains(
+            "(@ kind , $ ($ kind_root : ident) ? $ (:: $ kind_path : ident) *) => { \
+            $ ($ kind_root) ? $ (:: $ kind_path) * :: Enum } ;"
+        ));
+        assert_eq!(
+            import_tokens_kind_internal(quote!(a::Shape))
+                .unwrap()
+                .to_string(),
+            "a :: __export_tokens_tt_shape ! { @ kind , :: macro_magic :: ExportedKind }"
+        );
+    }
+
+    #[test]
+    fn import_tokens_ident_internal_paths() {
+        assert_eq!(
+            import_tokens_ident_internal(quote!(my_crate::some_mod::ComputeArea))
+                .unwrap()
+                .to_string(),
+            "my_crate :: __export_tokens_tt_compute_area ! { @ ident }"
+        );
+        let tokens = export_tokens_internal(
+            quote!(area),
+            quote!(
+                fn ComputeArea() {}
+            ),
+            true,
+        )
+        .unwrap()
+        .to_string();
+        assert!(tokens.contains("(@ ident) => { ComputeArea }"));
+    }
+
+    #[test]
+    fn export_tokens_macro_path_segments() {
+        let path = |tokens: TokenStream2| {
+            export_tokens_macro_path(&parse2::<Path>(tokens).unwrap())
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(path(quote!(SomeItem)), "__export_tokens_tt_some_item");
+        assert_eq!(
+            path(quote!(my_crate::SomeItem)),
+            "my_crate :: __export_tokens_tt_some_item"
+        );
+        // intermediate modules are dropped, since callback macros live at the crate root
+        assert_eq!(
+            path(quote!(my_crate::some_mod::SomeItem)),
+            "my_crate :: __export_tokens_tt_some_item"
+        );
+        assert_eq!(
+            path(quote!(::my_crate::some_mod::SomeItem)),
+            "my_crate :: __export_tokens_tt_some_item"
+        );
+        // importing and forwarding resolve the callback macro identically
+        for source in [
+            quote!(SomeItem),
+            quote!(my_crate::SomeItem),
+            quote!(my_crate::some_mod::SomeItem),
+        ] {
+            let expected = format!("{} !", path(source.clone()));
+            let import = import_tokens_internal(quote!(let tokens = #source))
+                .unwrap()
+                .to_string();
+            let forward = forward_tokens_internal(quote!(#source, target))
+                .unwrap()
+                .to_string();
+            assert!(import.contains(expected.as_str()));
+            assert!(forward.starts_with(expected.as_str()));
+        }
+    }
+
+    #[test]
+    fn export_tokens_macro_path_generics() {
+        let path = |tokens: TokenStream2| {
+            export_tokens_macro_path(&parse2::<Path>(tokens).unwrap()).map(|p| p.to_string())
+        };
+        assert_eq!(path(quote!(Foo<T>)).unwrap(), "__export_tokens_tt_foo");
+        assert_eq!(
+            path(quote!(my_crate::Foo<T, U>)).unwrap(),
+            "my_crate :: __export_tokens_tt_foo"
+        );
+        assert_eq!(
+            import_tokens_internal(quote!(let tokens = my_crate::Foo<T>))
+                .unwrap()
+                .to_string(),
+            import_tokens_internal(quote!(let tokens = my_crate::Foo))
+                .unwrap()
+                .to_string()
+        );
+        let err = path(quote!(my_crate::Foo<T>::Bar)).unwrap_err().to_string();
+        assert!(err.contains("generic arguments `< T >` on `Foo`"));
+        assert!(import_tokens_ident_internal(quote!(Foo<T>::Bar)).is_err());
+    }
+
+    #[test]
+    fn crafted_inputs_are_errors() {
+        // syn values built by hand can violate invariants that parsing would otherwise uphold
+        let empty_path = Path {
+            leading_colon: None,
+            segments: Punctuated::new(),
+        };
+        let err = export_tokens_macro_path(&empty_path)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "expected a path with at least one segment");
+        let mut item_struct: ItemStruct = parse_quote!(
+            struct Foo {
+                a: u32,
+            }
+        );
+        let Fields::Named(fields) = &mut item_struct.fields else {
+            unreachable!();
+        };
+        fields.named[0].ident = None;
+        let err = generate_export(
+            None,
+            Item::Struct(item_struct),
+            ExportTokensOptions::new().fields(true),
+        )
+        .unwrap_err()
+        .to_string();
+        assert_eq!(err, "the `fields` option requires every field to be named");
+    }
+
+    #[test]
+    fn export_tokens_file_internal_items() {
+        let tokens = export_tokens_file_internal(quote! {
+            struct Foo;
+            impl Foo {}
+            fn bar() {}
+        })
+        .unwrap()
+        .to_string();
+        assert!(tokens.contains("macro_rules ! __export_tokens_tt_foo"));
+        assert!(tokens.contains("macro_rules ! __export_tokens_tt_bar"));
+        assert!(tokens.contains("impl Foo { }"));
+        assert_eq!(tokens.matches("__ExportTokensFileSkipped ;").count(), 2);
+        assert!(tokens.contains("skipped this `impl` item"));
+        assert!(export_tokens_file_internal(quote!()).unwrap().is_empty());
+        assert!(export_tokens_file_internal(quote!(struct Foo)).is_err());
+        assert!(export_tokens_file_internal(quote! {
+            #[export_tokens]
+            struct Foo;
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn forward_tokens_dynamic_internal_paths() {
+        assert_eq!(
+            forward_tokens_dynamic_internal(quote!(my_crate::SomeItem, my_table, debug))
+                .unwrap()
+                .to_string(),
+            format!(
+                "my_crate :: __export_tokens_tt_some_item ! {{ my_table , {} :: __private :: forward_tokens_dynamic_inner , \"debug\" }}",
+                macro_magic_root().to_token_stream()
+            )
+        );
+        assert!(forward_tokens_dynamic_internal(quote!(SomeItem, a::table, key,)).is_ok());
+        for tokens in [
+            quote!(SomeItem, my_table),
+            quote!(SomeItem, my_table, "debug"),
+            quote!(SomeItem, my_table, a::b),
+        ] {
+            let err = forward_tokens_dynamic_internal(tokens).unwrap_err();
+            assert!(err
+                .to_string()
+                .starts_with("expected `<source path>, <table path>, <key>`"));
+        }
+        assert_eq!(
+            forward_tokens_dynamic_inner_internal(quote!(my_table, struct Foo;, "debug"))
+                .unwrap()
+                .to_string(),
+            "my_table ! { debug , struct Foo ; }"
+        );
+        assert!(forward_tokens_dynamic_inner_internal(quote!(my_table, struct Foo;)).is_err());
+        let err = forward_tokens_dynamic_inner_internal(quote!(my_table, struct Foo;, "a b"))
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("malformed `extra`: invalid key"));
+    }
+
+    #[test]
+    fn import_tokens_docs_internal_paths() {
+        assert_eq!(
+            import_tokens_docs_internal(quote!(my_crate::SomeItem))
+                .unwrap()
+                .to_string(),
+            format!(
+                "my_crate :: __export_tokens_tt_some_item ! {{ tokens , {} :: __private :: import_tokens_docs_inner }}",
+                macro_magic_root().to_token_stream()
+            )
+        );
+        assert!(import_tokens_docs_internal(quote!(1 + 1)).is_err());
+        let tokens = import_tokens_docs_inner_internal(quote! {
+            tokens,
+            /// Some docs
+            #[derive(Clone)]
+            #[doc = "More docs"]
+            struct Foo;
+        })
+        .unwrap()
+        .to_string();
+        assert_eq!(tokens, "\" Some docs\\nMore docs\"");
+        // inner doc comments of modules are included as well
+        let tokens = import_tokens_docs_inner_internal(quote! {
+            tokens,
+            /// Outer
+            mod foo {
+                //! Inner
+            }
+        })
+        .unwrap()
+        .to_string();
+        assert_eq!(tokens, "\" Outer\\n Inner\"");
+        // exports that aren't full items, such as fields
+        let tokens = import_tokens_docs_inner_internal(quote! {
+            tokens,
+            /// A field
+            pub field: u32
+        })
+        .unwrap()
+        .to_string();
+        assert_eq!(tokens, "\" A field\"");
+        let tokens = import_tokens_docs_inner_internal(quote!(tokens, struct Foo;))
+            .unwrap()
+            .to_string();
+        assert_eq!(tokens, "\"\"");
+        let err = import_tokens_docs_inner_internal(quote! {
+            tokens,
+            #[doc = include_str!("README.md")]
+            struct Foo;
+        })
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("only doc attributes with a string literal"));
+        // compressed tokens are handled like any other
+        let stored = LitStr::new(
+            &compress_tokens(quote! {
+                /// Compressed
+                struct Foo;
+            }),
+            Span::call_site(),
+        );
+        let tokens = import_tokens_docs_inner_internal(quote!(tokens, #stored))
+            .unwrap()
+            .to_string();
+        assert_eq!(tokens, "\" Compressed\"");
+    }
+
+    #[test]
+    fn import_tokens_mod_internal_paths() {
+        assert_eq!(
+            import_tokens_mod_internal(quote!(pub(crate) my_mod, my_crate::SomeItem))
+                .unwrap()
+                .to_string(),
+            format!(
+                "my_crate :: __export_tokens_tt_some_item ! {{ my_mod , {} :: __private :: import_tokens_mod_inner , \"pub (crate)\" }}",
+                macro_magic_root().to_token_stream()
+            )
+        );
+        assert!(import_tokens_mod_internal(quote!(my_mod, SomeItem)).is_ok());
+        assert!(import_tokens_mod_internal(quote!(SomeItem)).is_err());
+        let tokens = import_tokens_mod_inner_internal(quote!(my_mod, struct Foo;, "pub"))
+            .unwrap()
+            .to_string();
+        assert_eq!(
+            tokens,
+            "pub mod my_mod { # [allow (unused_imports)] use super :: * ; pub (super) struct Foo ; }"
+        );
+        // explicit visibilities are kept as-is
+        let tokens = import_tokens_mod_inner_internal(quote!(my_mod, pub fn foo() {}, ""))
+            .unwrap()
+            .to_string();
+        assert!(tokens.starts_with("mod my_mod {"));
+        assert!(tokens.contains(" pub fn foo () { }"));
+        assert!(import_tokens_mod_inner_internal(quote!(a::b, struct Foo;, "")).is_err());
+    }
+
+    #[test]
+    fn assert_tokens_eq_internal_paths() {
+        let root = macro_magic_root().to_token_stream();
+        assert_eq!(
+            assert_tokens_eq_internal(quote!(my_crate::SomeItem, OtherItem,))
+                .unwrap()
+                .to_string(),
+            format!(
+                "my_crate :: __export_tokens_tt_some_item ! {{ __assert_tokens_eq , {} :: __private :: assert_tokens_eq_inner , \"__export_tokens_tt_other_item\" }}",
+                root
+            )
+        );
+        assert!(assert_tokens_eq_internal(quote!(SomeItem)).is_err());
+        assert!(assert_tokens_eq_internal(quote!(SomeItem, Foo<T>::Bar)).is_err());
+        let tokens = assert_tokens_eq_inner_internal(quote! {
+            __assert_tokens_eq, struct Foo { a: u8 }, "other_crate :: __export_tokens_tt_bar"
+        })
+        .unwrap()
+        .to_string();
+        assert_eq!(
+            tokens,
+            format!(
+                "other_crate :: __export_tokens_tt_bar ! {{ __assert_tokens_eq , {} :: __private :: assert_tokens_eq_compare , \"struct Foo {{ a : u8 }}\" }}",
+                root
+            )
+        );
+        assert!(assert_tokens_eq_inner_internal(quote!(__assert_tokens_eq, struct Foo;)).is_err());
+        let err = assert_tokens_eq_inner_internal(quote!(__assert_tokens_eq, struct Foo;, "1 + 1"))
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("malformed `extra`: invalid right item path"));
+    }
+
+    #[test]
+    fn assert_tokens_eq_compare_internal_equal() {
+        for (left, right) in [
+            ("struct Foo { a : u8 }", quote! { struct Foo { a: u8 } }),
+            ("fn f()->u32{1+2}", quote! { fn f() -> u32 { 1 + 2 } }),
+        ] {
+            let extra = proc_macro2::Group::new(Delimiter::None, quote!(#left));
+            let tokens =
+                assert_tokens_eq_compare_internal(quote!(__assert_tokens_eq, #right, #extra))
+                    .unwrap();
+            assert!(tokens.is_empty());
+        }
+        // compressed tokens are compared just the same
+        let right = LitStr::new(&compress_tokens(quote! { struct Foo; }), Span::call_site());
+        let tokens =
+            assert_tokens_eq_compare_internal(quote!(__assert_tokens_eq, #right, "struct Foo ;"))
+                .unwrap();
+        assert!(tokens.is_empty());
+    }
+
+    #[test]
+    fn assert_tokens_eq_compare_internal_not_equal() {
+        let tokens = assert_tokens_eq_compare_internal(quote! {
+            __assert_tokens_eq, struct Foo { a: u16 }, "struct Foo { a : u8 }"
+        })
+        .unwrap()
+        .to_string();
+        assert!(tokens.contains("compile_error"));
+        assert!(tokens.contains("exported tokens are not equal: token 5 differs: `u8` != `u16`"));
+        let tokens = assert_tokens_eq_compare_internal(quote! {
+            __assert_tokens_eq, pub struct Foo;, "struct Foo ;"
+        })
+        .unwrap()
+        .to_string();
+        assert!(tokens.contains("token 0 differs: `struct` != `pub`"));
+        assert!(
+            assert_tokens_eq_compare_internal(quote!(__assert_tokens_eq, struct Foo;)).is_err()
+        );
+    }
+
+    #[test]
+    fn import_tokens_doc_internal_paths() {
+        let tokens =
+            import_tokens_doc_internal(quote!(my_crate::SomeItem => #[repr(C)] struct Foo;))
+                .unwrap()
+                .to_string();
+        assert_eq!(
+            tokens,
+            format!(
+                "# [repr (C)] # [doc = my_crate :: __export_tokens_tt_some_item ! {{ tokens , {} :: __private :: import_tokens_doc_inner }}] struct Foo ;",
+                macro_magic_root().to_token_stream()
+            )
+        );
+        assert!(import_tokens_doc_internal(quote!(my_crate::SomeItem)).is_err());
+        assert!(import_tokens_doc_internal(quote!(my_crate::SomeItem => 1 + 2)).is_err());
+    }
+
+    #[test]
+    fn import_tokens_doc_inner_internal_fences() {
+        let doc = |tokens: TokenStream2| {
+            parse2::<LitStr>(import_tokens_doc_inner_internal(tokens).unwrap())
+                .unwrap()
+                .value()
+        };
+        assert_eq!(
+            doc(quote!(tokens, struct Foo<T>(T);)),
+            "```text\nstruct Foo < T > (T) ;\n```"
+        );
+        assert_eq!(
+            doc(quote!(tokens, const S: &str = "````";)),
+            "`````text\nconst S : & str = \"````\" ;\n`````"
+        );
+    }
+
+    #[test]
+    fn import_tokens_str_internal_paths() {
+        assert_eq!(
+            import_tokens_str_internal(quote!(my_crate::add_stuff))
+                .unwrap()
+                .to_string(),
+            format!(
+                "my_crate :: __export_tokens_tt_add_stuff ! {{ tokens , {} :: __private :: import_tokens_str_inner }}",
+                macro_magic_root().to_token_stream()
+            )
+        );
+        assert_eq!(
+            import_tokens_str_inner_internal(quote!(tokens, fn add_stuff() {}))
+                .unwrap()
+                .to_string(),
+            "\"fn add_stuff () { }\""
+        );
+        assert!(import_tokens_str_internal(quote!(let tokens = add_stuff)).is_err());
+    }
+
+    #[test]
+    fn import_tokens_derive_internal_basic() {
+        let tokens = import_tokens_derive_internal(
+            quote!(),
+            quote! {
+                #[proc_macro_derive(MyDerive, attributes(my_source, other))]
+                pub fn my_derive(tokens: TokenStream) -> TokenStream {
+                    tokens
+                }
+            },
+        )
+        .unwrap();
+        let file = parse2::<syn::File>(tokens.clone()).unwrap();
+        let fns: Vec<ItemFn> = file
+            .items
+            .into_iter()
+            .filter_map(|item| match item {
+                Item::Fn(item_fn) => Some(item_fn),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(fns.len(), 2);
+        assert_eq!(fns[0].sig.ident, "my_derive");
+        assert_eq!(
+            fns[0].attrs[0].to_token_stream().to_string(),
+            "# [proc_macro_derive (MyDerive , attributes (my_source , other))]"
+        );
+        assert_eq!(fns[1].sig.ident, "__import_tokens_derive_my_derive_inner");
+        assert_eq!(
+            fns[1].attrs[1].to_token_stream().to_string(),
+            "# [proc_macro]"
+        );
+        let tokens = tokens.to_string();
+        assert!(tokens.contains("is_ident (\"my_source\")"));
+        assert!(tokens.contains("forward_tokens !"));
+        assert!(tokens.contains("__import_tokens_derive_my_derive_inner ,"));
+        assert!(tokens.contains("__derived_item"));
+
+        // a helper attribute is required to specify the source path
+        let err = import_tokens_derive_internal(
+            quote!(),
+            quote! {
+                #[proc_macro_derive(MyDerive)]
+                pub fn my_derive(tokens: TokenStream) -> TokenStream {
+                    tokens
+                }
+            },
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("must declare a helper attribute"));
+        assert!(import_tokens_derive_internal(
+            quote!(),
+            quote! {
+                #[proc_macro]
+                pub fn my_proc(tokens: TokenStream) -> TokenStream {
+                    tokens
+                }
+            },
+        )
+        .is_err());
+        assert_eq!(
+            use_internal(
+                quote!(),
+                quote!(
+                    pub use my_crate::my_derive;
+                ),
+                ProcMacroType::Derive
+            )
+            .unwrap()
+            .to_string(),
+            "pub use my_crate :: my_derive ; # [doc (hidden)] pub use my_crate :: \
+            __import_tokens_derive_my_derive_inner ;"
+        );
+    }
+
+    #[test]
+    fn importer_wrong_proc_macro_type() {
+        let proc_fn = quote! {
+            #[proc_macro]
+            pub fn my_proc(tokens: TokenStream) -> TokenStream {
+                tokens
+            }
+        };
+        let attr_fn = quote! {
+            #[proc_macro_attribute]
+            pub fn my_attr(attr: TokenStream, tokens: TokenStream) -> TokenStream {
+                tokens
+            }
+        };
+        let derive_fn = quote! {
+            #[proc_macro_derive(MyDerive)]
+            pub fn my_derive(tokens: TokenStream) -> TokenStream {
+                tokens
+            }
+        };
+        let err = import_tokens_attr_internal(quote!(), proc_fn.clone())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("use `#[import_tokens_proc]` to import tokens into #[proc_macro]"));
+        let err = import_tokens_proc_internal(quote!(), attr_fn.clone())
+            .unwrap_err()
+            .to_string();
+        assert!(err
+            .contains("use `#[import_tokens_attr]` to import tokens into #[proc_macro_attribute]"));
+        let err = import_tokens_attr_internal(quote!(), derive_fn)
+            .unwrap_err()
+            .to_string();
+        assert!(!err.contains("use `#["));
+        assert!(import_tokens_attr_internal(quote!(), attr_fn).is_ok());
+        assert!(import_tokens_proc_internal(quote!(), proc_fn).is_ok());
+    }
+
+    #[test]
+    fn import_tokens_proc_internal_mm_override_path() {
+        let proc_fn = quote! {
+            #[proc_macro]
+            pub fn my_proc(tokens: TokenStream) -> TokenStream {
+                tokens
+            }
+        };
+        let root = macro_magic_root().to_token_stream().to_string();
+        let tokens = import_tokens_proc_internal(quote!(::renamed), proc_fn.clone())
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains(":: renamed :: forward_tokens !"));
+        assert!(tokens.contains("__import_tokens_proc_my_proc_inner , :: renamed }"));
+        assert!(tokens.contains(format!("use {} :: __private :: * ;", root).as_str()));
+        assert!(!tokens.contains(format!("{} :: forward_tokens", root).as_str()));
+        let tokens = import_tokens_proc_internal(quote!(), proc_fn.clone())
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains(format!("{} :: forward_tokens !", root).as_str()));
+        assert!(import_tokens_proc_internal(quote!(123), proc_fn.clone()).is_err());
+        let attr_fn = quote! {
+            #[proc_macro_attribute]
+            pub fn my_attr(attr: TokenStream, tokens: TokenStream) -> TokenStream {
+                tokens
+            }
+        };
+        let tokens = import_tokens_attr_internal(quote!(::renamed), attr_fn.clone())
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains(":: renamed :: forward_tokens !"));
+        assert!(!tokens.contains(format!("{} :: forward_tokens", root).as_str()));
+        assert!(import_tokens_attr_internal(quote!(not a path), attr_fn).is_err());
+    }
+
+    #[test]
+    fn import_tokens_attr_internal_dry_run() {
+        let attr_fn = quote! {
+            #[proc_macro_attribute]
+            pub fn my_attr(attr: TokenStream, tokens: TokenStream) -> TokenStream {
+                tokens
+            }
+        };
+        let tokens = import_tokens_attr_internal(quote!(dry_run), attr_fn.clone())
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("attached_item . to_token_stream () . into ()"));
+        // only the inner macro still forwards to itself
+        assert_eq!(tokens.matches("forward_tokens !").count(), 1);
+        let tokens = import_tokens_attr_internal(quote!(::renamed, dry_run), attr_fn.clone())
+            .unwrap()
+            .to_string();
+        assert_eq!(tokens.matches(":: renamed :: forward_tokens !").count(), 1);
+        let tokens = import_tokens_attr_internal(quote!(::renamed), attr_fn.clone())
+            .unwrap()
+            .to_string();
+        assert_eq!(tokens.matches(":: renamed :: forward_tokens !").count(), 2);
+        assert!(import_tokens_attr_internal(quote!(dry_run, ::renamed), attr_fn.clone()).is_err());
+        assert!(import_tokens_attr_internal(quote!(::renamed, wet_run), attr_fn).is_err());
+    }
+
+    #[test]
+    fn import_tokens_attr_internal_reserved_param_names() {
+        let err = import_tokens_attr_internal(
+            quote!(),
+            quote! {
+                #[proc_macro_attribute]
+                pub fn my_attr(attr: TokenStream, __combined_args: TokenStream) -> TokenStream {
+                    attr
+                }
+            },
+        )
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("`__combined_args` is reserved by the code generated"));
+        for reserved in IMPORT_TOKENS_ATTR_RESERVED_IDENTS {
+            let reserved = format_ident!("{}", reserved);
+            let attr_fn = quote! {
+                #[proc_macro_attribute]
+                pub fn my_attr(#reserved: TokenStream, tokens: TokenStream) -> TokenStream {
+                    tokens
+                }
+            };
+            assert!(import_tokens_attr_internal(quote!(), attr_fn).is_err());
+        }
+        // names merely resembling reserved ones are fine
+        assert!(import_tokens_attr_internal(
+            quote!(),
+            quote! {
+                #[proc_macro_attribute]
+                pub fn my_attr(raw_attr_: TokenStream, extra: TokenStream) -> TokenStream {
+                    extra
+                }
+            },
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn import_tokens_attr_internal_then() {
+        let attr_fn = quote! {
+            #[proc_macro_attribute]
+            pub fn my_attr(attr: TokenStream, tokens: TokenStream) -> TokenStream {
+                tokens
+            }
+        };
+        let tokens = import_tokens_attr_internal(quote!(then = my_crate::other), attr_fn.clone())
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("# [my_crate :: other]"));
+        assert!(tokens.contains("let __output"));
+        let tokens = import_tokens_attr_internal(quote!(), attr_fn.clone())
+            .unwrap()
+            .to_string();
+        assert!(!tokens.contains("__output"));
+        let args: ImportTokensAttrArgs = parse_quote!(::renamed, dry_run, then = other);
+        assert!(args.dry_run);
+        assert_eq!(args.then.unwrap().to_token_stream().to_string(), "other");
+        assert_eq!(
+            args.mm_override_path.to_token_stream().to_string(),
+            ":: renamed"
+        );
+        let args: ImportTokensAttrArgs = parse_quote!(then = other, dry_run);
+        assert!(args.dry_run);
+        // a path merely named `then` is still a `macro_magic` override path
+        let args: ImportTokensAttrArgs = parse_quote!(then);
+        assert!(args.then.is_none());
+        assert!(import_tokens_attr_internal(quote!(then = a, then = b), attr_fn.clone()).is_err());
+        assert!(import_tokens_attr_internal(quote!(then =), attr_fn).is_err());
+    }
+
+    #[test]
+    fn parse_export_tokens_macro_ident_round_trip() {
+        let ident: Ident = parse_quote!(SomeThing);
+        assert_eq!(
+            parse_export_tokens_macro_ident(&export_tokens_macro_ident(&ident)).unwrap(),
+            "some_thing"
+        );
+        let prefixed = format_ident!("{}some_thing", MACRO_MAGIC_EXPORT_PREFIX);
+        assert_eq!(
+            parse_export_tokens_macro_ident(&prefixed).unwrap(),
+            "some_thing"
+        );
+        let prefix_only = format_ident!("{}", MACRO_MAGIC_EXPORT_PREFIX);
+        assert!(parse_export_tokens_macro_ident(&prefix_only).is_none());
+        assert!(parse_export_tokens_macro_ident(&ident).is_none());
+        let custom: Ident = parse_quote!(__my_lib_tt_some_thing);
+        assert!(parse_export_tokens_macro_ident(&custom).is_none());
+        assert_eq!(
+            parse_export_tokens_macro_ident_with_prefix(&custom, "__my_lib_tt_").unwrap(),
+            "some_thing"
+        );
+    }
+
+    #[test]
+    fn export_tokens_macro_ident_prefix() {
+        let ident: Ident = parse_quote!(SomeItem);
+        assert_eq!(
+            export_tokens_macro_ident(&ident),
+            format!("{}some_item", MACRO_MAGIC_EXPORT_PREFIX)
+        );
+        assert_eq!(
+            export_tokens_macro_ident_with_prefix(&ident, "__my_lib_tt_"),
+            "__my_lib_tt_some_item"
+        );
+    }
+
+    #[cfg(feature = "pretty_print")]
+    #[test]
+    fn pretty_format_with_non_file_tokens() {
+        let tokens = quote!(1 + 2);
+        assert_eq!(
+            pretty_format_with(&tokens, Formatter::Prettyplease),
+            tokens.to_string()
+        );
+    }
+
+    #[cfg(feature = "rustfmt")]
+    #[test]
+    fn pretty_format_with_backends() {
+        let item = quote! {
+            struct Foo { a: u32, b: Option<bool> }
+        };
+        let prettyplease = pretty_format_with(&item, Formatter::Prettyplease);
+        let rustfmt = pretty_format_with(&item, Formatter::Rustfmt);
+        assert_eq!(
+            prettyplease,
+            "struct Foo {\n    a: u32,\n    b: Option<bool>,\n}\n"
+        );
+        // both backends produce the same code, and agree on formatting for simple items
+        let reparse = |source: &str| {
+            syn::parse_file(source)
+                .unwrap()
+                .to_token_stream()
+                .to_string()
+        };
+        assert_eq!(reparse(rustfmt.as_str()), reparse(prettyplease.as_str()));
+        assert_eq!(rustfmt, prettyplease);
+    }
+
+    #[test]
+    fn export_tokens_with_options_round_trip() {
+        let item = quote!(
+            struct Widget {
+                size: u32,
+            }
+        );
+        let options = ExportTokensOptions::new();
+        assert!(options.emit && options.name.is_none());
+        assert_eq!(
+            export_tokens_with_options(item.clone(), options)
+                .unwrap()
+                .to_string(),
+            export_tokens_internal(quote!(), item.clone(), true)
+                .unwrap()
+                .to_string()
+        );
+        let options = ExportTokensOptions::new()
+            .name(parse_quote!(gadget))
+            .emit(false)
+            .make_pub(true)
+            .hash(true);
+        assert_eq!(
+            export_tokens_with_options(item.clone(), options)
+                .unwrap()
+                .to_string(),
+            export_tokens_internal(quote!(gadget, make_pub, hash), item.clone(), false)
+                .unwrap()
+                .to_string()
+        );
+        let options = parse2::<ExportTokensArgs>(quote!(gadget, variants)).unwrap();
+        let options = options.into_options(false);
+        assert_eq!(options.name.unwrap().to_string(), "gadget");
+        assert!(options.variants && !options.emit && !options.make_pub && !options.hash);
+        assert!(
+            export_tokens_with_options(quote!(impl Widget {}), ExportTokensOptions::new()).is_err()
+        );
+    }
+
+    #[test]
+    fn generate_export_typed_item() {
+        // an item constructed in code rather than parsed from source
+        let item = Item::Const(syn::ItemConst {
+            attrs: Vec::new(),
+            vis: Visibility::Inherited,
+            const_token: Default::default(),
+            ident: format_ident!("ANSWER"),
+            generics: Generics::default(),
+            colon_token: Default::default(),
+            ty: alloc::boxed::Box::new(parse_quote!(u32)),
+            eq_token: Default::default(),
+            expr: alloc::boxed::Box::new(parse_quote!(42)),
+            semi_token: Default::default(),
+        });
+        let tokens = generate_export(None, item.clone(), ExportTokensOptions::new())
+            .unwrap()
+            .to_string();
+        assert_eq!(
+            tokens,
+            export_tokens_internal(
+                quote!(),
+                quote!(
+                    const ANSWER: u32 = 42;
+                ),
+                true
+            )
+            .unwrap()
+            .to_string()
+        );
+        // an explicit ident takes precedence over the name set in the options
+        let options = ExportTokensOptions::new()
+            .name(format_ident!("ignored"))
+            .emit(false)
+            .value(true);
+        let tokens = generate_export(Some(format_ident!("the_answer")), item.clone(), options)
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("macro_rules ! __export_tokens_tt_the_answer"));
+        assert!(tokens.contains("(@ value) => { 42 }"));
+        assert!(!tokens.contains("ignored"));
+        // the verbatim source defaults to the stringified item
+        let options = ExportTokensOptions::new().verbatim_source(true);
+        let tokens = generate_export(None, item, options).unwrap().to_string();
+        assert!(tokens.contains("(@ source) => { \"const ANSWER : u32 = 42 ;\" }"));
+        // items without an inherent name still need one
+        let item: Item = parse_quote!(impl Widget {});
+        assert!(generate_export(None, item.clone(), ExportTokensOptions::new()).is_err());
+        assert!(generate_export(
+            Some(format_ident!("widget_impl")),
+            item,
+            ExportTokensOptions::new()
+        )
+        .unwrap()
+        .to_string()
+        .contains("__export_tokens_tt_widget_impl"));
+    }
+
+    #[test]
+    fn derive_foreign_path() {
+        let tokens = derive_foreign_path_internal(quote! {
+            struct Foo {
+                foreign_path: syn::Path,
+                other: syn::Path,
+            }
+        })
+        .unwrap()
+        .to_string();
+        assert!(tokens.contains("ForeignPath for Foo"));
+        assert!(tokens.contains("& self . foreign_path"));
+        let tokens = derive_foreign_path_internal(quote! {
+            struct Bar(syn::Ident, #[foreign_path] syn::Path);
+        })
+        .unwrap()
+        .to_string();
+        assert!(tokens.contains("& self . 1"));
+        assert!(derive_foreign_path_internal(quote!(
+            struct Baz {
+                path: syn::Path,
+            }
+        ))
+        .is_err());
+        assert!(derive_foreign_path_internal(quote!(
+            struct Qux(#[foreign_path] syn::Path, #[foreign_path] syn::Path);
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn import_tokens_raw_internal_skips_flattening() {
+        let tokens = import_tokens_raw_internal(quote!(my_crate::__export_tokens_tt_MyItem))
+            .unwrap()
+            .to_string();
+        assert!(tokens.starts_with("my_crate :: __export_tokens_tt_MyItem !"));
+        assert!(import_tokens_raw_internal(quote!(let x = y)).is_err());
+    }
+
+    #[test]
+    fn export_tokens_internal_strict() {
+        let item = quote! {
+            mod my_mod {
+                #![allow(unused)]
+                fn foo() {}
+            }
+        };
+        assert!(export_tokens_internal(quote!(), item.clone(), true).is_ok());
+        let err = export_tokens_internal(quote!(strict), item, true).unwrap_err();
+        assert!(err.to_string().contains("inner attributes"));
+        assert!(err.to_string().contains("`mod`"));
+        let item = quote! {
+            #[allow(unused)]
+            mod my_mod {
+                fn foo() {}
+            }
+        };
+        assert!(export_tokens_internal(quote!(strict), item, true).is_ok());
+    }
+
+    #[test]
+    fn export_tokens_internal_with_generics() {
+        let item = quote! {
+            struct Wrapper<T: Clone> where T: Default {
+                inner: T,
+            }
+        };
+        let tokens = export_tokens_internal(quote!(with_generics), item.clone(), true)
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("(@ generics"));
+        let tokens = export_tokens_internal(quote!(), item, true)
+            .unwrap()
+            .to_string();
+        assert!(!tokens.contains("(@ generics"));
+        assert!(export_tokens_internal(
+            quote!(with_generics),
+            quote!(
+                use foo::bar;
+            ),
+            true
+        )
+        .is_err());
+        let generics =
+            syn::parse::Parser::parse2(parse_generics, quote!(<T: Clone> where T: Default))
+                .unwrap();
+        assert_eq!(generics.params.len(), 1);
+        assert!(generics.where_clause.is_some());
+    }
+
+    #[test]
+    fn export_tokens_internal_const_fn() {
+        let item = quote! {
+            pub struct MyStruct {
+                field: u32,
+            }
+        };
+        let tokens = export_tokens_internal(quote!(const_fn), item.clone(), true)
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains(
+            "pub const fn my_struct_tokens_str () -> & 'static str { \"pub struct MyStruct"
+        ));
+        assert!(!tokens.contains("__EXPORT_TOKENS__"));
+        // with `export_const`, the string is only stored once
+        let tokens = export_tokens_internal(quote!(const_fn, export_const), item.clone(), true)
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains(
+            "pub const fn my_struct_tokens_str () -> & 'static str { __EXPORT_TOKENS__MY_STRUCT }"
+        ));
+        let tokens = export_tokens_internal(quote!(), item.clone(), true)
+            .unwrap()
+            .to_string();
+        assert!(!tokens.contains("my_struct_tokens_str"));
+        let tokens = export_tokens_internal(quote!(const_fn, Renamed), item.clone(), true)
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("const fn renamed_tokens_str"));
+        assert!(export_tokens_internal(quote!(const_fn), item.clone(), false).is_err());
+        assert!(export_tokens_internal(quote!(const_fn, no_emit), item, true).is_err());
+        assert_eq!(
+            export_tokens_const_fn_ident(&format_ident!("r#type")),
+            "type_tokens_str"
+        );
+    }
+
+    #[test]
+    fn export_tokens_internal_with_const_generics() {
+        let item = quote! {
+            struct Buffer<'a, T, const N: usize = 8, const M: usize = { N * 2 }> {
+                data: &'a [T; N],
+            }
+        };
+        let tokens = export_tokens_internal(quote!(with_generics), item, true)
+            .unwrap()
+            .to_string();
+        // the const params are stored along with their defaults
+        let stored =
+            export_tokens_stored(&quote!(<'a, T, const N: usize = 8, const M: usize = { N * 2 }>));
+        assert!(tokens.contains(format!(", {} }}", stored).as_str()));
+        let generics = syn::parse::Parser::parse2(
+            parse_generics,
+            quote!(<'a, T, const N: usize = 8, const M: usize = { N * 2 }>),
+        )
+        .unwrap();
+        let const_params = generics.const_params().collect::<Vec<_>>();
+        assert_eq!(const_params.len(), 2);
+        assert_eq!(const_params[0].ident, "N");
+        assert_eq!(const_params[0].default.to_token_stream().to_string(), "8");
+        // defaults are dropped when re-applying the generics to an impl
+        let (impl_generics, ty_generics, _) = generics.split_for_impl();
+        assert_eq!(
+            impl_generics.to_token_stream().to_string(),
+            "< 'a , T , const N : usize , const M : usize >"
+        );
+        assert_eq!(
+            ty_generics.to_token_stream().to_string(),
+            "< 'a , T , N , M >"
+        );
+    }
+
+    #[test]
+    fn export_tokens_internal_body_and_sig() {
+        let item = quote! {
+            pub fn add_stuff(a: u32, b: u32) -> u32 {
+                a + b
+            }
+        };
+        let tokens = export_tokens_internal(quote!(body, sig), item.clone(), true)
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("__export_tokens_tt_add_stuff "));
+        assert!(tokens.contains("__export_tokens_tt_add_stuff_body"));
+        assert!(tokens.contains("__export_tokens_tt_add_stuff_sig"));
+        let tokens = export_tokens_internal(quote!(adder, sig), item, false)
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("__export_tokens_tt_adder_sig"));
+        assert!(!tokens.contains("__export_tokens_tt_adder_body"));
+        assert!(export_tokens_internal(
+            quote!(body),
+            quote!(
+                struct Foo;
+            ),
+            true
+        )
+        .is_err());
+
+        #[cfg(not(feature = "compress"))]
+        {
+            let imported = import_tokens_inner_internal(quote!(tokens, { a + b })).unwrap();
+            assert!(imported.to_string().contains("\"{ a + b }\""));
+        }
+        assert_eq!(
+            import_tokens_str_inner_internal(quote!(tokens, fn add_stuff(a: u32) -> u32))
+                .unwrap()
+                .to_string(),
+            "\"fn add_stuff (a : u32) -> u32\""
+        );
+    }
+
+    #[test]
+    fn export_tokens_internal_fields() {
+        let item = quote! {
+            #[repr(C)]
+            pub union RawValue {
+                /// As an integer
+                pub as_int: u32,
+                as_float: f32,
+                as_bytes: core::mem::ManuallyDrop<[u8; 4]>,
+            }
+        };
+        let tokens = export_tokens_internal(quote!(fields), item.clone(), true)
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("__export_tokens_tt_raw_value "));
+        assert!(tokens.contains("(@ ident) => { as_int }"));
+        assert!(tokens.contains("__export_tokens_tt_raw_value_as_float"));
+        assert!(tokens.contains("__export_tokens_tt_raw_value_as_bytes"));
+        // the union keeps its `#[repr(C)]` and the fields keep their own attributes
+        assert!(tokens.contains("# [repr (C)] pub union RawValue"));
+        assert!(tokens.contains("# [doc = r\" As an integer\"] pub as_int : u32"));
+        let tokens = export_tokens_internal(
+            quote!(fields),
+            quote! {
+                struct Point {
+                    x: i32,
+                    y: i32,
+                }
+            },
+            true,
+        )
+        .unwrap()
+        .to_string();
+        assert!(tokens.contains("__export_tokens_tt_point_x"));
+        assert!(tokens.contains("__export_tokens_tt_point_y"));
+        let tokens = export_tokens_internal(quote!(Value, fields), item, false)
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("__export_tokens_tt_value_as_int"));
+        for item in [
+            quote!(
+                struct Foo(u8);
+            ),
+            quote!(
+                enum Foo {
+                    A,
+                }
+            ),
+        ] {
+            let err = export_tokens_internal(quote!(fields), item, true).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "the `fields` option can only be used on unions and structs with named fields"
+            );
+        }
+    }
+
+    #[test]
+    fn export_tokens_internal_trait_items() {
+        let item = quote! {
+            pub trait Shape {
+                const SIDES: usize;
+                type Unit;
+                fn area(&self) -> Self::Unit;
+                fn describe(&self) -> String {
+                    String::from("shape")
+                }
+                some_macro!();
+            }
+        };
+        let tokens = export_tokens_internal(quote!(items), item.clone(), true)
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("__export_tokens_tt_shape "));
+        // export names are flattened as usual, so consts end up as e.g. `shape_s_i_d_e_s`
+        let sides_ident = export_tokens_macro_ident(&format_ident!("Shape_SIDES"));
+        assert_eq!(
+            sides_ident.to_string(),
+            "__export_tokens_tt_shape_s_i_d_e_s"
+        );
+        assert!(tokens.contains(sides_ident.to_string().as_str()));
+        assert!(tokens.contains("__export_tokens_tt_shape_unit"));
+        assert!(tokens.contains("__export_tokens_tt_shape_area"));
+        assert!(tokens.contains("__export_tokens_tt_shape_describe"));
+        assert!(tokens.contains("(@ ident) => { area }"));
+        let tokens = export_tokens_internal(quote!(Figure, items), item, false)
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("__export_tokens_tt_figure_area"));
+        assert!(export_tokens_internal(
+            quote!(items),
+            quote!(
+                struct Foo;
+            ),
+            true
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn export_tokens_internal_emit_override() {
+        let item = quote!(
+            struct Widget;
+        );
+        let emitted = export_tokens_internal(quote!(), item.clone(), true)
+            .unwrap()
+            .to_string();
+        let not_emitted = export_tokens_internal(quote!(), item.clone(), false)
+            .unwrap()
+            .to_string();
+        assert_ne!(emitted, not_emitted);
+        let tokens = export_tokens_internal(quote!(no_emit), item.clone(), true).unwrap();
+        assert_eq!(tokens.to_string(), not_emitted);
+        let tokens = export_tokens_internal(quote!(emit), item.clone(), false).unwrap();
+        assert_eq!(tokens.to_string(), emitted);
+        let args = parse2::<ExportTokensArgs>(quote!(gadget, no_emit)).unwrap();
+        assert_eq!(args.name.unwrap().to_string(), "gadget");
+        assert_eq!(args.emit, Some(false));
+        assert!(export_tokens_internal(quote!(emit, no_emit), item, true).is_err());
+    }
+
+    #[test]
+    fn export_tokens_args_reserved_names() {
+        // a bare option keyword always means the option
+        let args = parse2::<ExportTokensArgs>(quote!(value)).unwrap();
+        assert!(args.value);
+        assert!(args.name.is_none());
+        // the explicit form accepts any ident, including every option keyword
+        for option in EXPORT_TOKENS_OPTIONS {
+            let option = format_ident!("{}", option);
+            let args = parse2::<ExportTokensArgs>(quote!(name = #option)).unwrap();
+            assert_eq!(args.name.unwrap(), option);
+            assert!(!args.value);
+        }
+        let args = parse2::<ExportTokensArgs>(quote!(name = items, items)).unwrap();
+        assert_eq!(args.name.unwrap().to_string(), "items");
+        assert!(args.items);
+        // `name` on its own is still a regular override name
+        let args = parse2::<ExportTokensArgs>(quote!(name, hash)).unwrap();
+        assert_eq!(args.name.unwrap().to_string(), "name");
+        assert!(args.hash);
+        assert!(parse2::<ExportTokensArgs>(quote!(gadget, name = widget)).is_err());
+        // every listed option is recognized as such rather than taken as a name
+        for option in EXPORT_TOKENS_OPTIONS {
+            let option = format_ident!("{}", option);
+            if let Ok(args) = parse2::<ExportTokensArgs>(quote!(#option)) {
+                assert!(args.name.is_none(), "`{}` was parsed as a name", option);
+            }
+        }
+        let item = quote!(
+            const LIMIT: u32 = 5;
+        );
+        let tokens = export_tokens_internal(quote!(name = value), item, true)
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("macro_rules ! __export_tokens_tt_value"));
+    }
+
+    #[test]
+    fn export_tokens_args_dump() {
+        let args = parse2::<ExportTokensArgs>(quote!(gadget, dump = "target/tokens")).unwrap();
+        assert_eq!(args.dump.unwrap().value(), "target/tokens");
+        assert!(parse2::<ExportTokensArgs>(quote!(dump)).is_err());
+        assert!(parse2::<ExportTokensArgs>(quote!(dump = "a", dump = "b")).is_err());
+        // ignored by the core implementation
+        assert_eq!(
+            export_tokens_internal(
+                quote!(dump = "target/tokens"),
+                quote!(
+                    struct Foo;
+                ),
+                true
+            )
+            .unwrap()
+            .to_string(),
+            export_tokens_internal(
+                quote!(),
+                quote!(
+                    struct Foo;
+                ),
+                true
+            )
+            .unwrap()
+            .to_string()
+        );
+        assert_eq!(
+            item_kind(&parse_quote!(
+                struct Foo;
+            )),
+            "struct"
+        );
+        assert_eq!(
+            item_kind(&parse_quote!(
+                extern "C" {}
+            )),
+            "foreign mod"
+        );
+        assert_eq!(
+            item_ident(&parse_quote!(
+                fn foo() {}
+            ))
+            .unwrap(),
+            "foo"
+        );
+        assert!(item_ident(&parse_quote!(impl Foo {})).is_none());
+    }
+
+    #[test]
+    fn tokens_eq_ignoring_whitespace() {
+        let parsed = "struct  Foo<T>{a:u32,b:Vec<T>,}\n"
+            .parse::<TokenStream2>()
+            .unwrap();
+        let quoted = quote!(
+            struct Foo<T> {
+                a: u32,
+                b: Vec<T>,
+            }
+        );
+        assert_ne!(parsed.to_string(), quoted.to_string());
+        assert!(tokens_eq_ignoring_spans(&parsed, &quoted));
+        assert_token_streams_eq!(parsed, quoted);
+        assert!(tokens_eq_ignoring_spans(
+            &"a::b=>c".parse().unwrap(),
+            &quote!(a :: b => c)
+        ));
+        assert!(!tokens_eq_ignoring_spans(
+            &"a:b".parse().unwrap(),
+            &quote!(a::b)
+        ));
+        let invisible = TokenStream2::from(TokenTree::Group(proc_macro2::Group::new(
+            Delimiter::None,
+            quote!(1 + 2),
+        )));
+        assert!(tokens_eq_ignoring_spans(&invisible, &quote!(1 + 2)));
+    }
+
+    #[test]
+    fn tokens_first_difference_reports_token() {
+        assert_eq!(
+            tokens_first_difference(
+                &quote!(
+                    struct Foo;
+                ),
+                &quote!(
+                    struct Bar;
+                )
+            )
+            .unwrap(),
+            "token 1 differs: `Foo` != `Bar`"
         );
+        assert_eq!(
+            tokens_first_difference(
+                &quote!(
+                    fn foo() {}
+                ),
+                &quote!(fn foo())
+            )
+            .unwrap(),
+            "token 4 (`{`) is missing on the right"
+        );
+        assert_eq!(
+            tokens_first_difference(&quote!(a), &quote!(a(b))).unwrap(),
+            "token 1 (`(`) is missing on the left"
+        );
+        assert!(tokens_first_difference(&quote!((a)), &quote!([a])).is_some());
     }
 
     #[test]
-    fn export_tokens_internal_normal_no_ident() {
-        assert!(export_tokens_internal(
-            quote!(),
+    #[should_panic(expected = "token 1 differs: `Foo` != `Bar`")]
+    fn assert_token_streams_eq_panics() {
+        assert_token_streams_eq!(
             quote!(
-                struct MyStruct {}
+                struct Foo;
             ),
-            true
-        )
-        .unwrap()
-        .to_string()
-        .contains("my_struct"));
+            quote!(
+                struct Bar;
+            )
+        );
     }
 
     #[test]
-    fn export_tokens_internal_normal_ident() {
-        assert!(export_tokens_internal(
-            quote!(some_name),
-            quote!(
-                struct Something {}
-            ),
-            true,
-        )
-        .unwrap()
-        .to_string()
-        .contains("some_name"));
+    fn compile_error_tokens() {
+        let tokens = compile_error(Span::call_site(), "something went wrong").to_string();
+        assert!(tokens.contains("compile_error !"));
+        assert!(tokens.contains("\"something went wrong\""));
     }
 
     #[test]
-    fn export_tokens_internal_generics_no_ident() {
-        assert!(export_tokens_internal(
+    fn custom_parsing_args_by_kind() {
+        let Ok(CustomParsingArgs::Single(_)) = parse2::<CustomParsingArgs>(quote!(my::Parser))
+        else {
+            panic!("expected a single parser");
+        };
+        let Ok(CustomParsingArgs::ByKind(parsers, fallback)) =
+            parse2::<CustomParsingArgs>(quote!(struct => StructParser, fn => FnParser, _ => Other))
+        else {
+            panic!("expected parsers by kind");
+        };
+        assert_eq!(parsers.len(), 2);
+        assert_eq!(parsers[1].0, "fn");
+        assert!(fallback.is_some());
+        assert!(parse2::<CustomParsingArgs>(quote!(structure => StructParser)).is_err());
+        assert!(parse2::<CustomParsingArgs>(quote!(fn => A, fn => B)).is_err());
+        assert!(parse2::<CustomParsingArgs>(quote!(_ => A, fn => B)).is_err());
+    }
+
+    #[test]
+    fn import_tokens_attr_internal_parser_assertions() {
+        let attr_fn = |custom: TokenStream2| {
+            quote! {
+                #[proc_macro_attribute]
+                #[with_custom_parsing(#custom)]
+                pub fn my_attr(attr: TokenStream, tokens: TokenStream) -> TokenStream {
+                    tokens
+                }
+            }
+        };
+        let tokens = import_tokens_attr_internal(quote!(), attr_fn(quote!(my::Parser)))
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains(
+            "fn __assert_custom_parser_impls < T : ForeignPath + quote :: ToTokens + syn :: parse :: Parse > () { }"
+        ));
+        assert!(tokens.contains("__assert_custom_parser_impls :: < my :: Parser > () ;"));
+        let tokens = import_tokens_attr_internal(
             quote!(),
-            quote!(
-                struct MyStruct<T> {}
-            ),
-            true,
+            attr_fn(quote!(struct => StructParser, _ => OtherParser)),
         )
         .unwrap()
-        .to_string()
-        .contains("__export_tokens_tt_my_struct"));
+        .to_string();
+        assert!(tokens.contains("__assert_custom_parser_impls :: < StructParser > () ;"));
+        assert!(tokens.contains("__assert_custom_parser_impls :: < OtherParser > () ;"));
+        let tokens = import_tokens_attr_internal(
+            quote!(),
+            quote! {
+                #[proc_macro_attribute]
+                pub fn my_attr(attr: TokenStream, tokens: TokenStream) -> TokenStream {
+                    tokens
+                }
+            },
+        )
+        .unwrap()
+        .to_string();
+        assert!(!tokens.contains("__assert_custom_parser_impls"));
     }
 
     #[test]
-    fn export_tokens_internal_bad_ident() {
+    fn export_tokens_internal_hash() {
+        assert_eq!(tokens_hash(""), 0x811c9dc5);
+        assert_eq!(tokens_hash("a"), 0xe40c292c);
+        let widget: Item = parse_quote!(
+            struct Widget {
+                size: u32,
+            }
+        );
+        let suffix = export_tokens_hash_suffix(&widget);
+        assert_eq!(suffix.len(), 4);
+        assert!(suffix.chars().all(|c| ('a'..='p').contains(&c)));
+        assert_eq!(
+            to_snake_case(format!("widget_{}", suffix)),
+            format!("widget_{}", suffix)
+        );
+        // stable for identical tokens...
+        assert_eq!(suffix, export_tokens_hash_suffix(&widget.clone()));
+        // ...but changes along with the item body
+        let changed: Item = parse_quote!(
+            struct Widget {
+                size: u64,
+            }
+        );
+        assert_ne!(suffix, export_tokens_hash_suffix(&changed));
+        // ...and independent of the spacing of the tokens
+        let spaced: TokenStream2 = "struct Widget { size : u32 , }".parse().unwrap();
+        let compact: TokenStream2 = "struct Widget{size:u32,}".parse().unwrap();
+        assert_eq!(
+            tokens_hash_normalized(spaced.clone()),
+            tokens_hash_normalized(compact)
+        );
+        assert_eq!(
+            suffix,
+            export_tokens_hash_suffix(&parse2(spaced.clone()).unwrap())
+        );
+        let joint: TokenStream2 = "a::b<c>>=d".parse().unwrap();
+        let alone: TokenStream2 = "a : : b < c > > = d".parse().unwrap();
+        assert_eq!(tokens_hash_normalized(joint), tokens_hash_normalized(alone));
+        let invisible = TokenStream2::from(TokenTree::Group(proc_macro2::Group::new(
+            Delimiter::None,
+            spaced,
+        )));
+        assert_eq!(
+            suffix,
+            export_tokens_hash_suffix(&parse2(invisible).unwrap())
+        );
+        // token boundaries and delimiters are part of the hash
+        assert_ne!(
+            tokens_hash_normalized(quote!(ab c)),
+            tokens_hash_normalized(quote!(a bc))
+        );
+        assert_ne!(
+            tokens_hash_normalized(quote!((a))),
+            tokens_hash_normalized(quote!([a]))
+        );
+        let tokens = export_tokens_internal(quote!(hash), widget.to_token_stream(), true)
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("__export_tokens_tt_widget "));
+        assert!(tokens.contains(format!("__export_tokens_tt_widget_{} ", suffix).as_str()));
+    }
+
+    #[test]
+    fn export_tokens_internal_variants() {
+        let item = quote! {
+            pub enum Shape<T> {
+                Circle { radius: T },
+                Rect(T, T),
+                Empty,
+            }
+        };
+        let tokens = export_tokens_internal(quote!(variants), item.clone(), true)
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("__export_tokens_tt_shape "));
+        assert!(tokens.contains("__export_tokens_tt_shape_circle"));
+        assert!(tokens.contains("__export_tokens_tt_shape_rect"));
+        assert!(tokens.contains("__export_tokens_tt_shape_empty"));
+        let tokens = export_tokens_internal(quote!(Figure, variants), item, false)
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("__export_tokens_tt_figure_circle"));
         assert!(export_tokens_internal(
-            quote!(Something<T>),
+            quote!(variants),
             quote!(
-                struct MyStruct {}
+                struct Foo;
             ),
-            true,
+            true
         )
         .is_err());
         assert!(export_tokens_internal(
-            quote!(some::path),
+            quote!(Foo, Bar),
             quote!(
-                struct MyStruct {}
+                struct Foo;
             ),
-            true,
+            true
         )
         .is_err());
+
+        let item_enum: ItemEnum = parse_quote! {
+            pub enum Shape<T> where T: Copy {
+                Circle { radius: T },
+                Rect(T, T),
+                Empty,
+            }
+        };
+        let structs: Vec<String> = item_enum
+            .variants
+            .iter()
+            .map(|variant| {
+                enum_variant_struct(&item_enum, variant)
+                    .to_token_stream()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(
+            structs,
+            [
+                "pub struct Circle < T > where T : Copy { radius : T }",
+                "pub struct Rect < T > (T , T) where T : Copy ;",
+                "pub struct Empty < T > where T : Copy ;",
+            ]
+        );
     }
 
     #[test]
-    fn test_export_tokens_no_emit() {
-        assert!(export_tokens_internal(
-            quote!(some_name),
-            quote!(
-                struct Something {}
-            ),
-            false,
-        )
+    fn forward_tokens_internal_multiple_extras() {
+        let root = macro_magic_root().to_token_stream().to_string();
+        let tokens = forward_tokens_internal(quote!(SomeStruct, receiver, mm, "a"))
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("mm :: __private :: forward_tokens_inner , \"a\""));
+        let tokens = forward_tokens_internal(quote!(SomeStruct, receiver, mm, "a~~b", 'c', 42))
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("mm :: __private :: forward_tokens_extras_inner"));
+        let packed = format!(
+            "{}~~{}~~{}",
+            escape_extra("\"a~~b\""),
+            escape_extra("'c'"),
+            escape_extra("42")
+        );
+        let tokens = forward_tokens_extras_inner_internal(quote!(
+            receiver,
+            struct SomeStruct;,
+            #packed
+        ))
         .unwrap()
-        .to_string()
-        .contains("some_name"));
+        .to_string();
+        assert_eq!(
+            tokens,
+            "receiver ! { struct SomeStruct ; , \"a~~b\" , 'c' , 42 }"
+        );
+        assert!(forward_tokens_extras_inner_internal(quote!(
+            receiver,
+            struct SomeStruct;,
+            "not a ~~ literal"
+        ))
+        .is_err());
+        assert!(forward_tokens_internal(quote!(SomeStruct, receiver, mm, foo)).is_err());
+        assert!(forward_tokens_internal(quote!(SomeStruct, receiver))
+            .unwrap()
+            .to_string()
+            .contains(format!("{} :: __private :: forward_tokens_inner }}", root).as_str()));
     }
 
     #[test]
-    fn import_tokens_internal_simple_path() {
+    fn forward_tokens_internal_transform() {
+        let tokens = forward_tokens_internal(quote!(my_crate::SomeStruct => rename => receiver))
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("my_crate :: __export_tokens_tt_some_struct"));
+        assert!(tokens.contains("forward_tokens_transform_inner"));
+        assert!(tokens.contains("\"rename , :: macro_magic\""));
         assert!(
-            import_tokens_internal(quote!(let tokens = my_crate::SomethingCool))
-                .unwrap()
-                .to_string()
-                .contains("__export_tokens_tt_something_cool")
+            forward_tokens_internal(quote!(SomeStruct => rename => receiver, mm, "a")).is_err()
+        );
+        assert!(forward_tokens_internal(quote!(SomeStruct => rename, receiver)).is_err());
+        assert!(forward_tokens_spanned_internal(quote!(SomeStruct => rename => receiver)).is_err());
+
+        let tokens = forward_tokens_transform_inner_internal(quote! {
+            receiver,
+            struct Foo {},
+            "rename , ::macro_magic"
+        })
+        .unwrap();
+        assert_eq!(
+            tokens.to_string(),
+            "# [rename] # [:: macro_magic :: __private :: forward_tokens_transformed (receiver)] \
+            struct Foo { }"
+        );
+        assert_eq!(
+            forward_tokens_transformed_internal(
+                quote!(receiver),
+                quote!(
+                    struct Bar {}
+                )
+            )
+            .unwrap()
+            .to_string(),
+            "receiver ! { struct Bar { } }"
         );
+        assert!(forward_tokens_transformed_internal(quote!(receiver), quote!(1 + 2)).is_err());
     }
 
     #[test]
-    fn import_tokens_internal_flatten_long_paths() {
-        assert!(import_tokens_internal(
-            quote!(let tokens = my_crate::some_mod::complex::SomethingElse)
-        )
-        .unwrap()
-        .to_string()
-        .contains("__export_tokens_tt_something_else"));
+    fn import_tokens_attr_context_round_trip() {
+        let string = |tokens: &dyn ToTokens| tokens.to_token_stream().to_string();
+        let mut context = ImportTokensAttrContext::new(
+            parse_quote!(
+                struct Attached {
+                    field: u32,
+                }
+            ),
+            alloc::vec![parse_quote!(a::B), parse_quote!(c)],
+            quote!(a::B, "~~weird~~"),
+            quote!(a::B, c),
+        );
+        assert_eq!(string(context.next_source_path().unwrap()), "a :: B");
+        context.foreign_items.push(parse_quote!(
+            struct B;
+        ));
+        let unpacked = ImportTokensAttrContext::from_extra(&context.to_extra()).unwrap();
+        assert_eq!(string(&unpacked.item), string(&context.item));
+        assert_eq!(unpacked.source_paths.len(), 2);
+        assert_eq!(string(unpacked.source_path().unwrap()), "a :: B");
+        assert_eq!(
+            string(&unpacked.custom_parsed),
+            string(&context.custom_parsed)
+        );
+        assert_eq!(string(&unpacked.raw_attr), string(&context.raw_attr));
+        assert_eq!(unpacked.foreign_items.len(), 1);
+        assert_eq!(string(&unpacked.foreign_items[0]), "struct B ;");
+        assert_eq!(string(unpacked.next_source_path().unwrap()), "c");
     }
 
     #[test]
-    fn import_tokens_internal_invalid_token_ident() {
-        assert!(import_tokens_internal(quote!(let 3 * 2 = my_crate::something)).is_err());
+    fn import_tokens_attr_context_malformed() {
+        let malformed = |extra: &str| {
+            ImportTokensAttrContext::from_extra(&LitStr::new(extra, Span::call_site()))
+                .err()
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(
+            malformed(""),
+            "malformed `extra`: expected at least 4 fields but found 1"
+        );
+        assert!(malformed("struct Foo;~~not a path~~~~").contains("invalid source path"));
+        assert!(malformed("not an item~~a::b~~~~").contains("invalid attached item"));
+        assert!(malformed("struct Foo;~~a::b~~(~~").contains("invalid custom parsed tokens"));
+        assert!(malformed("struct Foo;~~a::b~~~~~~1 + 1").contains("invalid foreign item"));
+        assert!(malformed("struct Foo;~~a::b~~~~)").contains("invalid raw attr"));
     }
 
     #[test]
-    fn import_tokens_internal_invalid_path() {
-        assert!(import_tokens_internal(quote!(let my_tokens = 2 - 2)).is_err());
+    fn forward_tokens_inner_malformed_extra() {
+        let transform_err = |extra: &str| {
+            forward_tokens_transform_inner_internal(quote!(receiver, struct Foo;, #extra))
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(transform_err("1, mm").starts_with("malformed `extra`: invalid transform path"));
+        assert!(transform_err("my::transform")
+            .starts_with("malformed `extra`: invalid `macro_magic` path"));
+        assert!(transform_err("my::transform, mm, extra")
+            .starts_with("malformed `extra`: invalid transform"));
+        assert!(forward_tokens_transform_inner_internal(quote!(
+            receiver,
+            struct Foo;,
+            "my::transform, mm"
+        ))
+        .is_ok());
+        let err = forward_tokens_extras_inner_internal(quote!(receiver, struct Foo;, "1~~("))
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("malformed `extra`: invalid literal"));
+        let err = import_tokens_mod_inner_internal(quote!(my_mod, struct Foo;, "pub(nowhere"))
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("malformed `extra`: invalid module visibility"));
     }
 
     #[test]
-    fn import_tokens_inner_internal_basic() {
-        assert!(import_tokens_inner_internal(quote! {
-            my_ident,
-            fn my_function() -> u32 {
-                33
-            }
-        })
-        .unwrap()
-        .to_string()
-        .contains("my_ident"));
+    fn escape_extra_round_trip() {
+        for value in ["", "a~~b", "ends with ~", "~", "\\~\\", "\\-", "a\\\\b~~~"] {
+            let escaped = escape_extra(value);
+            assert!(!escaped.contains('~'));
+            assert_eq!(unescape_extra(&escaped), value);
+        }
+        // a field ending in `~` no longer bleeds into the delimiter
+        let joined = format!("{}~~{}", escape_extra("a ~"), escape_extra("b"));
+        let fields: Vec<String> = joined.split("~~").map(unescape_extra).collect();
+        assert_eq!(fields, ["a ~", "b"]);
     }
 
     #[test]
-    fn import_tokens_inner_internal_impl() {
-        assert!(import_tokens_inner_internal(quote! {
-            another_ident,
-            impl Something for MyThing {
-                fn something() -> CoolStuff {
-                    CoolStuff {}
+    fn escape_extra_adversarial() {
+        for value in [
+            "~~",
+            "~~~~",
+            "\\",
+            "trailing backslash \\",
+            "\\~\\~",
+            "\\-\\-",
+            "\\\\-",
+            "-~-",
+            "\"~~\"",
+        ] {
+            assert_eq!(unescape_extra(escape_extra(value)), value);
+        }
+        // exhaustively round-trip every string of up to 6 chars over the significant alphabet
+        let alphabet = ['~', '\\', '-', 'a'];
+        let mut values = [String::new()].to_vec();
+        for _ in 0..6 {
+            let mut longer = Vec::new();
+            for value in &values {
+                for c in alphabet {
+                    let mut value = value.clone();
+                    value.push(c);
+                    assert_eq!(unescape_extra(escape_extra(&value)), value);
+                    assert!(!escape_extra(&value).contains('~'));
+                    longer.push(value);
                 }
             }
-        })
-        .unwrap()
-        .to_string()
-        .contains("something ()"));
+            values = longer;
+        }
     }
 
     #[test]
-    fn import_tokens_inner_internal_missing_comma() {
-        assert!(import_tokens_inner_internal(quote! {
-            {
-                another_ident
-                impl Something for MyThing {
-                    fn something() -> CoolStuff {
-                        CoolStuff {}
-                    }
-                }
-            }
-        })
-        .is_err());
+    fn pack_extras_round_trip() {
+        let fields = ["a~~b", "", "c\\~"];
+        assert_eq!(unpack_extra(&pack_extras(fields)).unwrap(), fields);
+        for (a, b, c) in [
+            ("~", "~", "~"),
+            ("\\", "~~", "-"),
+            ("", "", ""),
+            ("x~", "~y", "\\"),
+        ] {
+            assert_eq!(unpack_extra(&pack_extras([a, b, c])).unwrap(), [a, b, c]);
+        }
+        assert_eq!(unpack_extra(&pack_extras([""; 0])).unwrap(), [""]);
     }
 
     #[test]
-    fn import_tokens_inner_internal_non_item() {
-        assert!(import_tokens_inner_internal(quote! {
-            {
-                another_ident,
-                2 + 2
+    fn unpack_extra_field_counts() {
+        let packed = pack_extras(["struct Foo;", "a~~b", "c\\~"]);
+        assert_eq!(
+            unpack_extra_exact(&packed, 3).unwrap(),
+            ["struct Foo;", "a~~b", "c\\~"]
+        );
+        assert_eq!(
+            unpack_extra_exact(&packed, 4),
+            Err(ExtraParseError::FieldCount {
+                expected: 4,
+                found: 3
+            })
+        );
+        let err = unpack_extra_exact(&packed, 2).unwrap_err();
+        assert_eq!(
+            err,
+            ExtraParseError::FieldCount {
+                expected: 2,
+                found: 3
             }
-        })
-        .is_err());
+        );
+        assert_eq!(err.to_string(), "expected 2 fields but found 3");
+        assert_eq!(unpack_extra_exact("", 1).unwrap(), [""]);
+        // input that `pack_extras` can't produce is rejected rather than unpacked leniently
+        assert_eq!(
+            unpack_extra("a~b"),
+            Err(ExtraParseError::UnescapedTilde { index: 1 })
+        );
+        assert_eq!(
+            unpack_extra("a~~~b"),
+            Err(ExtraParseError::UnescapedTilde { index: 3 })
+        );
+        assert_eq!(
+            unpack_extra("a\\x"),
+            Err(ExtraParseError::InvalidEscape { index: 1 })
+        );
+        assert_eq!(
+            unpack_extra("a\\").unwrap_err().to_string(),
+            "invalid escape sequence at byte 1"
+        );
+        let err = forward_tokens_extras_inner_internal(quote!(receiver, struct Foo;, "1~~~2"))
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "malformed `extra`: unescaped `~` at byte 3");
     }
 
     #[test]
-    fn test_parse_use_stmt() {
-        assert!(use_internal(
-            quote!(),
-            quote!(
-                use some::path;
-            ),
-            ProcMacroType::Attribute,
-        )
-        .is_ok());
-        assert!(use_internal(
+    fn import_tokens_attr_context_two_fields() {
+        let extra = format!("{}~~{}", escape_extra("struct Foo;"), escape_extra("a::b"));
+        let err = ImportTokensAttrContext::from_extra(&LitStr::new(&extra, Span::call_site()))
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "malformed `extra`: expected at least 4 fields but found 2"
+        );
+        let tokens = import_tokens_attr_internal(
             quote!(),
-            quote!(
-                use some::path
-            ),
-            ProcMacroType::Normal,
+            quote! {
+                #[proc_macro_attribute]
+                pub fn my_attr(attr: TokenStream, tokens: TokenStream) -> TokenStream {
+                    attr
+                }
+            },
         )
-        .is_err());
-        assert!(use_internal(
-            quote!(),
-            quote!(
-                use some::
-            ),
-            ProcMacroType::Attribute,
+        .unwrap()
+        .to_string();
+        // malformed `extra` becomes a compile error rather than a panic
+        assert!(tokens.contains("ImportTokensAttrContext :: from_extra"));
+        assert!(tokens.contains("Err (err) => return err . to_compile_error () . into ()"));
+        assert!(!tokens.contains("unwrap"));
+    }
+
+    #[test]
+    fn export_tokens_internal_const() {
+        let item = quote!(
+            pub struct MyStruct;
+        );
+        let tokens = export_tokens_internal(quote!(export_const), item.clone(), true)
+            .unwrap()
+            .to_string();
+        assert!(tokens
+            .contains("pub const __EXPORT_TOKENS__MY_STRUCT : & str = \"pub struct MyStruct ;\""));
+        // the const is opt-in
+        let tokens = export_tokens_internal(quote!(), item.clone(), true)
+            .unwrap()
+            .to_string();
+        assert!(!tokens.contains("__EXPORT_TOKENS__"));
+        assert!(export_tokens_internal(quote!(export_const), item, false).is_err());
+        let tokens = export_tokens_internal(
+            quote!(MyImpl, export_const),
+            quote!(impl Foo for Bar {}),
+            true,
         )
-        .is_err());
-        assert!(use_internal(
-            quote!(),
+        .unwrap()
+        .to_string();
+        assert!(tokens.contains("const __EXPORT_TOKENS__MY_IMPL"));
+        assert!(!tokens.contains("pub const"));
+        assert_eq!(
+            export_tokens_const_ident(&parse_quote!(HTTPServer2_x)),
+            "__EXPORT_TOKENS__H_T_T_P_SERVER_2_X"
+        );
+        assert_eq!(
+            export_tokens_const_ident(&parse_quote!(r#type)),
+            "__EXPORT_TOKENS__TYPE"
+        );
+    }
+
+    #[test]
+    fn export_tokens_internal_make_pub() {
+        let tokens = export_tokens_internal(
+            quote!(make_pub),
             quote!(
-                pub use some::long::path;
+                struct Private {
+                    field: u32,
+                }
             ),
-            ProcMacroType::Attribute,
+            true,
         )
-        .is_ok());
+        .unwrap();
+        let file = syn::parse2::<syn::File>(tokens).unwrap();
+        let mut stored = None;
+        let mut emitted = None;
+        for item in file.items {
+            match item {
+                Item::Macro(item_macro) => stored = Some(item_macro.mac.tokens.to_string()),
+                Item::Struct(item_struct) => emitted = Some(item_struct),
+                _ => (),
+            }
+        }
+        // the stored tokens retain the original visibility...
+        #[cfg(not(feature = "compress"))]
+        {
+            let stored = stored.unwrap();
+            assert!(stored.contains("struct Private"));
+            assert!(!stored.contains("pub struct Private"));
+        }
+        #[cfg(feature = "compress")]
+        let _ = stored.unwrap();
+        // ...but the emitted item is `pub`
+        assert!(matches!(emitted.unwrap().vis, Visibility::Public(_)));
+        assert!(
+            export_tokens_internal(quote!(my_impl, make_pub), quote!(impl Foo {}), true).is_err()
+        );
     }
 
     #[test]
@@ -1116,4 +8534,32 @@ mod tests {
         );
         assert_eq!(to_snake_case("".to_string()), "");
     }
+
+    #[test]
+    fn test_shouty_snake_case() {
+        assert_eq!(to_shouty_snake_case("ThisIsATriumph"), "THIS_IS_A_TRIUMPH");
+        assert_eq!(
+            to_shouty_snake_case("IAmMakingANoteHere"),
+            "I_AM_MAKING_A_NOTE_HERE"
+        );
+        assert_eq!(to_shouty_snake_case("huge_success"), "HUGE_SUCCESS");
+        assert_eq!(
+            to_shouty_snake_case("It's hard to   Overstate my satisfaction!!!"),
+            "ITS_HARD_TO_OVERSTATE_MY_SATISFACTION"
+        );
+        assert_eq!(
+            to_shouty_snake_case("__aperature_science__"),
+            "__APERATURE_SCIENCE__"
+        );
+        assert_eq!(
+            to_shouty_snake_case("WeDoWhatWeMustBecause!<We, Can>()"),
+            "WE_DO_WHAT_WE_MUST_BECAUSE_WE_CAN"
+        );
+        assert_eq!(to_shouty_snake_case("HTTPServer2"), "H_T_T_P_SERVER_2");
+        assert_eq!(to_shouty_snake_case("".to_string()), "");
+        assert_eq!(
+            export_tokens_const_ident(&format_ident!("r#ThisIsATriumph")),
+            "__EXPORT_TOKENS__THIS_IS_A_TRIUMPH"
+        );
+    }
 }
@@ -7,9 +7,26 @@ use syn::{parse::Nothing, parse_macro_input};
 #[proc_macro]
 pub fn get_macro_magic_root(tokens: TokenStream) -> TokenStream {
     let _ = parse_macro_input!(tokens as Nothing);
-    let root = match option_env!("MACRO_MAGIC_ROOT") {
-        Some(root) => root,
-        None => "::macro_magic",
-    };
+    let root = option_env!("MACRO_MAGIC_ROOT").unwrap_or("::macro_magic");
     quote!(#root).into()
 }
+
+#[proc_macro]
+pub fn get_macro_magic_export_prefix(tokens: TokenStream) -> TokenStream {
+    let _ = parse_macro_input!(tokens as Nothing);
+    let prefix = option_env!("MACRO_MAGIC_EXPORT_PREFIX").unwrap_or("__export_tokens_tt_");
+    if prefix.is_empty()
+        || prefix.starts_with(|c: char| c.is_ascii_digit())
+        || !prefix
+            .chars()
+            .all(|c| c == '_' || c.is_ascii_alphanumeric())
+    {
+        return syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "environment variable `MACRO_MAGIC_EXPORT_PREFIX` must be a valid identifier prefix",
+        )
+        .to_compile_error()
+        .into();
+    }
+    quote!(#prefix).into()
+}
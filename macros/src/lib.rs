@@ -1,5 +1,7 @@
 #![no_std]
 
+extern crate std;
+
 use macro_magic_core::*;
 use proc_macro::TokenStream;
 
@@ -13,13 +15,22 @@ use proc_macro::TokenStream;
 /// would collide with `my_cool_name`, resulting in a compiler error if these items are being
 /// exported from the same crate.
 ///
+/// The option keywords described below (listed in
+/// [`macro_magic_core::EXPORT_TOKENS_OPTIONS`]) take precedence over the override name, so
+/// `#[export_tokens(value)]` enables the `value` option rather than exporting the item as
+/// `value`. To export under one of these names, use the explicit form
+/// `#[export_tokens(name = value)]`, which accepts any ident.
+///
 /// The reason this is true of items in the same _crate_ rather than just the same _module_ is
 /// because internally `#[export_tokens]` creates a `macro_rules!` / decl macro and utilizes
 /// callbacks to communicate the underlying tokens of the foreign item to whatever external
 /// macros might request this information, and decl macro names collide on a crate-wide basis.
 /// This is why when _importing_ tokens, specifying the full path other than
 /// `my_crate::my_item` is optional, since all exported tokens can be accessed directly from
-/// the crate root.
+/// the crate root. The full path is still accepted though: `my_crate::some::module::my_item`
+/// resolves to the same callback as `my_crate::my_item`, since every segment between the
+/// crate and the item is ignored. There is therefore no need to re-export the callback macro
+/// under the module path of the item.
 ///
 /// A convenient further implication of this design decision is that the visibility of the
 /// module containing the item you are exporting does not interfere with the accessibility of
@@ -29,7 +40,152 @@ use proc_macro::TokenStream;
 /// Note also that some types of items, namely [`syn::ItemForeignMod`], [`syn::ItemUse`],
 /// [`syn::ItemImpl`], and [`syn::Item::Verbatim`], do not have an inherent concept of a naming
 /// ident, and so for these items specifying an override name is required or you will get a
-/// compiler error. This also applies to `macro_rules!` definitions that do not specify a name.
+/// compiler error. This also applies to macro invocations other than `macro_rules!`
+/// definitions, which are exported under the name of the macro they define. Since their `$`
+/// metavariables would otherwise clash with the internal `macro_rules!`, the tokens of such
+/// definitions are always stored as if the `compress` feature were enabled, and so lose their
+/// spans.
+///
+/// Attributes attached to the item are exported along with it, regardless of whether they
+/// appear above or below `#[export_tokens]`. The one exception is `#[derive]` attributes
+/// placed _above_ `#[export_tokens]`: the compiler does not pass these to attribute macros at
+/// all, so while they are still applied to the emitted item, they won't appear in the
+/// exported tokens. Place derives below `#[export_tokens]` if you need them to be exported.
+///
+/// Stacking several `#[export_tokens]` attributes on the same item is only allowed if each of
+/// them exports the item under a different name. Otherwise a compile error pointing at the
+/// extra attribute is emitted, instead of a confusing duplicate macro definition error.
+///
+/// When applied to an enum, the `variants` option (e.g. `#[export_tokens(variants)]` or
+/// `#[export_tokens(SomeOtherName, variants)]`) additionally exports each variant of the enum
+/// on its own, under the name `<enum>_<variant>` (flattened to snake_case like any other
+/// export name). The tokens exported for each variant are those of a synthesized struct named
+/// after the variant, with the same fields as the variant, so that they can be imported as a
+/// regular [`syn::Item`].
+///
+/// The `no_emit` option (e.g. `#[export_tokens(no_emit)]`) has the same effect as using
+/// [`#[export_tokens_no_emit]`](`macro@export_tokens_no_emit`) instead, and is mainly useful
+/// with aliases created via [`export_tokens_alias!`](`macro@export_tokens_alias`).
+///
+/// The `make_pub` option (e.g. `#[export_tokens(make_pub)]`) causes the item to be emitted
+/// with `pub` visibility, so that it is reachable from elsewhere. This only affects the
+/// emitted item: the exported tokens retain the item's original visibility.
+///
+/// The `hash` option (e.g. `#[export_tokens(hash)]`) additionally exports the item under its
+/// name suffixed with a short hash of its tokens, such as `widget_jdfk`. Importing the item
+/// under this hashed name will stop compiling as soon as the upstream item changes, which is
+/// useful for detecting changes across crate boundaries.
+///
+/// When applied to a function, the `body` and `sig` options (e.g.
+/// `#[export_tokens(body, sig)]`) additionally export the body of the function (as a
+/// [`syn::Block`]) and its signature (as a [`syn::Signature`]) on their own, under the names
+/// `<fn>_body` and `<fn>_sig` respectively. Since these aren't full items, they can only be
+/// imported via [`import_tokens!`](`macro@import_tokens`),
+/// [`import_tokens_str!`](`macro@import_tokens_str`) and
+/// [`forward_tokens_spanned!`](`macro@forward_tokens_spanned`).
+///
+/// Similarly, when applied to a trait, the `items` option (e.g. `#[export_tokens(items)]`)
+/// additionally exports each associated fn, type and const of the trait on its own, under the
+/// name `<trait>_<item>`. The exported tokens are those of the [`syn::TraitItem`], so like
+/// `body` and `sig`, they can only be imported via the macros listed above.
+///
+/// When applied to a union or a struct with named fields, the `fields` option (e.g.
+/// `#[export_tokens(fields)]`) additionally exports each field on its own, under the name
+/// `<item>_<field>`, which is handy for generating accessors, e.g. for `#[repr(C)]` unions used
+/// in FFI code. The exported tokens are those of the [`syn::Field`] (e.g. `as_int : u32`),
+/// including its attributes, so they can also only be imported via the macros listed above.
+/// The attributes of the item itself, such as `#[repr(C)]`, remain part of the tokens of the
+/// item.
+///
+/// With the `dump` feature enabled, the `dump` option (e.g.
+/// `#[export_tokens(dump = "target/tokens")]`) additionally writes the tokens of the item to a
+/// JSON file named after the flattened export name (e.g. `target/tokens/my_struct.json`), so
+/// that they can be consumed by non-Rust tooling. Relative directories are resolved against
+/// the directory containing the `Cargo.toml` of the crate being compiled. The file contains a
+/// single JSON object with the following fields:
+/// - `ident`: the flattened export name (see [`macro_magic_core::flatten_ident`])
+/// - `kind`: the kind of item, such as `"struct"` or `"fn"` (see
+///   [`macro_magic_core::item_kind`])
+/// - `tokens`: the stringified tokens of the item
+///
+/// The `version` option (e.g. `#[export_tokens(version = 2)]`) declares a "schema version" for
+/// the exported tokens, which importers can check via the `min_version` argument of
+/// [`import_tokens!`](`macro@import_tokens`).
+///
+/// The `#[cfg_attr(..)]`s of the item itself are evaluated by the compiler before
+/// `#[export_tokens]` sees the item, so the exported tokens contain their expansion under the
+/// exporting crate's configuration. Those nested within the item (such as on fields) are
+/// stored unexpanded by default, so they are evaluated under the configuration of wherever the
+/// tokens end up being expanded. The `cfg_attr` option resolves them in the exported tokens
+/// instead, either as if their predicates held (`#[export_tokens(cfg_attr = apply)]`) or as if
+/// they didn't (`#[export_tokens(cfg_attr = strip)]`). The emitted item is unaffected.
+///
+/// The `with_generics` option (e.g. `#[export_tokens(with_generics)]`) additionally stores the
+/// generic parameters and where clause of the item on their own, so that they can be imported
+/// via [`import_tokens_generics!`](`macro@import_tokens_generics`) without having to parse
+/// them out of the full item. It can be used on any item that can have generics, such as
+/// structs, enums, fns, traits and impls.
+///
+/// The `strict` option (e.g. `#[export_tokens(strict)]`) rejects items containing inner
+/// attributes (`#![...]`), such as a module starting with `#![allow(unused)]`. Such attributes
+/// are part of the exported tokens, but are only valid when those tokens are expanded as the
+/// same kind of item, so they can lead to confusing errors when the item is imported into a
+/// different context.
+///
+/// When applied to a `const`, the `value` option (e.g. `#[export_tokens(value)]`) additionally
+/// stores the value expression of the const on its own, so that it can be pulled directly into
+/// expression position via [`import_tokens_value!`](`macro@import_tokens_value`).
+///
+/// The emitted item is marked `#[allow(unused)]` by default, since exported items are often
+/// only used through their tokens. The `warn_unused` option (e.g.
+/// `#[export_tokens(warn_unused)]`) emits the item without it, so that genuine unused warnings
+/// aren't masked.
+///
+/// The `local` option (e.g. `#[export_tokens(local)]`) generates the internal `macro_rules!`
+/// without `#[macro_export]`, so it doesn't take up a name at the root of your crate. The
+/// tokens of such items can only be imported further down in the same crate (following the
+/// usual textual scoping rules of `macro_rules!`), via
+/// `import_tokens!(let tokens = MyItem, local)`.
+///
+/// The `cfg` option (e.g. `#[export_tokens(cfg(feature = "extra"))]`) gates the internal
+/// `macro_rules!` behind the specified `cfg` predicate, so the tokens of the item are only
+/// exported when the predicate holds. Unlike attaching `#[cfg(..)]` to the item, the item
+/// itself is still emitted either way.
+///
+/// The `verbatim_source` option (e.g. `#[export_tokens(verbatim_source)]`) additionally stores
+/// the source text of the item exactly as it was written, including formatting and comments
+/// (which are otherwise lost, since comments aren't tokens), so that it can be imported via
+/// `import_tokens_str!(MyItem, verbatim_source)`. This relies on the compiler exposing the
+/// location of the item, which is supported on nightly and on stable Rust 1.88 or newer. Where
+/// that isn't possible, such as on older compilers or for items generated by other macros,
+/// the stringified tokens of the item are stored instead. Note that the text spans from the
+/// first to the last token of the item, so attributes placed above `#[export_tokens]` (and
+/// thus the `#[export_tokens]` attribute itself) are included. Aliases created via
+/// [`export_tokens_alias!`](`macro@export_tokens_alias`) only capture the source text on
+/// nightly.
+///
+/// The `export_const` option (e.g. `#[export_tokens(export_const)]`) additionally emits a
+/// `const` containing the stringified tokens of the item, with the same visibility as the
+/// item, so that the tokens can be referenced directly without any macros. The const is named
+/// `__EXPORT_TOKENS__` followed by the export name in SHOUTY_SNAKE_CASE, e.g.
+/// `__EXPORT_TOKENS__MY_STRUCT` for `MyStruct`. The stored string is never compressed, so this
+/// is opt-in to avoid doubling the size of every export (and adding an item to your API).
+///
+/// The `const_fn` option (e.g. `#[export_tokens(const_fn)]`) similarly emits a `const fn`
+/// returning the stringified tokens of the item, named after the export name in snake_case
+/// followed by `_tokens_str`, e.g. `my_struct_tokens_str()` for `MyStruct`. It has the same
+/// visibility as the item and can be called in const contexts. Since they are emitted along
+/// with the item, neither option can be combined with `no_emit`.
+///
+/// Trait aliases (e.g. `trait Shareable = Send + Sync;`) can be exported like any other item,
+/// but since they are still unstable, emitting them requires a nightly compiler and
+/// `#![feature(trait_alias)]`. On stable, the compiler accepts them as the input of
+/// [`#[export_tokens_no_emit]`](`macro@export_tokens_no_emit`) only with a future
+/// compatibility warning, so proc macros should instead use
+/// [`export_tokens_with_options`] with `emit(false)` from a function-like macro, whose input
+/// isn't parsed by the compiler. Importing the tokens of a trait alias works on any toolchain,
+/// since they are parsed by `syn` rather than the compiler, but emitting the imported tokens
+/// is again subject to the compiler's `trait aliases are experimental` error on stable.
 ///
 /// It is also possible to export tokens inside normally inaccessible scopes, such as inside a
 /// function definition, because `#[macro_export]` works even in these scenarios (and this use
@@ -65,6 +221,14 @@ use proc_macro::TokenStream;
 /// }
 /// ```
 ///
+/// Applied to an `extern` block requiring an override name:
+/// ```ignore
+/// #[export_tokens(c_bindings)]
+/// extern "C" {
+///     fn abs(input: i32) -> i32;
+/// }
+/// ```
+///
 /// Applied to a struct, but specifying an override name:
 /// ```ignore
 /// #[export_tokens(SomeOtherName)]
@@ -74,7 +238,10 @@ use proc_macro::TokenStream;
 /// ```
 #[proc_macro_attribute]
 pub fn export_tokens(attr: TokenStream, tokens: TokenStream) -> TokenStream {
-    match export_tokens_internal(attr, tokens, true) {
+    if let Err(err) = dump_export_tokens(&attr, &tokens) {
+        return err.to_compile_error().into();
+    }
+    match export_tokens_with_source(attr, tokens, true) {
         Ok(tokens) => tokens.into(),
         Err(err) => err.to_compile_error().into(),
     }
@@ -87,7 +254,43 @@ pub fn export_tokens(attr: TokenStream, tokens: TokenStream) -> TokenStream {
 /// and/or do not need to be used locally.
 #[proc_macro_attribute]
 pub fn export_tokens_no_emit(attr: TokenStream, tokens: TokenStream) -> TokenStream {
-    match export_tokens_internal(attr, tokens, false) {
+    if let Err(err) = dump_export_tokens(&attr, &tokens) {
+        return err.to_compile_error().into();
+    }
+    match export_tokens_with_source(attr, tokens, false) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Exports each of the items passed to it as if it had been annotated with
+/// [`#[export_tokens]`](`macro@export_tokens`), so all the top-level items of a file (or of a
+/// module) can be exported in one go by wrapping them in a single call.
+///
+/// Each item is exported under its own name. Items with no inherent name, such as `impl`
+/// blocks and `use` statements, are emitted unchanged and trigger a warning (as a use of a
+/// deprecated item), since they can only be exported by giving them a name via
+/// `#[export_tokens(some_name)]`. Items can still be annotated with
+/// [`#[export_tokens]`](`macro@export_tokens`) to export them under an additional name.
+///
+/// ## Example
+///
+/// ```ignore
+/// export_tokens_file! {
+///     pub struct Config {
+///         pub name: &'static str,
+///     }
+///
+///     pub const DEFAULT_NAME: &str = "config";
+///
+///     pub fn load() -> Config {
+///         Config { name: DEFAULT_NAME }
+///     }
+/// }
+/// ```
+#[proc_macro]
+pub fn export_tokens_file(tokens: TokenStream) -> TokenStream {
+    match export_tokens_file_internal(tokens) {
         Ok(tokens) => tokens.into(),
         Err(err) => err.to_compile_error().into(),
     }
@@ -99,6 +302,18 @@ pub fn export_tokens_no_emit(attr: TokenStream, tokens: TokenStream) -> TokenStr
 /// Simply pass an ident to this proc macro, and an alias for
 /// [`#[export_tokens]`](`macro@export_tokens`) will be created with the specified name.
 ///
+/// The alias emits the item by default, but this can be overridden at each use site with the
+/// `no_emit` option (e.g. `#[my_alias(no_emit)]`), so a single alias can be used in both
+/// modes. Likewise, the `emit` option overrides aliases created via
+/// [`export_tokens_alias_no_emit!`](`macro@export_tokens_alias_no_emit`).
+///
+/// An optional prefix for the names of the generated callback macros can be passed after the
+/// alias (e.g. `export_tokens_alias!(my_export, prefix = "__my_lib_tt_")`), so that items
+/// exported via the alias can't collide with items exported via
+/// [`#[export_tokens]`](`macro@export_tokens`) or other aliases. Such items must then be
+/// imported with the same prefix, e.g.
+/// `import_tokens!(let tokens = SomeItem, prefix = "__my_lib_tt_")`.
+///
 /// Can only be used within a proc macro crate.
 #[proc_macro]
 pub fn export_tokens_alias(tokens: TokenStream) -> TokenStream {
@@ -131,8 +346,10 @@ pub fn export_tokens_alias_no_emit(tokens: TokenStream) -> TokenStream {
 /// arguments passed are idents rather than paths (can't contain `::`). This is an unfortunate
 /// side effect of how decl macros are implemented in Rust
 ///
-/// There is also an optional third argument called "extra" which allows you to forward string
-/// literal data to the target macro. This is used by
+/// After the optional `macro_magic` path argument, any number of "extra" literals can be
+/// specified, which are forwarded to the target macro after the item, in order, e.g.
+/// `forward_tokens!(LionStruct, receiver, macro_magic, "a", "b", 42)` would result in
+/// `receiver! { struct LionStruct {}, "a", "b", 42 }`. This is used by
 /// [`#[import_tokens_attr]`](`macro@import_tokens_proc`) to pass the tokens for the attached
 /// item in addition to the tokens for the external item.
 ///
@@ -149,6 +366,30 @@ pub fn export_tokens_alias_no_emit(tokens: TokenStream) -> TokenStream {
 /// let result = forward_tokens!(LionStruct, receiver);
 /// assert_eq!(result, "struct LionStruct {}");
 /// ```
+///
+/// ## Transforms
+///
+/// The item can also be passed through a transform before it is delivered to the target,
+/// using the syntax `forward_tokens!(source => transform => target)`. The transform must be
+/// an attribute macro: it is attached to the forwarded item, and whatever it emits is what the
+/// target receives. To satisfy this contract, the transform must emit exactly one valid
+/// [`syn::Item`], and must keep any other attributes attached to that item (this is how the
+/// transformed item finds its way to the target). The optional `extra` argument is not
+/// supported when forwarding through a transform, and since attribute macros can only be
+/// applied to items, this form can only be used in item contexts.
+///
+/// ```ignore
+/// #[proc_macro_attribute]
+/// pub fn rename_struct(_attr: TokenStream, tokens: TokenStream) -> TokenStream {
+///     let mut item_struct = parse_macro_input!(tokens as ItemStruct);
+///     item_struct.ident = format_ident!("{}Renamed", item_struct.ident);
+///     item_struct.to_token_stream().into()
+/// }
+/// ```
+///
+/// ```ignore
+/// forward_tokens!(LionStruct => rename_struct => receiver);
+/// ```
 #[proc_macro]
 pub fn forward_tokens(tokens: TokenStream) -> TokenStream {
     match forward_tokens_internal(tokens) {
@@ -157,6 +398,115 @@ pub fn forward_tokens(tokens: TokenStream) -> TokenStream {
     }
 }
 
+/// Like [`forward_tokens!`](`macro@forward_tokens`), but forwards the tokens of the exported
+/// item to a target chosen by key from a table, so the same item can be routed to different
+/// receivers depending on context, e.g. `forward_tokens_dynamic!(LionStruct, my_table, debug)`.
+///
+/// The table is a `macro_rules!` with an arm per key, each matching the key followed by a
+/// comma and the tokens of the item (`($key, $($tokens:tt)*)`) and passing the tokens on to
+/// the target for that key. Any other key fails to match the table, resulting in a "no rules
+/// expected" compile error pointing at the key. Like the target of `forward_tokens!`, the
+/// table must be in scope where `forward_tokens_dynamic!` is used, and its targets receive
+/// just the item.
+///
+/// ## Example
+///
+/// ```ignore
+/// macro_rules! my_table {
+///     (debug, $($tokens:tt)*) => {
+///         debug_receiver! { $($tokens)* }
+///     };
+///     (display, $($tokens:tt)*) => {
+///         my_crate::display_receiver! { $($tokens)* }
+///     };
+/// }
+///
+/// forward_tokens_dynamic!(LionStruct, my_table, debug);
+/// ```
+#[proc_macro]
+pub fn forward_tokens_dynamic(tokens: TokenStream) -> TokenStream {
+    match forward_tokens_dynamic_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Like [`forward_tokens!`](`macro@forward_tokens`), but hands the tokens of the exported item
+/// to the target macro exactly as they were stored, rather than re-parsing them as a
+/// [`syn::Item`] along the way. This is useful for target macros that want to emit diagnostics
+/// pointing at specific parts of the forwarded item (such as an individual struct field).
+///
+/// Spans are preserved in the following situations:
+/// - When the exported item lives in the same crate as the `forward_tokens_spanned!`
+///   invocation, spans point at the original item.
+/// - When the exported item lives in another crate, spans point at the original item within
+///   the source of that crate. Diagnostics will be reported there, which is usually only
+///   useful if that crate is part of your workspace.
+///
+/// Spans are _not_ preserved when the exporting crate has the `compress` feature enabled,
+/// since in that case the tokens are stored as a string and re-lexed on import. For the same
+/// reason, the tokens of the attached item that
+/// [`#[import_tokens_attr]`](`macro@import_tokens_attr`) passes along via `extra` never
+/// preserve their spans.
+///
+/// ## Example
+///
+/// ```ignore
+/// #[proc_macro]
+/// pub fn field_line(tokens: TokenStream) -> TokenStream {
+///     let item_struct = parse_macro_input!(tokens as ItemStruct);
+///     let field = item_struct.fields.iter().next().unwrap();
+///     let line = field.span().unwrap().line() as u32;
+///     quote!(const FIELD_LINE: u32 = #line;).into()
+/// }
+/// ```
+///
+/// ```ignore
+/// forward_tokens_spanned!(SomeStruct, field_line);
+/// ```
+#[proc_macro]
+pub fn forward_tokens_spanned(tokens: TokenStream) -> TokenStream {
+    match forward_tokens_spanned_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Imports the tokens of the specified exported struct and generates a `<Name>Builder` struct
+/// for it, with a setter method for each field and a `build` method that returns the built
+/// struct.
+///
+/// Fields whose type is an `Option<T>` are optional: their setter takes a `T`, and if they are
+/// never set they are left as `None`. All other fields are required, and `build` returns an
+/// `Err` naming the first missing field if any of them were not set.
+///
+/// The referenced struct _must_ have named fields and the
+/// [`#[export_tokens]`][`macro@export_tokens`] attribute attached to it, and its fields must be
+/// visible from wherever this macro is called.
+///
+/// ## Example
+///
+/// ```ignore
+/// #[export_tokens]
+/// struct Config {
+///     name: String,
+///     retries: Option<u32>,
+/// }
+///
+/// generate_builder!(Config);
+///
+/// let config = ConfigBuilder::new().name("a".to_string()).build().unwrap();
+/// assert_eq!(config.retries, None);
+/// assert!(ConfigBuilder::new().retries(3).build().is_err());
+/// ```
+#[proc_macro]
+pub fn generate_builder(tokens: TokenStream) -> TokenStream {
+    match generate_builder_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
 /// Allows you to import the tokens of an external item marked with
 /// [`#[export_tokens]`][`macro@export_tokens`] whose path is already known at compile-time
 /// without having to do any additional parsing.
@@ -176,6 +526,50 @@ pub fn forward_tokens(tokens: TokenStream) -> TokenStream {
 /// [`#[export_tokens]`][`macro@export_tokens`] attached to it. The imported tokens wil be of
 /// type `TokenStream2`.
 ///
+/// To accumulate several imports into a single, existing `TokenStream2` variable, use
+/// `extend` instead of `let`. The imported tokens are then appended to the variable via
+/// `.extend(..)`, so it must be declared as mutable:
+///
+/// ```ignore
+/// let mut tokens = TokenStream2::new();
+/// import_tokens!(extend tokens = external_crate::SomeItem);
+/// import_tokens!(extend tokens = external_crate::OtherItem);
+/// ```
+///
+/// If the item was exported with a "schema version" (e.g. `#[export_tokens(version = 2)]`),
+/// importers can require a minimum version via
+/// `import_tokens!(let tokens = SomeItem, min_version = 2)`, which results in a compile error
+/// if the item was exported with a lower version. Items exported without a version have
+/// version `0`.
+///
+/// Items exported via an alias with a custom prefix (see
+/// [`export_tokens_alias!`](`macro@export_tokens_alias`)) can be imported by specifying the
+/// same prefix, e.g. `import_tokens!(let tokens = SomeItem, prefix = "__my_lib_tt_")`. Items
+/// exported with `#[export_tokens(local)]` must be imported with the `local` option, e.g.
+/// `import_tokens!(let tokens = SomeItem, local)`.
+///
+/// The imported item can be renamed before its tokens are bound, e.g. to avoid clashing with
+/// a local item of the same name, via `import_tokens!(let tokens = SomeItem as OtherName)`.
+/// Only the name of the item itself is replaced, and items without a name (such as `impl`
+/// blocks) can't be renamed, resulting in a compile error.
+///
+/// Attributes can be removed from the imported tokens via the `strip` option, e.g.
+/// `import_tokens!(let tokens = SomeItem, strip = [serde, doc])`, which is useful when the
+/// exporting crate can't be modified. Attributes whose path matches one of the listed paths
+/// exactly are removed wherever they occur, including on fields and variants. Doc comments
+/// are `doc` attributes, so they can be removed via `doc`.
+///
+/// Generic arguments on the last segment of the path are ignored, so `SomeItem<T>` imports
+/// the tokens of `SomeItem`. Generic arguments on any other segment (e.g. `SomeItem<T>::Foo`)
+/// result in a compile error.
+///
+/// The first segment of the path is always resolved from the point of view of the importing
+/// crate, so if the exporting crate was renamed in your `Cargo.toml` (e.g. `producer = {
+/// package = "real_producer", .. }`), simply use the new name, e.g.
+/// `import_tokens!(let tokens = producer::SomeItem)`. The same applies to the paths passed to
+/// [`macro@import_tokens_proc`] and [`macro@import_tokens_attr`] based macros, as well as to
+/// [`forward_tokens!`](`macro@forward_tokens`).
+///
 /// Unfortunately this macro isn't very useful, because it is quite rare that you already know
 /// the path of the item you want to import _inside_ your proc macro. Note that having the
 /// _tokens_ for the path you want isn't the same as having those tokens already expanded in
@@ -195,6 +589,427 @@ pub fn import_tokens(tokens: TokenStream) -> TokenStream {
     }
 }
 
+/// Expands to the value expression of the specified `const`, which must have been exported
+/// with [`#[export_tokens(value)]`](`macro@export_tokens`), so that it can be used directly in
+/// expression position.
+///
+/// Note that the expression is expanded as-is at the call site, so any paths it contains must
+/// resolve there as well.
+///
+/// ## Example
+///
+/// ```ignore
+/// #[export_tokens(value)]
+/// const FIVE: u32 = 5;
+///
+/// let n: u32 = import_tokens_value!(FIVE) * 2;
+/// ```
+#[proc_macro]
+pub fn import_tokens_value(tokens: TokenStream) -> TokenStream {
+    match import_tokens_value_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Expands to the variant of the `macro_magic::ExportedKind` enum matching the kind of the
+/// specified exported item, such as `ExportedKind::Fn`, `ExportedKind::Struct` or
+/// `ExportedKind::ForeignMod` (see [`macro_magic_core::item_kind_variant`]), without
+/// importing its tokens. The expansion is a plain path, so it can be used in `const`s and
+/// `match` patterns alike.
+///
+/// Exports that aren't full items, such as those created by the `body`, `sig` and `items`
+/// options of [`#[export_tokens]`](`macro@export_tokens`), have no kind, so this results in a
+/// compile error for them.
+///
+/// ## Example
+///
+/// ```ignore
+/// #[export_tokens]
+/// fn add_stuff(a: u32, b: u32) -> u32 {
+///     a + b
+/// }
+///
+/// assert_eq!(import_tokens_kind!(add_stuff), ExportedKind::Fn);
+/// assert_eq!(import_tokens_kind!(add_stuff).as_str(), "fn");
+/// ```
+#[proc_macro]
+pub fn import_tokens_kind(tokens: TokenStream) -> TokenStream {
+    match import_tokens_kind_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Expands to just the identifier of the specified exported item, rather than its tokens.
+///
+/// The identifier is the original, un-flattened ident of the item itself, even if it was
+/// exported under a different name. For items that have no inherent ident (such as `impl`
+/// blocks) the explicit name passed to [`#[export_tokens]`](`macro@export_tokens`) is used
+/// instead. This is handy for generating `use` statements, dispatch tables and the like.
+///
+/// Unlike [`import_tokens!`](`macro@import_tokens`), this expands directly to the identifier,
+/// so it can be used anywhere an identifier can, including expression and type positions.
+///
+/// ## Example
+///
+/// ```ignore
+/// #[export_tokens]
+/// fn compute_area(width: u32, height: u32) -> u32 {
+///     width * height
+/// }
+///
+/// assert_eq!(import_tokens_ident!(compute_area)(2, 3), 6);
+/// ```
+#[proc_macro]
+pub fn import_tokens_ident(tokens: TokenStream) -> TokenStream {
+    match import_tokens_ident_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Emits the specified exported item wrapped in a module of the specified name, so that its
+/// symbols can't clash with those already defined where it is imported.
+///
+/// The module can be given a visibility, e.g. `import_tokens_mod!(pub my_mod, SomeItem)`. It
+/// glob-imports its parent module, so the item can refer to the same names as if it had been
+/// emitted in place. If the item has no explicit visibility, it is made `pub(super)`, so that
+/// it remains accessible from the parent module via the module path, e.g. `my_mod::SomeItem`.
+/// Visibilities within the item (such as those of struct fields) are left unchanged.
+///
+/// ## Example
+///
+/// ```ignore
+/// #[export_tokens]
+/// fn add_stuff(a: u32, b: u32) -> u32 {
+///     a + b
+/// }
+///
+/// import_tokens_mod!(imported, add_stuff);
+///
+/// assert_eq!(imported::add_stuff(1, 2), 3);
+/// ```
+#[proc_macro]
+pub fn import_tokens_mod(tokens: TokenStream) -> TokenStream {
+    match import_tokens_mod_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Asserts at compile time that two exported items have identical tokens, emitting a
+/// `compile_error!` describing the first difference if they don't. This is useful for
+/// contract-testing across crates, e.g. to ensure that a generated item matches a hand-written
+/// one.
+///
+/// The tokens are compared one by one, ignoring their spans and the whitespace between them
+/// (see `macro_magic::mm_core::tokens_first_difference`), so e.g. `a+b` and `a + b` are
+/// considered equal, while the names, attributes and visibilities of the items must match.
+///
+/// This macro takes the paths of two exported items and expands to nothing if their tokens
+/// are equal. To compare two `TokenStream2`s at runtime instead, such as in the tests of a
+/// proc macro, use `macro_magic::mm_core::assert_token_streams_eq!`.
+///
+/// ## Example
+///
+/// ```ignore
+/// mod hand_written {
+///     #[export_tokens(HandWrittenAddStuff)]
+///     fn add_stuff(a: u32, b: u32) -> u32 {
+///         a + b
+///     }
+/// }
+///
+/// assert_tokens_eq!(other_crate::add_stuff, HandWrittenAddStuff);
+/// ```
+#[proc_macro]
+pub fn assert_tokens_eq(tokens: TokenStream) -> TokenStream {
+    match assert_tokens_eq_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Attaches a `#[doc = ..]` attribute containing the tokens of the specified exported item to
+/// the item that follows the `=>`, which is useful for documenting generated code with the
+/// source it was generated from.
+///
+/// The stringified tokens are wrapped in a `text` code block, so they are rendered as-is
+/// rather than interpreted as markdown. The resulting doc string is appended to the item's
+/// own doc comments, if it has any.
+///
+/// ## Example
+///
+/// ```ignore
+/// #[export_tokens]
+/// fn add_stuff(a: u32, b: u32) -> u32 {
+///     a + b
+/// }
+///
+/// import_tokens_doc!(add_stuff =>
+///     /// Generated from the following function:
+///     pub struct AddStuff;
+/// );
+/// ```
+#[proc_macro]
+pub fn import_tokens_doc(tokens: TokenStream) -> TokenStream {
+    match import_tokens_doc_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Generates a function returning the tokens (as a `TokenStream2`) of the specified exported
+/// item, so that the stringified tokens are expanded only once rather than at every import
+/// site.
+///
+/// The function is named as specified and defined wherever the macro is invoked, with the
+/// (optional) visibility given, e.g. `import_tokens_fn!(pub fn my_tokens = SomeItem)`
+/// defines `pub fn my_tokens() -> TokenStream2`. The tokens are parsed anew on each call,
+/// since a `TokenStream2` can't be kept across proc macro invocations.
+///
+/// ## Example
+///
+/// ```ignore
+/// import_tokens_fn!(fn widget_tokens = external_crate::Widget);
+///
+/// #[proc_macro]
+/// pub fn my_macro(tokens: TokenStream) -> TokenStream {
+///     let widget = widget_tokens();
+///     // ...
+/// }
+/// ```
+#[proc_macro]
+pub fn import_tokens_fn(tokens: TokenStream) -> TokenStream {
+    match import_tokens_fn_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Works just like [`import_tokens!`](`macro@import_tokens`), except it takes a bracketed list
+/// of paths and binds the concatenation of the tokens of all of the listed items, in the order
+/// they are listed, to the specified variable.
+///
+/// ## Example
+///
+/// ```ignore
+/// #[export_tokens]
+/// struct Point {
+///     x: i32,
+/// }
+///
+/// #[export_tokens(point_impl)]
+/// impl Point {
+///     fn x(&self) -> i32 {
+///         self.x
+///     }
+/// }
+///
+/// // `tokens` contains the struct followed by the impl
+/// import_tokens_merge!(let tokens = [Point, point_impl]);
+/// ```
+#[proc_macro]
+pub fn import_tokens_merge(tokens: TokenStream) -> TokenStream {
+    match import_tokens_merge_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Works just like [`import_tokens!`](`macro@import_tokens`), except it takes only the path of
+/// the item to import and expands to an expression evaluating to its tokens (as a
+/// `TokenStream2`), rather than to a `let` binding.
+///
+/// This avoids having to pick a variable name that might collide with surrounding bindings,
+/// and allows the imported tokens to be used directly in expression position.
+///
+/// ## Example
+///
+/// ```ignore
+/// let tokens = import_tokens_fresh!(external_crate::SomeItem);
+/// assert!(import_tokens_fresh!(SomeOtherItem).to_string().contains("struct"));
+/// ```
+#[proc_macro]
+pub fn import_tokens_fresh(tokens: TokenStream) -> TokenStream {
+    match import_tokens_fresh_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Expands to an expression evaluating to the tokens (as a `TokenStream2`) of a single named
+/// field of an exported struct, consisting of the name and type of the field, e.g.
+/// `field_name: u32`.
+///
+/// The argument is the path of the struct (as it would be passed to
+/// [`import_tokens_fresh!`](`macro@import_tokens_fresh`)) followed by the name of the field.
+/// If the exported item isn't a struct or doesn't have a field with that name, a compile error
+/// is emitted.
+///
+/// ## Example
+///
+/// ```ignore
+/// #[export_tokens]
+/// struct Config {
+///     retries: Option<u32>,
+/// }
+///
+/// let field = import_tokens_field!(Config::retries);
+/// let field = syn::Field::parse_named.parse2(field).unwrap();
+/// ```
+#[proc_macro]
+pub fn import_tokens_field(tokens: TokenStream) -> TokenStream {
+    match import_tokens_field_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Works just like [`import_tokens_fresh!`](`macro@import_tokens_fresh`), except the argument
+/// is the path of the `macro_rules!` generated by [`#[export_tokens]`](`macro@export_tokens`)
+/// itself (e.g. `my_crate::__export_tokens_tt_my_item`), which is invoked directly instead of
+/// being derived from the name of the item.
+///
+/// This can be useful when the name of the generated macro is already known, such as when
+/// several items flatten to the same name and one of them was given an explicit export name.
+///
+/// ## Example
+///
+/// ```ignore
+/// #[export_tokens]
+/// struct Widget {}
+///
+/// let tokens = import_tokens_raw!(__export_tokens_tt_widget);
+/// ```
+#[proc_macro]
+pub fn import_tokens_raw(tokens: TokenStream) -> TokenStream {
+    match import_tokens_raw_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Expands to an expression evaluating to the generics (as a `TokenStream2`) of the specified
+/// item, which must have been exported with
+/// [`#[export_tokens(with_generics)]`](`macro@export_tokens`).
+///
+/// The tokens consist of the generic parameters followed by the where clause, if any. Note
+/// that [`syn::Generics`]'s own `Parse` implementation only parses the parameters, so use
+/// [`macro_magic_core::parse_generics`] to parse generics that may have a where clause.
+///
+/// Lifetime, type and const parameters are all preserved as written, including any default
+/// values (e.g. `<const N: usize = 8>`). Defaults aren't allowed everywhere generics can be
+/// re-applied, so use [`syn::Generics::split_for_impl`] to get the generics of an `impl` block,
+/// which drops them.
+///
+/// ## Example
+///
+/// ```ignore
+/// #[export_tokens(with_generics)]
+/// struct Wrapper<T: Clone> {
+///     inner: T,
+/// }
+///
+/// let generics = syn::parse2::<syn::Generics>(import_tokens_generics!(Wrapper)).unwrap();
+/// ```
+#[proc_macro]
+pub fn import_tokens_generics(tokens: TokenStream) -> TokenStream {
+    match import_tokens_generics_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Expands to a `&'static str` literal containing the doc comments (i.e. the values of the
+/// `#[doc = ".."]` attributes) of the specified exported item, joined by newlines. This is
+/// useful for generating documentation for wrappers of exported items.
+///
+/// Like [`import_tokens_str!`](`macro@import_tokens_str`), the result can be used in `const`
+/// and `static` initializers, as well as in `#[doc = ..]` attributes. Each line is kept as
+/// written, including the leading space of `/// Some docs`, which rustdoc strips as usual.
+/// Undocumented items result in an empty string, while doc attributes whose value isn't a
+/// string literal (e.g. `#[doc = include_str!("..")]`) result in a compile error.
+///
+/// ## Example
+///
+/// ```ignore
+/// /// Adds two numbers.
+/// #[export_tokens]
+/// fn add_stuff(a: u32, b: u32) -> u32 {
+///     a + b
+/// }
+///
+/// #[doc = import_tokens_docs!(add_stuff)]
+/// pub fn add_stuff_wrapper(a: u32, b: u32) -> u32 {
+///     add_stuff(a, b)
+/// }
+/// ```
+#[proc_macro]
+pub fn import_tokens_docs(tokens: TokenStream) -> TokenStream {
+    match import_tokens_docs_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Expands to a `&'static str` literal containing the tokens of the specified exported item.
+///
+/// Unlike [`import_tokens!`](`macro@import_tokens`), which binds a parsed `TokenStream2` to a
+/// variable at runtime, this expands directly to a string literal, so it can be used in
+/// `const` and `static` initializers without needing to parse anything at runtime.
+///
+/// ## Example
+///
+/// ```ignore
+/// #[export_tokens]
+/// fn add_stuff(a: u32, b: u32) -> u32 {
+///     a + b
+/// }
+///
+/// const ADD_STUFF: &str = import_tokens_str!(add_stuff);
+/// ```
+///
+/// Items exported with [`#[export_tokens(verbatim_source)]`](`macro@export_tokens`) can have
+/// their source text imported as it was written, including formatting and comments, via
+/// `import_tokens_str!(add_stuff, verbatim_source)`.
+#[proc_macro]
+pub fn import_tokens_str(tokens: TokenStream) -> TokenStream {
+    match import_tokens_str_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Works just like [`import_tokens!`](`macro@import_tokens`), except the path of the item to
+/// import is read from the specified environment variable at macro-expansion time rather than
+/// being written out directly.
+///
+/// This allows consumers to select which exported item gets imported at build time, for
+/// example via the `[env]` section of `.cargo/config.toml` (the same mechanism used to set
+/// [`MACRO_MAGIC_ROOT`]):
+///
+/// ```toml
+/// [env]
+/// MY_IMPL_PATH = "external_crate::SomeItem"
+/// ```
+///
+/// ```ignore
+/// import_tokens_env!(let tokens = MY_IMPL_PATH);
+/// ```
+///
+/// A compile error is emitted if the environment variable is not set, or if its value does
+/// not parse as a [`syn::Path`]. Note that changing the value of the environment variable may
+/// not on its own cause crates using this macro to be recompiled.
+#[proc_macro]
+pub fn import_tokens_env(tokens: TokenStream) -> TokenStream {
+    match import_tokens_env_internal(tokens, |key| std::env::var(key).ok()) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
 /// An attribute macro that can be attached to a proc macro function definition that will cause
 /// it to receive the tokens of the external item referred to by its argument as input to your
 /// proc macro.
@@ -284,10 +1099,41 @@ pub fn import_tokens_proc(attr: TokenStream, tokens: TokenStream) -> TokenStream
 /// as the path for the foreign item before its tokens were imported. You can access this
 /// directly simply by referring to `__source_path`. This should parse to a [`syn::Path`].
 ///
+/// Several foreign paths can also be passed to the resulting attribute, separated by commas,
+/// e.g. `#[my_attr(path::to::a, path::to::b)]`. The tokens for each foreign item are imported
+/// one after another, and are then made available all at once via the magic variable
+/// `__foreign_items: Vec<syn::Item>`, in the order the paths were given. In this case the
+/// `attr` argument of your proc macro still contains the tokens of the _first_ foreign item,
+/// and `__source_path` contains the full comma-separated list of paths. Custom parsers can
+/// opt into this behavior by overriding [`ForeignPath::foreign_paths`].
+///
+/// All of this information is also available in typed form via the magic variable
+/// `__context: ImportTokensAttrContext`, which bundles the attached item, the foreign path(s),
+/// the custom parsed tokens, the raw attr tokens, and the imported foreign items.
+///
+/// Since these variables are bound alongside the parameters of your proc macro function, the
+/// parameters can't be named after any of them, nor after the other identifiers used by the
+/// generated code (see [`IMPORT_TOKENS_ATTR_RESERVED_IDENTS`]). Doing so results in
+/// a compile error asking you to rename the parameter.
+///
 /// Note that you can provide a module path as an optional argument to this attribute macro and
 /// that path will be used as the override for [`MACRO_MAGIC_ROOT`] within the context of code
 /// generated by this attribute.
 ///
+/// When debugging, `#[import_tokens_attr(dry_run)]` (or `#[import_tokens_attr(my::path,
+/// dry_run)]`) makes the resulting attribute still parse its arguments (including any custom
+/// parsing), but then emit the attached item unchanged instead of importing the foreign item
+/// and running your proc macro body. This helps narrow down whether an issue lies in the
+/// parsing of the attribute or in the body of your macro. Since nothing is forwarded, such
+/// attributes can be imported with a plain `use` instead of [`#[use_attr]`](`macro@use_attr`).
+///
+/// To combine the resulting attribute with another attribute macro, pass `then = <path>`, e.g.
+/// `#[import_tokens_attr(then = my_crate::other_attr)]`. Once your proc macro body has run,
+/// its output is passed through `#[my_crate::other_attr]` (with no arguments), which thus
+/// receives the first item of that output, followed by any remaining items verbatim. The
+/// path is resolved wherever the resulting attribute is used, so it should be absolute or
+/// otherwise in scope there. `then` has no effect in combination with `dry_run`.
+///
 ///
 /// ## Optional Feature: `#[with_custom_parsing(..)]`
 ///
@@ -300,9 +1146,15 @@ pub fn import_tokens_proc(attr: TokenStream, tokens: TokenStream) -> TokenStream
 /// attribute definition that you attached `#[import_tokens_attr]` to.
 ///
 /// This optional attribute takes one argument, which should be the path to a struct that
-/// implements [`syn::parse::Parse`], [`quote::ToTokens`], and [`ForeignPath`]. To access the
-/// tokens for your custom parsed input, you can use the magic variable `__custom_tokens:
-/// TokenStream` anywhere in your attribute proc macro.
+/// implements [`syn::parse::Parse`], [`quote::ToTokens`], and [`ForeignPath`]. If any of these
+/// impls is missing, the resulting compile error points at the struct path passed to
+/// `#[with_custom_parsing(..)]`. To access the tokens for your custom parsed input, you can use
+/// the magic variable `__custom_tokens: TokenStream` anywhere in your attribute proc macro.
+///
+/// Note that `__custom_tokens` is produced by your struct's [`quote::ToTokens`] implementation,
+/// so if that implementation doesn't exactly round-trip the attribute, `__custom_tokens` will
+/// differ from what was actually written. The magic variable `__raw_attr: TokenStream` always
+/// contains the original, unparsed tokens of the attribute, regardless of any custom parsing.
 ///
 /// Here is a full example:
 ///
@@ -347,6 +1199,23 @@ pub fn import_tokens_proc(attr: TokenStream, tokens: TokenStream) -> TokenStream
 /// This is just an example, you could implement the parsing any way you want, maybe even using
 /// something that isn't initially a [`syn::Path`] but is transformed into one. The
 /// possibilities are endless.
+///
+/// If your attribute can be attached to different kinds of items that call for different
+/// argument syntax, you can instead register one parser per item kind, optionally followed by
+/// a `_` fallback. The parser is then chosen based on the kind of the attached item, and
+/// attaching the attribute to a kind of item without a parser results in a compile error:
+///
+/// ```ignore
+/// #[import_tokens_attr]
+/// #[with_custom_parsing(struct => MyStructParsing, fn => MyFnParsing, _ => MyOtherParsing)]
+/// #[proc_macro_attribute]
+/// pub fn my_attribute(attr: TokenStream, tokens: TokenStream) -> TokenStream {
+///     // ...
+/// }
+/// ```
+///
+/// The supported kinds are `const`, `enum`, `fn`, `impl`, `macro`, `mod`, `static`, `struct`,
+/// `trait`, `type`, `union` and `use`.
 #[proc_macro_attribute]
 pub fn import_tokens_attr(attr: TokenStream, tokens: TokenStream) -> TokenStream {
     match import_tokens_attr_internal(attr, tokens) {
@@ -355,8 +1224,37 @@ pub fn import_tokens_attr(attr: TokenStream, tokens: TokenStream) -> TokenStream
     }
 }
 
+/// Derives [`ForeignPath`] for a struct that stores the foreign path in one of its fields, for
+/// use with [`#[with_custom_parsing(..)]`](`macro@with_custom_parsing`).
+///
+/// The field marked with `#[foreign_path]` is used, falling back to the field named
+/// `foreign_path` if no field is marked.
+///
+/// ## Example
+///
+/// ```ignore
+/// #[derive(Parse, ForeignPath)]
+/// struct MyCustomParsing {
+///     #[foreign_path]
+///     source: syn::Path,
+///     _comma: syn::token::Comma,
+///     custom_path: syn::Path,
+/// }
+/// ```
+#[proc_macro_derive(ForeignPath, attributes(foreign_path))]
+pub fn derive_foreign_path(tokens: TokenStream) -> TokenStream {
+    match derive_foreign_path_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
 /// To be used in tandem with [`#[import_tokens_attr]`](`macro@import_tokens_attr`)
 ///
+/// Takes either a single parser, or several `kind => Parser` pairs to pick a parser based on the
+/// kind of item the attribute is attached to (see
+/// [`#[import_tokens_attr]`](`macro@import_tokens_attr`) for details).
+///
 /// Example:
 /// ```ignore
 /// #[import_tokens_attr]
@@ -439,6 +1337,27 @@ pub fn use_proc(attr: TokenStream, tokens: TokenStream) -> TokenStream {
     }
 }
 
+/// A helper macro used by [`macro@import_tokens_fresh`], [`macro@import_tokens_raw`] and
+/// [`macro@import_tokens_generics`]. Hidden from docs.
+#[doc(hidden)]
+#[proc_macro]
+pub fn import_tokens_fresh_inner(tokens: TokenStream) -> TokenStream {
+    match import_tokens_fresh_inner_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// A helper macro used by [`macro@import_tokens_field`]. Hidden from docs.
+#[doc(hidden)]
+#[proc_macro]
+pub fn import_tokens_field_inner(tokens: TokenStream) -> TokenStream {
+    match import_tokens_field_inner_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
 /// A helper macro used by [`macro@import_tokens`]. Hidden from docs.
 #[doc(hidden)]
 #[proc_macro]
@@ -449,6 +1368,106 @@ pub fn import_tokens_inner(tokens: TokenStream) -> TokenStream {
     }
 }
 
+/// A helper macro used by [`macro@import_tokens`]. Hidden from docs.
+#[doc(hidden)]
+#[proc_macro]
+pub fn import_tokens_extend_inner(tokens: TokenStream) -> TokenStream {
+    match import_tokens_extend_inner_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// A helper macro used by [`macro@import_tokens`]. Hidden from docs.
+#[doc(hidden)]
+#[proc_macro]
+pub fn import_tokens_version_check(tokens: TokenStream) -> TokenStream {
+    match import_tokens_version_check_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// A helper macro used by [`macro@import_tokens_mod`]. Hidden from docs.
+#[doc(hidden)]
+#[proc_macro]
+pub fn import_tokens_mod_inner(tokens: TokenStream) -> TokenStream {
+    match import_tokens_mod_inner_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// A helper macro used by [`macro@assert_tokens_eq`]. Hidden from docs.
+#[doc(hidden)]
+#[proc_macro]
+pub fn assert_tokens_eq_inner(tokens: TokenStream) -> TokenStream {
+    match assert_tokens_eq_inner_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// A helper macro used by [`macro@assert_tokens_eq`]. Hidden from docs.
+#[doc(hidden)]
+#[proc_macro]
+pub fn assert_tokens_eq_compare(tokens: TokenStream) -> TokenStream {
+    match assert_tokens_eq_compare_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// A helper macro used by [`macro@import_tokens_doc`]. Hidden from docs.
+#[doc(hidden)]
+#[proc_macro]
+pub fn import_tokens_doc_inner(tokens: TokenStream) -> TokenStream {
+    match import_tokens_doc_inner_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// A helper macro used by [`macro@import_tokens_docs`]. Hidden from docs.
+#[doc(hidden)]
+#[proc_macro]
+pub fn import_tokens_docs_inner(tokens: TokenStream) -> TokenStream {
+    match import_tokens_docs_inner_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// A helper macro used by [`macro@import_tokens_str`]. Hidden from docs.
+#[doc(hidden)]
+#[proc_macro]
+pub fn import_tokens_str_inner(tokens: TokenStream) -> TokenStream {
+    match import_tokens_str_inner_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// A helper macro used by [`macro@forward_tokens`]. Hidden from docs.
+#[doc(hidden)]
+#[proc_macro]
+pub fn forward_tokens_extras_inner(tokens: TokenStream) -> TokenStream {
+    match forward_tokens_extras_inner_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// A helper macro used by [`macro@forward_tokens_dynamic`]. Hidden from docs.
+#[doc(hidden)]
+#[proc_macro]
+pub fn forward_tokens_dynamic_inner(tokens: TokenStream) -> TokenStream {
+    match forward_tokens_dynamic_inner_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
 /// A helper macro used by [`macro@forward_tokens`]. Hidden from docs.
 #[doc(hidden)]
 #[proc_macro]
@@ -458,3 +1477,182 @@ pub fn forward_tokens_inner(tokens: TokenStream) -> TokenStream {
         Err(err) => err.to_compile_error().into(),
     }
 }
+
+/// A helper macro used by [`macro@forward_tokens_spanned`]. Hidden from docs.
+#[doc(hidden)]
+#[proc_macro]
+pub fn forward_tokens_spanned_inner(tokens: TokenStream) -> TokenStream {
+    match forward_tokens_spanned_inner_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// A helper macro used by [`macro@generate_builder`]. Hidden from docs.
+#[doc(hidden)]
+#[proc_macro]
+pub fn generate_builder_inner(tokens: TokenStream) -> TokenStream {
+    match generate_builder_inner_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// A helper macro used by [`macro@forward_tokens`]. Hidden from docs.
+#[doc(hidden)]
+#[proc_macro]
+pub fn forward_tokens_transform_inner(tokens: TokenStream) -> TokenStream {
+    match forward_tokens_transform_inner_internal(tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// A helper attribute used by [`macro@forward_tokens`]. Hidden from docs.
+#[doc(hidden)]
+#[proc_macro_attribute]
+pub fn forward_tokens_transformed(attr: TokenStream, tokens: TokenStream) -> TokenStream {
+    match forward_tokens_transformed_internal(attr, tokens) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Calls [`export_tokens_internal`], first capturing the source text of the item via
+/// [`item_source_text`] if the `verbatim_source` option is specified.
+fn export_tokens_with_source(
+    attr: TokenStream,
+    tokens: TokenStream,
+    emit: bool,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let args = syn::parse::<ExportTokensArgs>(attr.clone())?;
+    if !args.verbatim_source {
+        return export_tokens_internal(attr, tokens, emit);
+    }
+    let mut options = args.into_options(emit);
+    if let Some(source_text) = item_source_text(&tokens) {
+        options = options.source_text(source_text);
+    }
+    export_tokens_with_options(tokens, options)
+}
+
+/// Returns the source text of the specified item tokens exactly as written, including
+/// formatting and comments.
+///
+/// On nightly this is obtained directly from the compiler (see [`verbatim_source_text`]). On
+/// stable Rust 1.88+ the text is instead cut out of the source file using the line and column
+/// of the first and last tokens. Returns [`None`] if neither is possible, such as on older
+/// compilers or for items that were generated by other macros.
+fn item_source_text(tokens: &TokenStream) -> Option<std::string::String> {
+    let tokens = proc_macro2::TokenStream::from(tokens.clone());
+    if let Some(source_text) = verbatim_source_text(&tokens) {
+        return Some(source_text);
+    }
+    let mut tokens = tokens.into_iter();
+    let first = tokens.next()?.span();
+    let last = tokens.last().map_or(first, |token| token.span());
+    let file = first.local_file()?;
+    if last.local_file()? != file {
+        return None;
+    }
+    let source = std::fs::read_to_string(file).ok()?;
+    let start = source_offset(&source, first.start())?;
+    let end = source_offset(&source, last.end())?;
+    source.get(start..end).map(std::string::ToString::to_string)
+}
+
+/// Converts a [`proc_macro2::LineColumn`] (with a 1-indexed line and a 0-indexed column in
+/// characters) into a byte offset into `source`. Returns [`None`] for unknown (zero) lines and
+/// positions past the end of `source`.
+fn source_offset(source: &str, position: proc_macro2::LineColumn) -> Option<usize> {
+    let line_start = match position.line {
+        0 => return None,
+        1 => 0,
+        line => source
+            .match_indices('\n')
+            .nth(line - 2)
+            .map(|(index, _)| index + 1)?,
+    };
+    let line = &source[line_start..];
+    let line = &line[..line.find('\n').unwrap_or(line.len())];
+    match line.char_indices().nth(position.column) {
+        Some((index, _)) => Some(line_start + index),
+        None if line.chars().count() == position.column => Some(line_start + line.len()),
+        None => None,
+    }
+}
+
+/// Writes the JSON file requested via the `dump` option of
+/// [`#[export_tokens]`](`macro@export_tokens`), if specified.
+#[cfg(feature = "dump")]
+fn dump_export_tokens(attr: &TokenStream, tokens: &TokenStream) -> syn::Result<()> {
+    use quote::ToTokens;
+    use std::{env, fs, path::PathBuf, string::ToString};
+
+    let args = syn::parse::<ExportTokensArgs>(attr.clone())?;
+    let Some(dir) = args.dump else {
+        return Ok(());
+    };
+    let item = syn::parse::<syn::Item>(tokens.clone())?;
+    // missing names are reported by `export_tokens_internal`
+    let Some(ident) = args.name.or_else(|| item_ident(&item)) else {
+        return Ok(());
+    };
+    let ident = flatten_ident(&ident).to_string();
+    let mut path = PathBuf::from(dir.value());
+    if path.is_relative() {
+        if let Ok(manifest_dir) = env::var("CARGO_MANIFEST_DIR") {
+            path = PathBuf::from(manifest_dir).join(path);
+        }
+    }
+    let json = std::format!(
+        "{{\"ident\": {}, \"kind\": {}, \"tokens\": {}}}\n",
+        json_string(&ident),
+        json_string(item_kind(&item)),
+        json_string(&item.to_token_stream().to_string()),
+    );
+    fs::create_dir_all(&path)
+        .and_then(|_| fs::write(path.join(std::format!("{}.json", ident)), json))
+        .map_err(|err| {
+            syn::Error::new(
+                dir.span(),
+                std::format!("failed to write `dump` file: {}", err),
+            )
+        })
+}
+
+/// Errors if the `dump` option is used without the `dump` feature being enabled.
+#[cfg(not(feature = "dump"))]
+fn dump_export_tokens(attr: &TokenStream, _tokens: &TokenStream) -> syn::Result<()> {
+    match syn::parse::<ExportTokensArgs>(attr.clone())?.dump {
+        Some(dir) => Err(syn::Error::new(
+            dir.span(),
+            "the `dump` option requires the `dump` feature of `macro_magic` to be enabled",
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Encodes `value` as a JSON string literal.
+#[cfg(feature = "dump")]
+fn json_string(value: &str) -> std::string::String {
+    use core::fmt::Write;
+
+    let mut json = std::string::String::with_capacity(value.len() + 2);
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
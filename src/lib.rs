@@ -47,6 +47,32 @@
 //! useful for debugging. This feature is not enabled by default since it relies on some things
 //! that can be problematic in `no_std` environments.
 //!
+//! ### rustfmt
+//!
+//! The `rustfmt` feature implies `pretty_print` and adds a `Formatter::Rustfmt` backend to the
+//! `pretty_print_with` and `pretty_format_with` functions in [`mm_core`]. This backend pipes
+//! the tokens through the `rustfmt` executable, so the output matches the formatting produced
+//! by your project's `rustfmt.toml`, falling back to `prettyplease` if `rustfmt` isn't
+//! available. This feature requires `std`.
+//!
+//! ### compress
+//!
+//! The `compress` feature, when enabled, causes [`#[export_tokens]`](`export_tokens`) to store
+//! the tokens of exported items as a compressed string literal rather than as raw tokens, which
+//! can significantly reduce the size of the generated code for crates exporting very large
+//! items. The compression scheme is a small LZ77-style scheme implemented directly in
+//! `macro_magic_core`, so no additional dependencies are pulled in. The import side always
+//! knows how to decompress, so this feature only needs to be enabled in crates that export
+//! tokens. Note that decompressed tokens lose their original spans.
+//!
+//! ### dump
+//!
+//! The `dump` feature enables the `dump` option of [`#[export_tokens]`](`export_tokens`), e.g.
+//! `#[export_tokens(dump = "target/tokens")]`, which additionally writes the tokens of the item
+//! to a JSON file (with `ident`, `kind` and `tokens` fields) in the specified directory, so
+//! they can be consumed by non-Rust tooling such as codegen scripts and docs generators. This
+//! feature requires `std`, which is only used by the proc macro crate itself.
+//!
 //! ## Limitations
 //!
 //! One thing that `macro_magic` _doesn't_ provide is the ability to build up state information
@@ -72,18 +98,77 @@ pub mod mm_core {
     pub use macro_magic_core::*;
 
     #[cfg(feature = "pretty_print")]
-    pub use macro_magic_core::pretty_print;
+    pub use macro_magic_core::{pretty_format_with, pretty_print, pretty_print_with, Formatter};
 }
 
 pub use macro_magic_macros::{
-    export_tokens, export_tokens_alias, export_tokens_no_emit, forward_tokens, use_attr, use_proc,
+    assert_tokens_eq, export_tokens, export_tokens_alias, export_tokens_file,
+    export_tokens_no_emit, forward_tokens, forward_tokens_dynamic, forward_tokens_spanned,
+    generate_builder, import_tokens_doc, import_tokens_docs, import_tokens_ident,
+    import_tokens_kind, import_tokens_mod, import_tokens_str, import_tokens_value, use_attr,
+    use_proc,
 };
 
 #[cfg(feature = "proc_support")]
 pub use macro_magic_macros::{
-    import_tokens, import_tokens_attr, import_tokens_proc, with_custom_parsing,
+    import_tokens, import_tokens_attr, import_tokens_env, import_tokens_field, import_tokens_fn,
+    import_tokens_fresh, import_tokens_generics, import_tokens_merge, import_tokens_proc,
+    import_tokens_raw, with_custom_parsing, ForeignPath,
 };
 
+/// The kind of an item exported via [`#[export_tokens]`](`export_tokens`), as returned by
+/// [`import_tokens_kind!`](`import_tokens_kind`).
+///
+/// Each variant corresponds to a kind of item, e.g. [`ExportedKind::ForeignMod`] to an
+/// `extern "C" { .. }` block. Use [`ExportedKind::as_str`] to get the short, human-readable
+/// name of the kind, such as `"foreign mod"`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ExportedKind {
+    Const,
+    Enum,
+    ExternCrate,
+    Fn,
+    ForeignMod,
+    Impl,
+    Macro,
+    Mod,
+    Static,
+    Struct,
+    Trait,
+    TraitAlias,
+    Type,
+    Union,
+    Use,
+    /// Items that [`syn`](https://docs.rs/syn) doesn't parse into any of the other kinds
+    Verbatim,
+}
+
+impl ExportedKind {
+    /// Returns the short, human-readable name of this kind, such as `"struct"`, `"fn"` or
+    /// `"foreign mod"`.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            ExportedKind::Const => "const",
+            ExportedKind::Enum => "enum",
+            ExportedKind::ExternCrate => "extern crate",
+            ExportedKind::Fn => "fn",
+            ExportedKind::ForeignMod => "foreign mod",
+            ExportedKind::Impl => "impl",
+            ExportedKind::Macro => "macro",
+            ExportedKind::Mod => "mod",
+            ExportedKind::Static => "static",
+            ExportedKind::Struct => "struct",
+            ExportedKind::Trait => "trait",
+            ExportedKind::TraitAlias => "trait alias",
+            ExportedKind::Type => "type",
+            ExportedKind::Union => "union",
+            ExportedKind::Use => "use",
+            ExportedKind::Verbatim => "verbatim",
+        }
+    }
+}
+
 /// Contains re-exports required at compile-time by the macro_magic macros and support
 /// functions.
 #[doc(hidden)]
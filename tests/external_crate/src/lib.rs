@@ -7,6 +7,7 @@ mod some_submodule {
 
     struct FooBarStruct {}
 
+    #[allow(dead_code)]
     trait FooBarTrait {
         fn foo(n: u32) -> u32;
         fn bar(n: i32) -> i32;
@@ -41,6 +42,7 @@ mod an_external_module {
     }
 }
 
+#[allow(non_local_definitions)]
 fn _some_function() {
     #[export_tokens]
     fn some_sub_function() -> u32 {
@@ -20,3 +20,22 @@ fn test_distant_re_export_proc() {
     let tokens_str = distant_re_export_proc!(middle_crate::ForeignItem);
     assert_eq!(tokens_str, "struct ForeignItem {}");
 }
+
+mod renamed_dependency {
+    use super::*;
+
+    #[distant_re_export_attr(producer::an_external_function)]
+    struct AttachedItem {}
+
+    #[test]
+    fn test_distant_re_export_attr_renamed_dependency() {
+        assert_eq!(DISTANT_ATTR_ATTACHED_ITEM, "struct AttachedItem {}");
+        assert!(DISTANT_ATTR_IMPORTED_ITEM.starts_with("fn an_external_function"));
+    }
+
+    #[test]
+    fn test_distant_re_export_proc_renamed_dependency() {
+        let tokens_str = distant_re_export_proc!(producer::an_external_function);
+        assert!(tokens_str.starts_with("fn an_external_function"));
+    }
+}
@@ -1,7 +1,7 @@
 use derive_syn_parse::Parse;
 use macro_magic::{mm_core::ForeignPath, *};
 use proc_macro::TokenStream;
-use quote::{quote, ToTokens};
+use quote::{format_ident, quote, ToTokens};
 use syn::{parse_macro_input, spanned::Spanned, Error, Fields, Item, ItemMod, ItemStruct, Path};
 
 /// An example proc macro built on top of `import_tokens_internal`.
@@ -91,7 +91,7 @@ pub fn test_tokens_attr1(attr: TokenStream, tokens: TokenStream) -> TokenStream
     assert_eq!(imported_item_str, "struct AnotherStruct { field1 : u32, }");
     assert_eq!(
         attached_item_str,
-        "pub mod hunter { pub fn stuff() { println! (\"things\") ; } }"
+        "pub mod hunter { pub fn stuff() { println! (\"things\"); } }"
     );
     quote! {
         #attached_item
@@ -118,6 +118,78 @@ pub fn test_tokens_attr2(attr: TokenStream, tokens: TokenStream) -> TokenStream
     .into()
 }
 
+#[import_tokens_attr(dry_run)]
+#[proc_macro_attribute]
+pub fn dry_run_attr(attr: TokenStream, tokens: TokenStream) -> TokenStream {
+    let _ = (attr, tokens);
+    quote!(compile_error!("the body of a dry run attribute should never run");).into()
+}
+
+/// Appends an `impl` block defining a `MARKER` const to the attached struct.
+#[proc_macro_attribute]
+pub fn append_marker(attr: TokenStream, tokens: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        return Error::new(
+            proc_macro2::TokenStream::from(attr).span(),
+            "expected no args",
+        )
+        .to_compile_error()
+        .into();
+    }
+    let item = parse_macro_input!(tokens as ItemStruct);
+    let ident = &item.ident;
+    quote! {
+        #item
+        impl #ident {
+            pub const MARKER: &'static str = "append_marker";
+        }
+    }
+    .into()
+}
+
+/// Derives an `impl` block defining a `DOCS` const listing the doc strings of the struct.
+///
+/// The values of the doc attributes are copied as expressions, since macro calls within them
+/// (e.g. as generated by `import_tokens_doc!`) are only expanded after derives run.
+#[proc_macro_derive(DocConst)]
+pub fn doc_const(tokens: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(tokens as ItemStruct);
+    let mut docs = Vec::new();
+    for attr in item.attrs.iter().filter(|attr| attr.path().is_ident("doc")) {
+        match &attr.meta {
+            syn::Meta::NameValue(name_value) => docs.push(&name_value.value),
+            meta => {
+                return Error::new(meta.span(), "expected a name-value doc attribute")
+                    .to_compile_error()
+                    .into()
+            }
+        }
+    }
+    let ident = &item.ident;
+    quote! {
+        impl #ident {
+            pub const DOCS: &'static [&'static str] = &[#(#docs),*];
+        }
+    }
+    .into()
+}
+
+#[import_tokens_attr(then = test_macros::append_marker)]
+#[proc_macro_attribute]
+pub fn foreign_name_then_marker(attr: TokenStream, tokens: TokenStream) -> TokenStream {
+    let local_item = parse_macro_input!(tokens as ItemStruct);
+    let foreign_item = parse_macro_input!(attr as ItemStruct);
+    let ident = &local_item.ident;
+    let foreign_name = foreign_item.ident.to_string();
+    quote! {
+        #local_item
+        impl #ident {
+            pub const FOREIGN_NAME: &'static str = #foreign_name;
+        }
+    }
+    .into()
+}
+
 #[derive(Parse)]
 struct CustomParsingA {
     foreign_path: syn::Path,
@@ -200,6 +272,106 @@ pub fn import_tokens_attr_with_custom_parsing_b(
     .into()
 }
 
+#[derive(Parse)]
+struct CustomParsingLossy {
+    foreign_path: syn::Path,
+    _comma: syn::token::Comma,
+    _note: syn::LitStr,
+}
+
+/// deliberately lossy: the note is dropped
+impl ToTokens for CustomParsingLossy {
+    fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
+        tokens.extend(self.foreign_path.to_token_stream());
+    }
+}
+
+impl ForeignPath for CustomParsingLossy {
+    fn foreign_path(&self) -> &syn::Path {
+        &self.foreign_path
+    }
+}
+
+#[derive(Parse, ForeignPath)]
+struct CustomParsingDerived {
+    _note: syn::LitStr,
+    _comma: syn::token::Comma,
+    #[foreign_path]
+    source: syn::Path,
+}
+
+impl ToTokens for CustomParsingDerived {
+    fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
+        tokens.extend(self.source.to_token_stream());
+    }
+}
+
+#[import_tokens_attr]
+#[with_custom_parsing(CustomParsingDerived)]
+#[proc_macro_attribute]
+pub fn import_tokens_attr_with_derived_foreign_path(
+    attr: TokenStream,
+    tokens: TokenStream,
+) -> TokenStream {
+    let imported_item = parse_macro_input!(attr as Item);
+    let attached_item = parse_macro_input!(tokens as Item);
+    let imported_item_str = imported_item.to_token_stream().to_string();
+    quote! {
+        const derived_foreign_path_imported: &'static str = #imported_item_str;
+        #attached_item
+    }
+    .into()
+}
+
+/// picks a different parser depending on what the attribute is attached to
+#[import_tokens_attr]
+#[with_custom_parsing(struct => CustomParsingA, fn => CustomParsingLossy)]
+#[proc_macro_attribute]
+pub fn import_tokens_attr_with_parsing_by_kind(
+    attr: TokenStream,
+    tokens: TokenStream,
+) -> TokenStream {
+    let imported_item = parse_macro_input!(attr as Item);
+    let attached_item = parse_macro_input!(tokens as Item);
+    let imported_item_str = imported_item.to_token_stream().to_string();
+    let custom_tokens_str = __custom_tokens.to_string();
+    let (imported_ident, custom_ident) = match &attached_item {
+        Item::Struct(item) => (
+            format_ident!("{}_IMPORTED", item.ident.to_string().to_uppercase()),
+            format_ident!("{}_CUSTOM", item.ident.to_string().to_uppercase()),
+        ),
+        Item::Fn(item) => (
+            format_ident!("{}_IMPORTED", item.sig.ident.to_string().to_uppercase()),
+            format_ident!("{}_CUSTOM", item.sig.ident.to_string().to_uppercase()),
+        ),
+        _ => unreachable!(),
+    };
+    quote! {
+        const #imported_ident: &'static str = #imported_item_str;
+        const #custom_ident: &'static str = #custom_tokens_str;
+        #attached_item
+    }
+    .into()
+}
+
+#[import_tokens_attr]
+#[with_custom_parsing(CustomParsingLossy)]
+#[proc_macro_attribute]
+pub fn import_tokens_attr_with_lossy_parsing(
+    _attr: TokenStream,
+    tokens: TokenStream,
+) -> TokenStream {
+    let attached_item = parse_macro_input!(tokens as Item);
+    let custom_tokens_str = __custom_tokens.to_string();
+    let raw_attr_str = __raw_attr.to_string();
+    quote! {
+        const lossy_custom_tokens_str: &'static str = #custom_tokens_str;
+        const lossy_raw_attr_str: &'static str = #raw_attr_str;
+        #attached_item
+    }
+    .into()
+}
+
 #[proc_macro_attribute]
 #[import_tokens_attr]
 pub fn test_tokens_attr_direct_import(attr: TokenStream, tokens: TokenStream) -> TokenStream {
@@ -244,6 +416,24 @@ pub fn emit_foreign_path(attr: TokenStream, tokens: TokenStream) -> TokenStream
     .into()
 }
 
+#[import_tokens_attr]
+#[proc_macro_attribute]
+pub fn emit_foreign_items(attr: TokenStream, tokens: TokenStream) -> TokenStream {
+    let first_item_str = attr.to_string();
+    let foreign_item_strs = __foreign_items
+        .iter()
+        .map(|foreign_item| foreign_item.to_token_stream().to_string());
+    let paths = __source_path.to_string();
+    let item = parse_macro_input!(tokens as Item);
+    quote! {
+        const first_foreign_item_str: &'static str = #first_item_str;
+        const foreign_item_strs: &'static [&'static str] = &[#(#foreign_item_strs),*];
+        const emitted_paths: &'static str = #paths;
+        #item
+    }
+    .into()
+}
+
 #[import_tokens_attr]
 #[proc_macro_attribute]
 pub fn combine_structs(attr: TokenStream, tokens: TokenStream) -> TokenStream {
@@ -252,14 +442,18 @@ pub fn combine_structs(attr: TokenStream, tokens: TokenStream) -> TokenStream {
     let Fields::Named(local_fields) = local_struct.fields else {
         return Error::new(
             local_struct.fields.span(),
-            "unnamed fields are not supported"
-        ).to_compile_error().into()
+            "unnamed fields are not supported",
+        )
+        .to_compile_error()
+        .into();
     };
     let Fields::Named(foreign_fields) = foreign_struct.fields else {
         return Error::new(
             foreign_struct.fields.span(),
-            "unnamed fields are not supported"
-        ).to_compile_error().into()
+            "unnamed fields are not supported",
+        )
+        .to_compile_error()
+        .into();
     };
     let local_fields = local_fields.named.iter();
     let foreign_fields = foreign_fields.named.iter();
@@ -289,8 +483,10 @@ pub fn require(tokens: TokenStream) -> TokenStream {
         return Error::new(
             external_mod.span(),
             "cannot import tokens from a file-based module since custom file-level \
-            attributes are not yet supported by Rust"
-        ).to_compile_error().into()
+            attributes are not yet supported by Rust",
+        )
+        .to_compile_error()
+        .into();
     };
     quote! {
         #(#stmts)
@@ -299,4 +495,40 @@ pub fn require(tokens: TokenStream) -> TokenStream {
     .into()
 }
 
+/// Receives a struct via `forward_tokens_spanned!` and emits the line of its first field
+#[proc_macro]
+pub fn first_field_line(tokens: TokenStream) -> TokenStream {
+    let item_struct = parse_macro_input!(tokens as ItemStruct);
+    let Some(field) = item_struct.fields.iter().next() else {
+        return Error::new(item_struct.span(), "expected at least one field")
+            .to_compile_error()
+            .into();
+    };
+    let line = field.span().unwrap().line() as u32;
+    quote!(const FORWARDED_FIELD_LINE: u32 = #line;).into()
+}
+
+/// Exports the item passed to it without emitting it, like `#[export_tokens_no_emit]`.
+///
+/// Unlike the input of an attribute macro, the input of a function-like macro isn't parsed
+/// by the compiler, so this also works for items whose syntax is still unstable, such as
+/// trait aliases.
+#[proc_macro]
+pub fn export_unstable_syntax(tokens: TokenStream) -> TokenStream {
+    let options = mm_core::ExportTokensOptions::new().emit(false);
+    match mm_core::export_tokens_with_options(tokens, options) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
 export_tokens_alias!(custom_export_tokens);
+export_tokens_alias!(prefixed_export_tokens, prefix = "__prefixed_tt_");
+
+/// A transform for `forward_tokens!` that appends `Renamed` to the name of a struct
+#[proc_macro_attribute]
+pub fn rename_struct(_attr: TokenStream, tokens: TokenStream) -> TokenStream {
+    let mut item_struct = parse_macro_input!(tokens as ItemStruct);
+    item_struct.ident = format_ident!("{}Renamed", item_struct.ident);
+    item_struct.to_token_stream().into()
+}
@@ -1,21 +1,34 @@
 use macro_magic::*;
 
 use macro_magic_macros::export_tokens_no_emit;
-use test_macros::{custom_export_tokens, include_impl, include_impl_inner};
+use test_macros::{custom_export_tokens, include_impl, include_impl_inner, prefixed_export_tokens};
 
 #[cfg(feature = "proc_support")]
 use test_macros::some_macro;
 
+// dry run attributes never forward to their inner macro, so `#[use_attr]` isn't needed
+use test_macros::dry_run_attr;
+
 #[use_attr]
 use test_macros::combine_structs;
 #[use_attr]
+use test_macros::emit_foreign_items;
+#[use_attr]
 use test_macros::emit_foreign_path;
 #[use_proc]
 use test_macros::example_tokens_proc;
 #[use_attr]
+use test_macros::foreign_name_then_marker;
+#[use_attr]
 use test_macros::import_tokens_attr_with_custom_parsing_a;
 #[use_attr]
 use test_macros::import_tokens_attr_with_custom_parsing_b;
+#[use_attr]
+use test_macros::import_tokens_attr_with_derived_foreign_path;
+#[use_attr]
+use test_macros::import_tokens_attr_with_lossy_parsing;
+#[use_attr]
+use test_macros::import_tokens_attr_with_parsing_by_kind;
 #[use_proc]
 use test_macros::item_level_proc;
 #[use_proc]
@@ -44,6 +57,53 @@ struct CustomParsingStructLocal2 {
     field: u32,
 }
 
+#[import_tokens_attr_with_lossy_parsing(CustomParsingStructForeign, "keep me")]
+struct CustomParsingStructLossy {}
+
+#[test]
+fn test_import_tokens_attr_raw_attr() {
+    assert_eq!(lossy_custom_tokens_str, "CustomParsingStructForeign");
+    assert_eq!(
+        lossy_raw_attr_str,
+        "CustomParsingStructForeign, \"keep me\""
+    );
+    let _ = CustomParsingStructLossy {};
+}
+
+#[import_tokens_attr_with_derived_foreign_path("note", CustomParsingStructForeign)]
+struct CustomParsingStructDerived {}
+
+#[test]
+fn test_derive_foreign_path() {
+    assert_eq!(
+        derived_foreign_path_imported,
+        "struct CustomParsingStructForeign { field : bool, }"
+    );
+    let _ = CustomParsingStructDerived {};
+}
+
+#[import_tokens_attr_with_parsing_by_kind(CustomParsingStructForeign, some::cool::path)]
+struct ParsedByKind {}
+
+#[import_tokens_attr_with_parsing_by_kind(CustomParsingStructForeign, "dropped")]
+fn parsed_by_kind() {}
+
+#[test]
+fn test_with_custom_parsing_by_kind() {
+    assert_eq!(
+        PARSEDBYKIND_IMPORTED,
+        "struct CustomParsingStructForeign { field : bool, }"
+    );
+    assert_eq!(
+        PARSEDBYKIND_CUSTOM,
+        "CustomParsingStructForeign, some :: cool :: path"
+    );
+    assert_eq!(PARSED_BY_KIND_IMPORTED, PARSEDBYKIND_IMPORTED);
+    assert_eq!(PARSED_BY_KIND_CUSTOM, "CustomParsingStructForeign");
+    let _ = ParsedByKind {};
+    parsed_by_kind();
+}
+
 pub mod example_export {
     pub mod subpath {
         pub use ::macro_magic::*;
@@ -52,18 +112,224 @@ pub mod example_export {
 
 mod external_file;
 
-#[export_tokens]
+#[export_tokens(export_const)]
 struct SomeStruct {
     field1: u32,
     field2: bool,
 }
 
-#[export_tokens(charlie)]
+#[export_tokens(charlie, export_const)]
 struct Struct2 {
     field1: i64,
     field2: usize,
 }
 
+#[test]
+fn test_export_tokens_const() {
+    assert_eq!(
+        __EXPORT_TOKENS__SOME_STRUCT,
+        "struct SomeStruct { field1 : u32, field2 : bool, }"
+    );
+    assert_eq!(
+        __EXPORT_TOKENS__CHARLIE,
+        "struct Struct2 { field1 : i64, field2 : usize, }"
+    );
+}
+
+#[export_tokens]
+fn add_stuff(a: u32, b: u32) -> u32 {
+    a + b
+}
+
+const ADD_STUFF: &str = import_tokens_str!(add_stuff);
+
+#[export_tokens(const_fn)]
+fn sub_stuff(a: u32, b: u32) -> u32 {
+    a - b
+}
+
+const SUB_STUFF: &str = sub_stuff_tokens_str();
+
+#[test]
+fn test_export_tokens_const_fn() {
+    assert_eq!(sub_stuff(3, 2), 1);
+    assert_eq!(SUB_STUFF, "fn sub_stuff(a : u32, b : u32) -> u32 { a - b }");
+    assert_eq!(sub_stuff_tokens_str(), import_tokens_str!(sub_stuff));
+}
+
+/// Multiplies two numbers.
+///
+/// Overflows are not checked.
+#[export_tokens]
+fn mul_stuff(a: u32, b: u32) -> u32 {
+    a * b
+}
+
+const MUL_STUFF_DOCS: &str = import_tokens_docs!(mul_stuff);
+
+#[doc = import_tokens_docs!(mul_stuff)]
+fn mul_stuff_wrapper(a: u32, b: u32) -> u32 {
+    mul_stuff(a, b)
+}
+
+#[test]
+fn test_import_tokens_docs() {
+    assert_eq!(mul_stuff_wrapper(2, 3), 6);
+    assert_eq!(
+        MUL_STUFF_DOCS,
+        " Multiplies two numbers.\n\n Overflows are not checked."
+    );
+    assert_eq!(import_tokens_docs!(add_stuff), "");
+}
+
+#[export_tokens(value)]
+const EXPORTED_FIVE: u32 = 5;
+
+#[test]
+fn test_import_tokens_value() {
+    let n: u32 = import_tokens_value!(EXPORTED_FIVE);
+    assert_eq!(n, 5);
+    assert_eq!(import_tokens_value!(EXPORTED_FIVE) * 2, 10);
+    assert_eq!(EXPORTED_FIVE, 5);
+}
+
+#[test]
+fn test_import_tokens_kind() {
+    const ADD_STUFF_KIND: ExportedKind = import_tokens_kind!(add_stuff);
+    assert_eq!(ADD_STUFF_KIND, ExportedKind::Fn);
+    assert_eq!(ADD_STUFF_KIND.as_str(), "fn");
+    assert_eq!(import_tokens_kind!(SomeStruct), ExportedKind::Struct);
+    assert_eq!(import_tokens_kind!(c_bindings), ExportedKind::ForeignMod);
+    assert_eq!(import_tokens_kind!(c_bindings).as_str(), "foreign mod");
+    assert_eq!(import_tokens_kind!(EXPORTED_FIVE), ExportedKind::Const);
+    assert_eq!(import_tokens_kind!(ThreeVariants), ExportedKind::Enum);
+    assert_eq!(import_tokens_kind!(GenericConvert), ExportedKind::Trait);
+    assert_eq!(import_tokens_kind!(merged_point_impl), ExportedKind::Impl);
+    assert_eq!(import_tokens_kind!(RawValue), ExportedKind::Union);
+    // the expansion is a path, so it can also be used as a pattern
+    assert!(matches!(
+        ExportedKind::Struct,
+        import_tokens_kind!(SomeStruct)
+    ));
+}
+
+// the `impl` block has no inherent name, so it is skipped with a deprecation warning
+#[allow(deprecated)]
+mod exported_file {
+    use macro_magic::export_tokens_file;
+
+    export_tokens_file! {
+        pub struct FileConfig {
+            pub name: &'static str,
+        }
+
+        impl FileConfig {
+            pub fn new() -> Self {
+                FileConfig { name: FILE_DEFAULT_NAME }
+            }
+        }
+
+        pub const FILE_DEFAULT_NAME: &str = "config";
+
+        pub fn file_config_name() -> &'static str {
+            FileConfig::new().name
+        }
+    }
+}
+
+#[test]
+fn test_export_tokens_file() {
+    assert_eq!(exported_file::file_config_name(), "config");
+    assert_eq!(
+        import_tokens_str!(FileConfig),
+        "pub struct FileConfig { pub name : & 'static str, }"
+    );
+    assert_eq!(
+        import_tokens_str!(FILE_DEFAULT_NAME),
+        "pub const FILE_DEFAULT_NAME : & str = \"config\";"
+    );
+    assert_eq!(import_tokens_kind!(file_config_name), ExportedKind::Fn);
+}
+
+import_tokens_doc!(add_stuff =>
+    /// Generated from:
+    #[derive(test_macros::DocConst)]
+    struct AddStuffDocs;
+);
+
+#[test]
+fn test_import_tokens_doc() {
+    assert_eq!(
+        AddStuffDocs::DOCS,
+        [
+            " Generated from:",
+            "```text\nfn add_stuff(a : u32, b : u32) -> u32 { a + b }\n```"
+        ]
+    );
+}
+
+// a second `add_stuff` and `LionStruct`, which would clash without the module
+#[allow(dead_code)]
+mod hand_written {
+    use macro_magic::*;
+
+    #[export_tokens(HandWrittenExternalFunction)]
+    fn an_external_function(my_num: u32) -> u32 {
+        my_num + 33
+    }
+}
+
+// expands to nothing, as the tokens are equal (a difference would be a compile error)
+assert_tokens_eq!(
+    external_crate::an_external_function,
+    HandWrittenExternalFunction
+);
+assert_tokens_eq!(AnotherStruct, AnotherStruct);
+
+import_tokens_mod!(wrapped_add_stuff, add_stuff);
+import_tokens_mod!(pub(crate) wrapped_lion, LionStruct);
+
+#[test]
+fn test_import_tokens_mod() {
+    assert_eq!(wrapped_add_stuff::add_stuff(2, 3), 5);
+    assert!(
+        core::any::type_name::<wrapped_lion::LionStruct>().ends_with("wrapped_lion::LionStruct")
+    );
+    assert_ne!(
+        core::any::TypeId::of::<wrapped_lion::LionStruct>(),
+        core::any::TypeId::of::<LionStruct>()
+    );
+}
+
+#[test]
+fn test_import_tokens_str() {
+    assert_eq!(add_stuff(1, 2), 3);
+    assert_eq!(ADD_STUFF, "fn add_stuff(a : u32, b : u32) -> u32 { a + b }");
+    assert_eq!(ADD_STUFF, import_tokens_str!(add_stuff));
+}
+
+#[export_tokens(verbatim_source)]
+/// Doubles the input
+fn double_it(n: u32) -> u32 {
+    // comments are part of the source text
+    n * 2 // but not of the tokens
+}
+
+const DOUBLE_IT_SOURCE: &str = import_tokens_str!(double_it, verbatim_source);
+
+#[test]
+fn test_export_tokens_verbatim_source() {
+    assert_eq!(double_it(2), 4);
+    assert_eq!(
+        DOUBLE_IT_SOURCE,
+        "/// Doubles the input\nfn double_it(n: u32) -> u32 {\n    \
+        // comments are part of the source text\n    n * 2 // but not of the tokens\n}"
+    );
+    assert!(include_str!("tests.rs").contains(DOUBLE_IT_SOURCE));
+    // the regular tokens are unaffected
+    assert!(!import_tokens_str!(double_it).contains("//"));
+}
+
 mod some_module {
     use macro_magic::*;
 
@@ -96,6 +362,27 @@ pub mod hunter {
 #[test_tokens_attr2(external_crate::AnExternalTraitImpl)]
 struct LocalItemStruct {}
 
+// the foreign path is never imported in dry run mode, so it doesn't need to exist
+#[dry_run_attr(does_not::Exist)]
+#[derive(Debug, PartialEq)]
+struct DryRunStruct {
+    field: u8,
+}
+
+#[test]
+fn test_import_tokens_attr_dry_run() {
+    assert_eq!(DryRunStruct { field: 3 }.field, 3);
+}
+
+#[foreign_name_then_marker(LionStruct)]
+struct ChainedStruct;
+
+#[test]
+fn test_import_tokens_attr_then() {
+    assert_eq!(ChainedStruct::FOREIGN_NAME, "LionStruct");
+    assert_eq!(ChainedStruct::MARKER, "append_marker");
+}
+
 #[test_tokens_attr_direct_import(external_crate::an_external_function)]
 fn cute_little_fn() {
     println!("hey!");
@@ -143,6 +430,7 @@ fn attr_direct_import() {
 }
 
 #[test]
+#[allow(non_local_definitions)]
 fn test_forward_tokens() {
     #[macro_export]
     macro_rules! receiver {
@@ -155,6 +443,162 @@ fn test_forward_tokens() {
     assert_eq!(result, "struct LionStruct {}");
 }
 
+macro_rules! extras_receiver {
+    ($tokens:item, $($extra:literal),*) => {
+        const FORWARDED_EXTRAS: &[&str] = &[$(stringify!($extra)),*];
+    };
+}
+
+forward_tokens!(
+    LionStruct,
+    extras_receiver,
+    macro_magic,
+    "a",
+    "b~~c",
+    42,
+    true
+);
+
+#[test]
+fn test_forward_tokens_multiple_extras() {
+    assert_eq!(FORWARDED_EXTRAS, ["\"a\"", "\"b~~c\"", "42", "true"]);
+}
+
+#[macro_export]
+macro_rules! dollar_crate_receiver {
+    ($($tokens:tt)*) => {
+        stringify!($($tokens)*)
+    };
+}
+
+macro_rules! forward_to_dollar_crate_receiver {
+    () => {
+        __export_tokens_tt_lion_struct! { ::a::b, $crate::dollar_crate_receiver }
+    };
+}
+
+#[test]
+fn test_callback_path_shapes() {
+    // single ident, multi-segment and leading `::` callbacks are all invoked as given
+    let forwarded = __export_tokens_tt_lion_struct! { a, stringify };
+    assert!(forwarded.starts_with("a,"));
+    assert_eq!(
+        __export_tokens_tt_lion_struct! { a, core::stringify },
+        forwarded
+    );
+    assert_eq!(
+        __export_tokens_tt_lion_struct! { a, ::core::stringify },
+        forwarded
+    );
+    // the leading `::` of the tokens var is passed along as well
+    let forwarded = __export_tokens_tt_lion_struct! { ::a::b, ::core::stringify };
+    assert!(forwarded.starts_with(":: a :: b,"));
+    assert_eq!(forward_to_dollar_crate_receiver!(), forwarded);
+    let forwarded = __export_tokens_tt_lion_struct! { a, ::core::stringify, 1 + 1 };
+    assert!(forwarded.ends_with("1 + 1"));
+    let forwarded = __export_tokens_tt_lion_struct! { @version, ::core::stringify, a, b };
+    assert_eq!(forwarded, "0, a, b");
+}
+
+macro_rules! comma_extra_receiver {
+    ($tokens_var:ident, $($tokens_and_extra:tt)*) => {
+        const COMMA_EXTRA_TOKENS: &str = stringify!($($tokens_and_extra)*);
+    };
+}
+
+// both the forwarded item and the extra contain commas, and the extra isn't a single expression
+__export_tokens_tt_charlie! { tokens, comma_extra_receiver, ("a", 1 + 2), [b, c] }
+
+#[test]
+fn test_forward_extra_with_commas() {
+    assert!(COMMA_EXTRA_TOKENS.contains("Struct2"));
+    assert!(COMMA_EXTRA_TOKENS.ends_with(r#"("a", 1 + 2), [b, c]"#));
+}
+
+macro_rules! lion_table {
+    (name, $($tokens:tt)*) => {
+        lion_name_receiver! { $($tokens)* }
+    };
+    (tokens, $($tokens:tt)*) => {
+        lion_tokens_receiver! { $($tokens)* }
+    };
+}
+
+macro_rules! lion_name_receiver {
+    ($vis:vis struct $name:ident $($rest:tt)*) => {
+        const DYNAMIC_LION_NAME: &str = stringify!($name);
+    };
+}
+
+macro_rules! lion_tokens_receiver {
+    ($tokens:item) => {
+        const DYNAMIC_LION_TOKENS: &str = stringify!($tokens);
+    };
+}
+
+forward_tokens_dynamic!(LionStruct, lion_table, name);
+forward_tokens_dynamic!(LionStruct, lion_table, tokens);
+
+#[test]
+fn test_forward_tokens_dynamic() {
+    assert_eq!(DYNAMIC_LION_NAME, "LionStruct");
+    assert_eq!(DYNAMIC_LION_TOKENS, "struct LionStruct {}");
+}
+
+macro_rules! renamed_receiver {
+    ($tokens:item) => {
+        const RENAMED_ITEM: &str = stringify!($tokens);
+    };
+}
+
+forward_tokens!(LionStruct => test_macros::rename_struct => renamed_receiver);
+
+#[test]
+fn test_forward_tokens_transform() {
+    assert_eq!(RENAMED_ITEM, "struct LionStructRenamed {}");
+}
+
+#[export_tokens]
+struct SpannedStruct {
+    _spanned_field: u32,
+}
+
+#[cfg(not(feature = "compress"))] // compressed tokens are re-lexed and lose their spans
+const SPANNED_FIELD_LINE: u32 = line!() - 4;
+
+#[cfg(not(feature = "compress"))]
+forward_tokens_spanned!(SpannedStruct, test_macros::first_field_line);
+
+#[cfg(not(feature = "compress"))]
+#[test]
+fn test_forward_tokens_spanned() {
+    assert_eq!(FORWARDED_FIELD_LINE, SPANNED_FIELD_LINE);
+}
+
+// trait aliases are unstable, so the alias can't be emitted (or parsed as an item) here
+test_macros::export_unstable_syntax! {
+    pub trait ShareAlias<T> = Send + Sync + AsRef<T>;
+}
+
+#[test]
+fn test_export_trait_alias() {
+    assert_eq!(import_tokens_kind!(ShareAlias), ExportedKind::TraitAlias);
+    assert_eq!(
+        import_tokens_str!(ShareAlias),
+        "pub trait ShareAlias < T > = Send + Sync + AsRef < T > ;"
+    );
+}
+
+#[cfg(feature = "proc_support")]
+#[test]
+fn import_tokens_trait_alias() {
+    import_tokens!(let tokens = ShareAlias);
+    let alias = syn::parse2::<syn::ItemTraitAlias>(tokens).unwrap();
+    assert_eq!(alias.ident, "ShareAlias");
+    assert_eq!(alias.generics.params.len(), 1);
+    assert_eq!(alias.bounds.len(), 3);
+}
+
 #[cfg(feature = "proc_support")]
 #[test]
 fn import_tokens_same_mod_no_ident() {
@@ -163,6 +607,51 @@ fn import_tokens_same_mod_no_ident() {
     assert!(tokens.to_string().contains("field1"));
 }
 
+#[cfg(feature = "proc_support")]
+#[test]
+fn import_tokens_extend() {
+    let mut tokens = __private::TokenStream2::new();
+    import_tokens!(extend tokens = LionStruct);
+    import_tokens!(extend tokens = TigerStruct);
+    import_tokens!(let lion = LionStruct);
+    import_tokens!(let tiger = TigerStruct);
+    assert_eq!(tokens.to_string(), format!("{} {}", lion, tiger));
+}
+
+#[cfg(feature = "proc_support")]
+#[test]
+fn import_tokens_rename() {
+    import_tokens!(let tokens = LionStruct as Bar);
+    let expected: __private::TokenStream2 = "struct Bar {}".parse().unwrap();
+    assert_eq!(tokens.to_string(), expected.to_string());
+    // renaming composes with the other options
+    import_tokens!(let tokens = StrippedOnImport as Stripped, strip = [doc, allow, derive]);
+    let expected: __private::TokenStream2 = "struct Stripped { field: u8, }".parse().unwrap();
+    assert_eq!(tokens.to_string(), expected.to_string());
+}
+
+/// Docs that are stripped on import
+#[export_tokens]
+#[derive(Clone)]
+#[allow(dead_code)]
+struct StrippedOnImport {
+    #[allow(dead_code)]
+    /// A field
+    field: u8,
+}
+
+#[cfg(feature = "proc_support")]
+#[test]
+fn import_tokens_strip() {
+    import_tokens!(let tokens = StrippedOnImport, strip = [doc, allow]);
+    assert_eq!(
+        tokens.to_string(),
+        "# [derive (Clone)] struct StrippedOnImport { field : u8 , }"
+    );
+    import_tokens!(let tokens = StrippedOnImport);
+    assert!(tokens.to_string().contains("A field"));
+}
+
 #[cfg(feature = "proc_support")]
 #[test]
 fn import_tokens_same_mod_ident() {
@@ -170,6 +659,67 @@ fn import_tokens_same_mod_ident() {
     assert!(tokens.to_string().contains("field2 : usize"));
 }
 
+#[cfg(feature = "proc_support")]
+#[test]
+fn import_tokens_fresh_expression() {
+    let tokens = import_tokens_fresh!(charlie);
+    assert!(tokens.to_string().contains("field2 : usize"));
+    assert_eq!(
+        import_tokens_fresh!(PlusPlus).to_string(),
+        "fn plus_plus < T : Into < i64 > > (n : T) -> i64 { n . into () + 1 }"
+    );
+    let __imported_tokens = 7;
+    assert!(import_tokens_fresh!(SomeStruct)
+        .to_string()
+        .contains("field1"));
+    assert_eq!(__imported_tokens, 7);
+}
+
+#[cfg(feature = "proc_support")]
+#[test]
+fn import_tokens_field_extraction() {
+    let field = import_tokens_field!(charlie::field2);
+    assert_eq!(field.to_string(), "field2 : usize");
+    let field = syn::parse::Parser::parse2(syn::Field::parse_named, field).unwrap();
+    assert_eq!(field.ident.unwrap(), "field2");
+    assert_eq!(
+        import_tokens_field!(charlie::field1).to_string(),
+        "field1 : i64"
+    );
+}
+
+#[cfg(feature = "proc_support")]
+import_tokens_fn!(fn charlie_tokens = charlie);
+
+#[cfg(feature = "proc_support")]
+mod tokens_fns {
+    use super::*;
+
+    import_tokens_fn!(pub fn plus_plus_tokens = PlusPlus);
+}
+
+#[cfg(feature = "proc_support")]
+#[test]
+fn import_tokens_fn_generated() {
+    assert!(charlie_tokens().to_string().contains("field2 : usize"));
+    assert_eq!(charlie_tokens().to_string(), charlie_tokens().to_string());
+    assert_eq!(
+        tokens_fns::plus_plus_tokens().to_string(),
+        import_tokens_fresh!(PlusPlus).to_string()
+    );
+}
+
+#[cfg(feature = "proc_support")]
+#[test]
+fn import_tokens_raw_macro_ident() {
+    let tokens = import_tokens_raw!(__export_tokens_tt_charlie);
+    assert!(tokens.to_string().contains("field2 : usize"));
+    assert_eq!(
+        import_tokens_raw!(__export_tokens_tt_plus_plus).to_string(),
+        import_tokens_fresh!(PlusPlus).to_string()
+    );
+}
+
 #[cfg(feature = "proc_support")]
 #[test]
 fn import_tokens_different_mod_no_ident() {
@@ -190,6 +740,360 @@ fn import_tokens_different_mod_ident() {
     );
 }
 
+#[cfg(feature = "proc_support")]
+#[test]
+fn import_tokens_full_module_path() {
+    // every segment between the crate and the item is ignored, so the full path of an item
+    // (even one in a private module) resolves to the same callback at the crate root
+    import_tokens!(let full = external_crate::some_submodule::AnExternalTraitImpl);
+    import_tokens!(let short = external_crate::AnExternalTraitImpl);
+    assert_eq!(full.to_string(), short.to_string());
+    assert_eq!(
+        import_tokens_kind!(external_crate::some_submodule::AnExternalTraitImpl),
+        ExportedKind::Impl
+    );
+}
+
+#[cfg(feature = "proc_support")]
+#[test]
+fn import_tokens_env_selected_item() {
+    // set via `[env]` in `.cargo/config.toml`
+    import_tokens_env!(let tokens = MACRO_MAGIC_TEST_IMPORT_PATH);
+    assert!(tokens.to_string().contains("an_external_function"));
+    assert!(tokens.to_string().contains("my_num + 33"));
+}
+
+#[export_tokens(body, sig)]
+fn instrumented_fn(a: u32, b: u32) -> u32 {
+    a * b + 1
+}
+
+#[cfg(feature = "proc_support")]
+#[test]
+fn import_tokens_fn_body_and_sig() {
+    assert_eq!(instrumented_fn(2, 3), 7);
+    import_tokens!(let body = instrumented_fn_body);
+    let block = syn::parse2::<syn::Block>(body).unwrap();
+    assert_eq!(block.stmts.len(), 1);
+    import_tokens!(let sig = instrumented_fn_sig);
+    let sig = syn::parse2::<syn::Signature>(sig).unwrap();
+    assert_eq!(sig.ident, "instrumented_fn");
+    assert_eq!(sig.inputs.len(), 2);
+    assert_eq!(
+        import_tokens_str!(instrumented_fn_sig),
+        "fn instrumented_fn(a : u32, b : u32) -> u32"
+    );
+}
+
+#[export_tokens(version = 3)]
+struct VersionedStruct {
+    _field: u8,
+}
+
+#[cfg(feature = "proc_support")]
+#[test]
+fn import_tokens_min_version() {
+    import_tokens!(let tokens = VersionedStruct, min_version = 2);
+    assert!(tokens.to_string().contains("VersionedStruct"));
+    import_tokens!(let tokens = VersionedStruct, min_version = 3);
+    assert!(tokens.to_string().contains("_field"));
+    // unversioned exports are still importable as usual
+    import_tokens!(let tokens = SomeStruct, min_version = 0);
+    assert!(tokens.to_string().contains("SomeStruct"));
+}
+
+#[export_tokens(with_generics)]
+struct GenericWrapper<T: Clone> {
+    inner: T,
+}
+
+#[export_tokens(with_generics)]
+trait GenericConvert<T>
+where
+    T: Default,
+{
+    fn convert(&self) -> T;
+}
+
+#[export_tokens(with_generics)]
+struct Buffer<const N: usize = 8> {
+    data: [u8; N],
+}
+
+#[cfg(feature = "proc_support")]
+#[test]
+fn import_tokens_generics() {
+    let _ = GenericWrapper { inner: 1u8 }.inner;
+    let generics = syn::parse2::<syn::Generics>(import_tokens_generics!(GenericWrapper)).unwrap();
+    assert_eq!(generics.params.len(), 1);
+    assert_eq!(
+        import_tokens_generics!(GenericWrapper).to_string(),
+        "< T : Clone >"
+    );
+    let generics = syn::parse::Parser::parse2(
+        mm_core::parse_generics,
+        import_tokens_generics!(GenericConvert),
+    )
+    .unwrap();
+    assert_eq!(generics.params.len(), 1);
+    assert!(generics.where_clause.is_some());
+}
+
+#[cfg(feature = "proc_support")]
+#[test]
+fn import_tokens_generics_const() {
+    use quote::ToTokens;
+
+    let buffer: Buffer = Buffer { data: [0; 8] };
+    assert_eq!(buffer.data.len(), 8);
+    assert_eq!(
+        import_tokens_generics!(Buffer).to_string(),
+        "< const N : usize = 8 >"
+    );
+    let generics = syn::parse2::<syn::Generics>(import_tokens_generics!(Buffer)).unwrap();
+    let Some(syn::GenericParam::Const(param)) = generics.params.first() else {
+        panic!("expected a const generic param");
+    };
+    assert_eq!(param.ident, "N");
+    assert_eq!(param.ty.to_token_stream().to_string(), "usize");
+    assert_eq!(param.default.to_token_stream().to_string(), "8");
+    let (impl_generics, ty_generics, _) = generics.split_for_impl();
+    assert_eq!(
+        impl_generics.to_token_stream().to_string(),
+        "< const N : usize >"
+    );
+    assert_eq!(ty_generics.to_token_stream().to_string(), "< N >");
+}
+
+#[export_tokens]
+struct MergedPoint {
+    x: i32,
+}
+
+#[export_tokens(merged_point_impl)]
+impl MergedPoint {
+    fn x(&self) -> i32 {
+        self.x
+    }
+}
+
+#[cfg(feature = "proc_support")]
+#[test]
+fn import_tokens_merge() {
+    assert_eq!(MergedPoint { x: 3 }.x(), 3);
+    import_tokens_merge!(let tokens = [MergedPoint, merged_point_impl]);
+    let merged = tokens.to_string();
+    let struct_pos = merged.find("struct MergedPoint").unwrap();
+    let impl_pos = merged.find("impl MergedPoint").unwrap();
+    assert!(struct_pos < impl_pos);
+    let file = syn::parse2::<syn::File>(tokens).unwrap();
+    assert_eq!(file.items.len(), 2);
+}
+
+#[export_tokens(cfg(all()))]
+struct CfgEnabled {}
+
+#[export_tokens(cfg(any()))]
+struct CfgDisabled {}
+
+// this would collide with the callback macro of `CfgDisabled` if it had been generated
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __export_tokens_tt_cfg_disabled {
+    (@ident) => {
+        "not exported"
+    };
+}
+
+#[test]
+fn test_export_tokens_cfg() {
+    // the item itself is emitted either way
+    let _item = CfgDisabled {};
+    let _item: import_tokens_ident!(CfgEnabled) = CfgEnabled {};
+    assert_eq!(import_tokens_ident!(CfgDisabled), "not exported");
+}
+
+mod local_export {
+    use super::*;
+
+    #[export_tokens(local)]
+    struct LocalOnly {
+        _field: u8,
+    }
+
+    #[cfg(feature = "proc_support")]
+    #[test]
+    fn import_tokens_local() {
+        import_tokens!(let tokens = LocalOnly, local);
+        assert!(tokens.to_string().contains("struct LocalOnly"));
+    }
+}
+
+#[export_tokens]
+macro_rules! sum_all {
+    ($($x:expr),*) => {
+        0 $(+ $x)*
+    };
+}
+
+#[cfg(feature = "proc_support")]
+#[test]
+fn import_tokens_macro_rules() {
+    assert_eq!(sum_all!(1, 2, 3), 6);
+    import_tokens!(let tokens = sum_all);
+    let item = syn::parse2::<syn::ItemMacro>(tokens).unwrap();
+    assert_eq!(item.ident.unwrap(), "sum_all");
+    assert!(item.mac.path.is_ident("macro_rules"));
+    assert!(item.mac.tokens.to_string().contains("$ (+ $ x) *"));
+}
+
+#[export_tokens(c_bindings)]
+extern "C" {
+    fn abs(input: i32) -> i32;
+}
+
+#[cfg(feature = "proc_support")]
+#[test]
+fn import_tokens_foreign_mod() {
+    assert_eq!(unsafe { abs(-3) }, 3);
+    import_tokens!(let tokens = c_bindings);
+    let item = syn::parse2::<syn::Item>(tokens).unwrap();
+    let syn::Item::ForeignMod(foreign_mod) = item else {
+        panic!("expected a foreign mod");
+    };
+    assert_eq!(foreign_mod.abi.name.unwrap().value(), "C");
+    assert_eq!(foreign_mod.items.len(), 1);
+}
+
+#[export_tokens(items)]
+trait Greeter {
+    type Output;
+    fn greet(&self, name: &str) -> Self::Output;
+}
+
+#[cfg(feature = "proc_support")]
+#[test]
+fn import_tokens_trait_items() {
+    import_tokens!(let tokens = Greeter_greet);
+    let method = syn::parse2::<syn::TraitItemFn>(tokens).unwrap();
+    assert_eq!(method.sig.ident, "greet");
+    assert_eq!(method.sig.inputs.len(), 2);
+    assert!(method.default.is_none());
+    import_tokens!(let tokens = greeter_output);
+    let assoc_type = syn::parse2::<syn::TraitItemType>(tokens).unwrap();
+    assert_eq!(assoc_type.ident, "Output");
+}
+
+#[export_tokens]
+#[cfg_attr(all(), derive(Clone))]
+struct CfgAttrStruct {
+    #[cfg_attr(feature = "pretty_print", allow(dead_code))]
+    field: u8,
+}
+
+#[export_tokens(CfgAttrApplied, cfg_attr = apply)]
+struct CfgAttrAppliedStruct {
+    #[cfg_attr(feature = "pretty_print", allow(dead_code))]
+    field: u8,
+}
+
+#[test]
+fn test_export_tokens_cfg_attr() {
+    // the item's own `cfg_attr` was already evaluated, while the nested one is stored as-is
+    assert_eq!(
+        import_tokens_str!(CfgAttrStruct),
+        "#[derive(Clone)] struct CfgAttrStruct\n\
+        { #[cfg_attr(feature = \"pretty_print\", allow(dead_code))] field : u8, }"
+    );
+    assert_eq!(
+        import_tokens_str!(CfgAttrApplied),
+        "struct CfgAttrAppliedStruct { #[allow(dead_code)] field : u8, }"
+    );
+    assert_eq!(CfgAttrStruct { field: 1 }.clone().field, 1);
+    assert_eq!(CfgAttrAppliedStruct { field: 2 }.field, 2);
+}
+
+#[export_tokens(fields)]
+#[repr(C)]
+union RawValue {
+    as_int: u32,
+    as_float: f32,
+}
+
+#[cfg(feature = "proc_support")]
+#[test]
+fn import_tokens_union_fields() {
+    use quote::ToTokens;
+
+    assert_eq!(unsafe { RawValue { as_float: 1.0 }.as_int }, 0x3f80_0000);
+    import_tokens!(let tokens = RawValue_as_float);
+    let field = syn::parse::Parser::parse2(syn::Field::parse_named, tokens).unwrap();
+    assert_eq!(field.ident.unwrap(), "as_float");
+    assert_eq!(field.ty.to_token_stream().to_string(), "f32");
+    import_tokens!(let tokens = RawValue);
+    assert!(syn::parse2::<syn::ItemUnion>(tokens)
+        .unwrap()
+        .attrs
+        .iter()
+        .any(|attr| attr.path().is_ident("repr")));
+}
+
+#[export_tokens(variants)]
+enum ThreeVariants {
+    First,
+    Second(u32, bool),
+    Third { name: &'static str, count: usize },
+}
+
+#[cfg(feature = "proc_support")]
+#[test]
+fn import_tokens_enum_variant() {
+    import_tokens!(let tokens = ThreeVariants_Third);
+    let item_struct = syn::parse2::<syn::ItemStruct>(tokens).unwrap();
+    assert_eq!(item_struct.ident, "Third");
+    let field_names: Vec<String> = item_struct
+        .fields
+        .iter()
+        .map(|field| field.ident.as_ref().unwrap().to_string())
+        .collect();
+    assert_eq!(field_names, ["name", "count"]);
+    import_tokens!(let tokens = three_variants_second);
+    assert_eq!(
+        syn::parse2::<syn::ItemStruct>(tokens).unwrap().fields.len(),
+        2
+    );
+    let _ = (ThreeVariants::First, ThreeVariants::Second(1, true));
+}
+
+#[export_tokens]
+fn compute_area(width: u32, height: u32) -> u32 {
+    width * height
+}
+
+#[test]
+fn test_import_tokens_ident() {
+    assert_eq!(import_tokens_ident!(compute_area)(2, 3), 6);
+    // exported under an override name, but the original ident is returned
+    let item: import_tokens_ident!(charlie) = Struct2 {
+        field1: 1,
+        field2: 2,
+    };
+    assert_eq!(item.field2, 2);
+}
+
+// `syn` can't model a free function without a body, so this is an `Item::Verbatim`
+#[export_tokens_no_emit(verbatim_blob)]
+fn verbatim_fn_decl(my_num: u32) -> u32;
+
+#[cfg(feature = "proc_support")]
+#[test]
+fn import_tokens_verbatim_item() {
+    import_tokens!(let tokens = verbatim_blob);
+    let expected: __private::TokenStream2 =
+        "fn verbatim_fn_decl(my_num: u32) -> u32;".parse().unwrap();
+    assert_eq!(tokens.to_string(), expected.to_string());
+}
+
 #[export_tokens]
 fn a_random_fn() {
     println!("hey");
@@ -200,7 +1104,7 @@ fn println_inside_fn_current_file() {
     let tokens = example_tokens_proc!(a_random_fn);
     assert_eq!(
         tokens.to_string(),
-        "fn a_random_fn() { println! (\"hey\") ; }"
+        "fn a_random_fn() { println! (\"hey\"); }"
     );
 }
 
@@ -209,7 +1113,7 @@ fn println_inside_fn_external_file() {
     let tokens = example_tokens_proc!(external_fn_with_println);
     assert_eq!(
         tokens.to_string(),
-        "fn external_fn_with_println() { println! (\"testing\") ; }"
+        "fn external_fn_with_println() { println! (\"testing\"); }"
     );
 }
 
@@ -218,7 +1122,7 @@ fn macro_calls_inside_fn_external_crate() {
     let tokens = example_tokens_proc!(external_crate::external_fn_with_local_macro_calls);
     assert_eq!(
         tokens.to_string(),
-        "fn external_fn_with_local_macro_calls() -> u32 { another_macro! () ; 1337 }"
+        "fn external_fn_with_local_macro_calls() -> u32 { another_macro! (); 1337 }"
     );
 }
 
@@ -258,6 +1162,39 @@ struct Wombat {
     field2: u64,
 }
 
+#[custom_export_tokens(no_emit)]
+struct NotEmittedWombat {
+    field1: DoesNotExist,
+}
+
+#[custom_export_tokens(emit)]
+struct EmittedWombat;
+
+#[prefixed_export_tokens]
+struct Wombat2 {
+    field: bool,
+}
+
+mod unprefixed_wombat {
+    use super::*;
+
+    // exported under the default prefix, so it doesn't collide with the prefixed export
+    #[export_tokens]
+    struct Wombat2 {
+        field: u8,
+    }
+}
+
+#[cfg(feature = "proc_support")]
+#[test]
+fn test_export_tokens_alias_prefix() {
+    let _ = Wombat2 { field: true }.field;
+    import_tokens!(let prefixed = Wombat2, prefix = "__prefixed_tt_");
+    assert!(prefixed.to_string().contains("field : bool"));
+    import_tokens!(let unprefixed = Wombat2);
+    assert!(unprefixed.to_string().contains("field : u8"));
+}
+
 #[test]
 fn test_export_tokens_alias() {
     let tokens = example_tokens_proc!(Wombat);
@@ -265,6 +1202,12 @@ fn test_export_tokens_alias() {
         tokens.to_string(),
         "struct Wombat { field1 : u32, field2 : u64, }"
     );
+    let tokens = example_tokens_proc!(NotEmittedWombat);
+    assert_eq!(
+        tokens.to_string(),
+        "struct NotEmittedWombat { field1 : DoesNotExist, }"
+    );
+    let _ = EmittedWombat;
 }
 
 #[emit_foreign_path(external_crate::an_external_function)]
@@ -279,6 +1222,96 @@ fn test_foreign_path_emission() {
     );
 }
 
+#[emit_foreign_items(external_crate::an_external_function, charlie)]
+struct MultiForeignStruct {}
+
+#[test]
+fn test_import_multiple_foreign_items() {
+    assert_eq!(
+        emitted_paths,
+        "external_crate :: an_external_function, charlie"
+    );
+    assert_eq!(
+        first_foreign_item_str,
+        "fn an_external_function(my_num : u32) -> u32 { my_num + 33 }"
+    );
+    assert_eq!(
+        foreign_item_strs,
+        [
+            "fn an_external_function(my_num : u32) -> u32 { my_num + 33 }",
+            "struct Struct2 { field1 : i64, field2 : usize, }",
+        ]
+    );
+    let _ = MultiForeignStruct {};
+}
+
+#[export_tokens]
+#[derive(Debug, PartialEq)]
+struct BuilderTarget {
+    required: u32,
+    optional: Option<&'static str>,
+}
+
+generate_builder!(BuilderTarget);
+
+#[test]
+fn test_generate_builder() {
+    assert_eq!(
+        BuilderTargetBuilder::new().required(3).build(),
+        Ok(BuilderTarget {
+            required: 3,
+            optional: None
+        })
+    );
+    assert_eq!(
+        BuilderTargetBuilder::new()
+            .optional("hey")
+            .required(4)
+            .build(),
+        Ok(BuilderTarget {
+            required: 4,
+            optional: Some("hey")
+        })
+    );
+    assert_eq!(
+        BuilderTargetBuilder::default().optional("hey").build(),
+        Err("missing required field `required`")
+    );
+}
+
+// the generated builder must not rely on the prelude names shadowed here
+#[allow(dead_code)]
+mod shadowed_prelude {
+    use macro_magic::*;
+
+    struct Option;
+    struct Some;
+    struct None;
+    struct Ok;
+    struct Err;
+    struct Result;
+
+    #[export_tokens]
+    pub struct ShadowedBuilderTarget {
+        pub required: u32,
+        pub optional: ::core::option::Option<u8>,
+    }
+
+    generate_builder!(ShadowedBuilderTarget);
+
+    #[test]
+    fn test_generate_builder_shadowed_prelude() {
+        let target = ShadowedBuilderTargetBuilder::new()
+            .required(7)
+            .optional(1)
+            .build()
+            .unwrap();
+        assert_eq!(target.required, 7);
+        assert_eq!(target.optional, ::core::option::Option::Some(1));
+        assert!(ShadowedBuilderTargetBuilder::new().build().is_err());
+    }
+}
+
 #[export_tokens_no_emit]
 fn _non_compiling_fn() {
     compile_error!("this should not compile ");
@@ -300,3 +1333,57 @@ fn test_export_tokens_no_emit_exportation() {
     );
     assert_eq!(_non_compiling_fn(), 3);
 }
+
+#[allow(dead_code)]
+#[export_tokens(export_const)]
+#[derive(Clone, Debug)]
+#[allow(unused)]
+struct AttrsBothSides {
+    field: u32,
+}
+
+#[derive(Clone)]
+#[export_tokens(export_const)]
+#[derive(Debug)]
+struct DeriveAbove;
+
+#[test]
+fn test_export_tokens_sibling_attrs() {
+    // inert attributes above `#[export_tokens]` and all attributes below it are captured
+    assert_eq!(
+        __EXPORT_TOKENS__ATTRS_BOTH_SIDES
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" "),
+        "#[allow(dead_code)] #[derive(Clone, Debug)] #[allow(unused)] struct AttrsBothSides \
+        { field : u32, }"
+    );
+    // derives above `#[export_tokens]` are still applied to the emitted item, but are not
+    // visible to attribute macros and so don't appear in the exported tokens
+    let _ = DeriveAbove.clone();
+    assert_eq!(
+        __EXPORT_TOKENS__DERIVE_ABOVE,
+        "#[derive(Debug)] struct DeriveAbove;"
+    );
+}
+
+#[cfg(feature = "dump")]
+#[export_tokens(dump = "target/tokens")]
+struct DumpedStruct {
+    _name: &'static str,
+}
+
+#[cfg(feature = "dump")]
+#[test]
+fn test_export_tokens_dump() {
+    let path = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/target/tokens/dumped_struct.json"
+    );
+    let json = std::fs::read_to_string(path).unwrap();
+    assert_eq!(
+        json,
+        "{\"ident\": \"dumped_struct\", \"kind\": \"struct\", \
+        \"tokens\": \"struct DumpedStruct { _name : & 'static str, }\"}\n"
+    );
+}